@@ -0,0 +1,388 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use num_format::ToFormattedString;
+use serde::{Serialize, Deserialize};
+
+use crate::error::Result;
+use crate::evedata::{Database, ProductionRun};
+use crate::fees::FeeModel;
+use crate::industry::{self, Facility, JobActivity};
+use crate::isk::Isk;
+use crate::sde::Sde;
+use crate::settings;
+
+/// Whether an intermediate material is produced in-house or bought on the market.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildChoice
+{
+    Build,
+    Buy,
+}
+
+/// A material needed by a job. Materials that can be produced carry the job producing
+/// them, so the nodes form a tree down to materials that are always bought.
+#[derive(Serialize, Clone, Debug)]
+pub struct BuildNode
+{
+    pub name: String,
+    pub type_id: i64,
+    pub quantity: u64,
+    /// Cost of buying the quantity on the market. Zero if the item has no price.
+    pub buy_cost: Isk,
+    pub job: Option<Job>,
+}
+
+/// Sub-build that can produce the material of a node.
+#[derive(Serialize, Clone, Debug)]
+pub struct Job
+{
+    pub blueprint: String,
+    pub bp_id: i64,
+    pub activity: JobActivity,
+    pub runs: u64,
+    /// Units produced. Can exceed the needed quantity when a run yields several units.
+    pub produced: u64,
+    pub material_research: u8,
+    pub facility: Facility,
+    /// Zero if the run's facility has no solar system.
+    pub installation_cost: Isk,
+    /// Inputs as built or bought plus the installation cost. Surplus units are not
+    /// credited.
+    pub build_cost: Isk,
+    /// The cheaper option. Materials without a market price are recommended to be built.
+    pub recommended: BuildChoice,
+    /// The choice made for the run, or the recommendation if there is none.
+    pub choice: BuildChoice,
+    pub inputs: Vec<BuildNode>,
+}
+
+/// Blueprint and activity producing each type. Published blueprints are preferred over
+/// unpublished ones and manufacturing over reactions.
+pub fn producer_index(sde: &Sde) -> HashMap<i64, (i64, JobActivity)>
+{
+    let rank = |bp_id: i64, activity: JobActivity|
+        (!sde.types.get(&bp_id).is_some_and(|t| t.published),
+        activity != JobActivity::Manufacturing, bp_id);
+    let mut ret = HashMap::<i64, (i64, JobActivity)>::new();
+    for (bp_id, bp) in sde.blueprints.iter()
+    {
+        for activity in JobActivity::ALL.iter().copied()
+        {
+            let products = match bp.activities.get(activity.sde_name())
+            {
+                Some(a) => &a.products,
+                None => continue,
+            };
+            for product in products.iter()
+            {
+                let better = match ret.get(&product.type_id)
+                {
+                    Some(&(id, current)) => rank(*bp_id, activity) < rank(id, current),
+                    None => true,
+                };
+                if better
+                {
+                    ret.insert(product.type_id, (*bp_id, activity));
+                }
+            }
+        }
+    }
+    ret
+}
+
+/// Type IDs of a material and of everything that can be used to produce it.
+pub fn material_ids(db: &Database, type_id: i64) -> Vec<i64>
+{
+    let mut ret = vec![type_id];
+    let mut idx = 0;
+    while idx < ret.len()
+    {
+        let producer = db.sde_producer(ret[idx])
+            .and_then(|(bp_id, activity)| db.sde_activity(bp_id, activity.sde_name()).ok());
+        for (id, _) in producer.iter().flat_map(|a| a.materials.iter())
+        {
+            if !ret.contains(id)
+            {
+                ret.push(*id);
+            }
+        }
+        idx += 1;
+    }
+    ret
+}
+
+/// Expands `quantity` units of a material of `run` into the jobs producing it,
+/// recursively down to materials that no blueprint produces, and decides for every
+/// intermediate whether it is built or bought.
+///
+/// Manufacturing jobs are installed in the run's facility and reactions in its refinery.
+/// Without a refinery reactions only use the facility's solar system and tax, as
+/// engineering structure and rig bonuses do not apply to them.
+/// Sub-builds use the ME of the blueprint if it is known, else 0.
+pub fn expand(db: &Database, type_id: i64, quantity: u64, run: &ProductionRun, fees: &FeeModel)
+    -> Result<BuildNode>
+{
+    expand_node(db, type_id, quantity, run, fees, &mut Vec::new())
+}
+
+/// `path` holds the types being expanded above this node. A type needing itself is bought
+/// to break the cycle.
+fn expand_node(db: &Database, type_id: i64, quantity: u64, run: &ProductionRun,
+    fees: &FeeModel, path: &mut Vec<i64>) -> Result<BuildNode>
+{
+    let mut node = BuildNode::leaf(db, db.type_name(type_id)?, type_id, quantity, fees);
+    let (bp_id, activity) = match db.sde_producer(type_id)
+    {
+        Some(producer) if !path.contains(&type_id) => producer,
+        _ => return Ok(node),
+    };
+    let sde_activity = db.sde_activity(bp_id, activity.sde_name())?;
+    let per_run = sde_activity.products.iter().find(|p| p.type_id == type_id)
+        .map_or(1, |p| p.quantity.max(1));
+    let runs = quantity.div_ceil(per_run);
+    let facility = match activity
+    {
+        JobActivity::Manufacturing => run.facility,
+        JobActivity::Reaction => run.refinery.unwrap_or(Facility {
+            solar_system: run.facility.solar_system, facility_tax: run.facility.facility_tax,
+            ..Facility::default()}),
+    };
+    let blueprint = db.type_name(bp_id)?.to_string();
+    let material_research = db.get_blueprint(&blueprint).map_or(0, |bp| bp.material_research);
+
+    path.push(type_id);
+    let mut inputs = Vec::<BuildNode>::new();
+    for (id, base_quantity) in sde_activity.materials.iter()
+    {
+        let needed = industry::material_quantity(*base_quantity, runs, material_research,
+            &facility, activity);
+        inputs.push(expand_node(db, *id, needed, run, fees, path)?);
+    }
+    path.pop();
+
+    let installation_cost = match facility.solar_system
+    {
+        Some(system) =>
+        {
+            let eiv = db.sde_estimated_item_value(bp_id, activity)? * runs;
+            industry::installation_cost(eiv, db.get_cost_index(system, activity.sde_name())?,
+                &facility)
+        }
+        None => Isk::ZERO,
+    };
+    let build_cost = inputs.iter().map(|n| n.cost()).sum::<Isk>() + installation_cost;
+    let recommended = if node.buy_cost == Isk::ZERO || build_cost < node.buy_cost
+    {
+        BuildChoice::Build
+    }
+    else
+    {
+        BuildChoice::Buy
+    };
+    let choice = run.choices.get(&node.name).copied().unwrap_or(recommended);
+    node.job = Some(Job {blueprint, bp_id, activity, runs, produced: runs * per_run,
+        material_research, facility, installation_cost, build_cost, recommended, choice,
+        inputs});
+    Ok(node)
+}
+
+impl BuildNode
+{
+    /// A material that is bought.
+    pub fn leaf(db: &Database, name: &str, type_id: i64, quantity: u64, fees: &FeeModel)
+        -> BuildNode
+    {
+        let buy_cost = db.get_item(name).map_or(Isk::ZERO, |item| fees.material_price(item))
+            * quantity;
+        BuildNode {name: name.to_string(), type_id, quantity, buy_cost, job: None}
+    }
+
+    /// The job producing this material if it is built rather than bought.
+    pub fn built_job(&self) -> Option<&Job>
+    {
+        self.job.as_ref().filter(|job| job.choice == BuildChoice::Build)
+    }
+
+    pub fn cost(&self) -> Isk
+    {
+        self.built_job().map_or(self.buy_cost, |job| job.build_cost)
+    }
+
+    /// This node and all nodes below it, including the inputs of bought intermediates.
+    pub fn all_nodes(&self) -> Vec<&BuildNode>
+    {
+        let mut ret = vec![self];
+        if let Some(job) = &self.job
+        {
+            for input in job.inputs.iter()
+            {
+                ret.extend(input.all_nodes());
+            }
+        }
+        ret
+    }
+
+    /// Jobs that are built in this subtree.
+    pub fn built_jobs(&self) -> Vec<&Job>
+    {
+        let mut ret = Vec::<&Job>::new();
+        if let Some(job) = self.built_job()
+        {
+            ret.push(job);
+            for input in job.inputs.iter()
+            {
+                ret.extend(input.built_jobs());
+            }
+        }
+        ret
+    }
+
+    /// Adds the bought materials of this subtree to `list`, merging equal items.
+    pub fn add_bought_materials(&self, list: &mut Vec<(String, u64)>)
+    {
+        match self.built_job()
+        {
+            Some(job) => for input in job.inputs.iter()
+            {
+                input.add_bought_materials(list);
+            },
+            None => match list.iter_mut().find(|m| m.0 == self.name)
+            {
+                Some(m) => m.1 += self.quantity,
+                None => list.push((self.name.clone(), self.quantity)),
+            },
+        }
+    }
+}
+
+/// Draws the materials of a run as a tree. Intermediates show whether they are built or
+/// bought and both costs, marked with `!` where the choice differs from the
+/// recommendation. Inputs of bought intermediates are left out.
+pub fn format_tree(nodes: &[BuildNode]) -> String
+{
+    let mut ret = String::new();
+    for (i, node) in nodes.iter().enumerate()
+    {
+        format_node(node, "", i + 1 == nodes.len(), &mut ret);
+    }
+    ret
+}
+
+fn format_node(node: &BuildNode, prefix: &str, last: bool, out: &mut String)
+{
+    let num = |v: u64| v.to_formatted_string(settings::locale());
+    let (branch, indent) = if last { ("└─ ", "   ") } else { ("├─ ", "│  ") };
+    out.push_str(&format!("{}{}{} x {}", prefix, branch, node.name, num(node.quantity)));
+    let job = match &node.job
+    {
+        Some(job) => job,
+        None =>
+        {
+            out.push_str(&format!("  buy {}\n", node.buy_cost));
+            return;
+        }
+    };
+    out.push_str(&format!("  [{}{}] build {} / buy {}", job.choice,
+        if job.choice == job.recommended { "" } else { "!" }, job.build_cost, node.buy_cost));
+    if job.choice == BuildChoice::Buy
+    {
+        out.push('\n');
+        return;
+    }
+    out.push_str(&format!("  ({} {} run(s) of {}, {} produced)\n", num(job.runs), job.activity,
+        job.blueprint, num(job.produced)));
+    let prefix = format!("{}{}", prefix, indent);
+    for (i, input) in job.inputs.iter().enumerate()
+    {
+        format_node(input, &prefix, i + 1 == job.inputs.len(), out);
+    }
+}
+
+impl BuildChoice
+{
+    pub const ALL: [BuildChoice; 2] = [BuildChoice::Build, BuildChoice::Buy];
+}
+
+impl fmt::Display for BuildChoice
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            BuildChoice::Build => write!(f, "build"),
+            BuildChoice::Buy => write!(f, "buy"),
+        }
+    }
+}
+
+impl FromStr for BuildChoice
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<BuildChoice, String>
+    {
+        BuildChoice::ALL.iter().copied().find(|x| x.to_string() == s)
+            .ok_or_else(|| format!("unknown build choice '{}'", s))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::evedata::RunStatus;
+    use crate::fees::{BuyMode, SellMode};
+    use crate::sde::{Activity, Product, SdeBlueprint, SdeType};
+
+    use super::*;
+
+    /// Type 1 is made from type 2 by blueprint 11, and type 2 from type 1 by blueprint 12.
+    fn cyclic_database() -> Database
+    {
+        let mut sde = Sde::default();
+        for (id, name) in [(1, "Alpha"), (2, "Beta"), (11, "Alpha Blueprint"),
+            (12, "Beta Blueprint")].iter()
+        {
+            sde.types.insert(*id, SdeType {name: name.to_string(), group_id: 0, published: true});
+        }
+        for (bp_id, product, material) in [(11, 1, 2), (12, 2, 1)].iter()
+        {
+            let manufacturing = Activity {time: 60, materials: vec![(*material, 10)],
+                products: vec![Product {type_id: *product, quantity: 1, probability: None}],
+                skills: Vec::new()};
+            sde.blueprints.insert(*bp_id, SdeBlueprint {max_production_limit: 10,
+                activities: vec![("manufacturing".to_string(), manufacturing)].into_iter()
+                    .collect()});
+        }
+        Database::from_sde(sde)
+    }
+
+    fn run() -> ProductionRun
+    {
+        ProductionRun {id: 1, name: "Alpha Blueprint".to_string(), status: RunStatus::Planned,
+            planned_date: None, blueprint: "Alpha Blueprint".to_string(), materials: Vec::new(),
+            products: Vec::new(), jobruns: 1, installation_cost: None,
+            facility: Facility::default(), build_components: true, choices: HashMap::new(),
+            refinery: None, materials_consumed: false, products_added: false}
+    }
+
+    #[test]
+    fn material_needing_itself_is_bought()
+    {
+        let db = cyclic_database();
+        let fees = FeeModel {base_sales_tax: 7.5, accounting: 0, broker_relations: 0,
+            faction_standing: 0.0, corp_standing: 0.0, structure_broker_fee: None,
+            sell_mode: SellMode::SellOrder, buy_mode: BuyMode::BuyOrder};
+        let beta = expand(&db, 2, 10, &run(), &fees).unwrap();
+        let beta_job = beta.job.as_ref().expect("Beta is built");
+        assert_eq!((beta_job.blueprint.as_str(), beta_job.runs), ("Beta Blueprint", 10));
+        let alpha = &beta_job.inputs[0];
+        let alpha_job = alpha.job.as_ref().expect("Alpha is built");
+        assert_eq!((alpha.name.as_str(), alpha.quantity), ("Alpha", 100));
+        // Beta is already being expanded above, so it ends the branch as a bought leaf
+        let leaf = &alpha_job.inputs[0];
+        assert_eq!((leaf.name.as_str(), leaf.quantity), ("Beta", 1000));
+        assert!(leaf.job.is_none());
+    }
+}
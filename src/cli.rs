@@ -0,0 +1,1389 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use serde::Serialize;
+use num_format::ToFormattedString;
+
+use crate::buildtree::{self, BuildChoice};
+use crate::error::{Error, Result};
+use crate::evedata::{BlueprintKind, Database, ProductionRun, Profit, RunStatus,
+    DEFAULT_LOCATION, MAX_QUANTITY};
+use crate::industry::{Facility, Structure};
+use crate::invention::{Decryptor, InventionCost};
+use crate::isk::Isk;
+use crate::esi::EsiClient;
+use crate::esi_import;
+use crate::marketlogs::PricePoint;
+use crate::research::{self, CopyJob, ResearchStep};
+use crate::search::SearchFilter;
+use crate::settings::{self, Settings};
+use crate::utils;
+
+pub const COMMANDS_HELP: &str = "Commands:\n  \
+    list-blueprints [--json]\n  \
+    list-items [--json]\n  \
+    list-runs [--json]\n  \
+    search <QUERY>... [--category blueprint|ship|module] [--group <GROUP_ID>] [--all]\n      \
+        [--limit <N>] [--json]         (--all includes unpublished types)\n  \
+    add-blueprint --id <TYPE_ID> [--me <0-10>] [--te <0-20>] [--copy --runs <N>]\n      \
+        [--quantity <N>] [--json]       (--runs is the total over all copies)\n  \
+    set-research <BLUEPRINT> [--me <0-10>] [--te <0-20>] [--json]\n  \
+    set-ownership <BLUEPRINT> original|copy [--quantity <N>] [--runs <N>] [--json]\n  \
+    delete-blueprint <BLUEPRINT> [--with-runs] [--json]\n  \
+    set-price <ITEM> [--buy <ISK>] [--sell <ISK>] [--json]\n  \
+    delete-item <ITEM> [--force] [--json]\n                                       \
+    (--force also deletes an item that has stock)\n  \
+    add-run <BLUEPRINT> --runs <N> (--install-cost <ISK> | --system <ID> [--facility-tax <PCT>])\n      \
+        [--structure station|raitaru|azbel|sotiyo|athanor|tatara] [--rig none|t1|t2]\n      \
+        [--te-rig none|t1|t2] [--security high|low|null] [--build-components]\n      \
+        [--refinery-structure athanor|tatara] [--refinery-rig ...] [--refinery-te-rig ...]\n      \
+        [--refinery-security ...] [--refinery-system <ID>] [--refinery-facility-tax <PCT>]\n      \
+        [--name <NAME>] [--date <YYYY-MM-DD>] [--json]\n  \
+    set-run-status <RUN> planned|running|done|cancelled [--json]\n  \
+    update-run <RUN> [--runs <N>] [--name <NAME>] [--date <YYYY-MM-DD>|none] [--json]\n  \
+    delete-run <RUN> [--json]\n  \
+    build-tree <RUN> [--json]           (materials and sub-builds of a production run)\n  \
+    set-build-choice <RUN> <ITEM> build|buy|auto [--json]\n  \
+    add-invention --id <T2_BLUEPRINT_ID> [--decryptor none|<NAME>] [--system <ID>]\n      \
+        [--facility-tax <PCT>] [--structure ...] [--security ...] [--json]\n  \
+    invention [BLUEPRINT...] [--json]   (expected cost per invented copy)\n  \
+    research-plan <BLUEPRINT> [--me <0-10>] [--te <0-20>] [--copies <N> [--copy-runs <N>]]\n      \
+        [--system <ID>] [--facility-tax <PCT>] [--structure ...] [--te-rig ...]\n      \
+        [--security ...] [--json]     (research time, cost and payback up to ME/TE)\n  \
+    profit [RUN...] [--json]\n  \
+    import-adjusted-prices <FILE> [--json]\n                                       \
+    (ESI /markets/prices/ response)\n  \
+    import-cost-indices <FILE> [--json]\n                                       \
+    (ESI /industry/systems/ response)\n  \
+    import-esi [--blueprints <FILE>] [--assets <FILE>] [--character <ID>] [--base-url <URL>]\n      \
+        [--json]                       (blueprints and assets of a character, from saved\n                                       \
+    responses or else fetched from ESI)\n  \
+    import-market-logs [DIR] [--price-point best|p<PERCENT>] [--json]\n                                       \
+    (EVE client market exports)\n  \
+    import-orders [FILE] [--region <ID>] [--location <ID>] [--json]\n                                       \
+    (order books from an ESI /markets/{region_id}/orders/\n                                       \
+    response, or fetched for all known items)\n  \
+    esi-login                          (log a character in with EVE SSO)\n  \
+    esi-logout\n  \
+    esi-update [--prices] [--cost-indices] [--json]\n                                       \
+    (fetch adjusted prices and cost indices, default both)\n  \
+    set-cost-index <SYSTEM_ID> <INDEX> [--activity <ACTIVITY>] [--json]\n  \
+    shopping-list <RUN>... [--location <LOC>] [--all] [--json]\n      \
+        (--all also lists materials that are fully in stock; with an imported order book\n      \
+        instant-buy walks its sell orders and flags quantities the book cannot fill, and\n      \
+        buy-order prices at its best bid)\n  \
+    list-stock [ITEM] [--location <LOC>] [--json]\n  \
+    set-stock <ITEM> (--quantity <N> | --add <N> | --remove <N>) [--location <LOC>] [--json]\n  \
+    consume-materials <RUN> [--location <LOC>] [--json]\n  \
+    add-products <RUN> [--location <LOC>] [--json]\n\n\
+RUN is the ID or name of a production run. Reaction formulas are added with add-blueprint\n\
+and run in an athanor or tatara; the --refinery-* options set where reaction sub-builds of\n\
+--build-components are installed. Stock is kept per location, hangar unless --location is\n\
+given; shopping lists and consume-materials use the stock at all locations by default.";
+
+pub enum Command
+{
+    ListBlueprints,
+    ListItems,
+    ListRuns,
+    Search {query: String, filter: SearchFilter, limit: usize},
+    AddBlueprint {id: usize, material_research: u8, time_research: u8,
+        ownership: Option<(BlueprintKind, u64, u64)>},
+    SetOwnership {blueprint: String, kind: BlueprintKind, quantity: Option<u64>, runs: u64},
+    SetResearch {blueprint: String, material_research: Option<u8>, time_research: Option<u8>},
+    DeleteBlueprint {blueprint: String, with_runs: bool},
+    SetPrice {item: String, buy: Option<Isk>, sell: Option<Isk>},
+    DeleteItem {item: String, force: bool},
+    AddRun {blueprint: String, jobruns: u64, installation_cost: Option<Isk>, facility: Facility,
+        build_components: bool, refinery: Option<Facility>, name: Option<String>,
+        planned_date: Option<String>},
+    SetRunStatus {run: String, status: RunStatus},
+    UpdateRun {run: String, jobruns: Option<u64>, name: Option<String>,
+        planned_date: Option<Option<String>>},
+    DeleteRun {run: String},
+    BuildTree {run: String},
+    SetBuildChoice {run: String, item: String, choice: Option<BuildChoice>},
+    AddInvention {id: i64, decryptor: Option<Decryptor>, facility: Facility},
+    Invention {blueprints: Vec<String>},
+    ResearchPlan {blueprint: String, target_me: u8, target_te: u8, copies: Option<(u64, u64)>,
+        facility: Facility},
+    ImportAdjustedPrices {path: PathBuf},
+    ImportCostIndices {path: PathBuf},
+    ImportMarketLogs {dir: Option<PathBuf>, price_point: Option<PricePoint>},
+    ImportEsi {blueprints: Option<PathBuf>, assets: Option<PathBuf>, character_id: Option<i64>,
+        base_url: Option<String>},
+    ImportOrders {path: Option<PathBuf>, region: Option<i64>, location: Option<i64>},
+    EsiLogin,
+    EsiLogout,
+    EsiUpdate {prices: bool, cost_indices: bool},
+    SetCostIndex {solar_system: i64, activity: String, index: f64},
+    Profit {runs: Vec<String>},
+    ShoppingList {runs: Vec<String>, location: Option<String>, all: bool},
+    ListStock {item: Option<String>, location: Option<String>},
+    SetStock {item: String, location: String, change: StockChange},
+    ConsumeMaterials {run: String, location: Option<String>},
+    AddProducts {run: String, location: String},
+}
+
+pub struct Invocation
+{
+    pub command: Command,
+    pub json: bool,
+}
+
+/// Arguments of a subcommand split into positionals and `--flag value` options.
+struct CommandArgs
+{
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+    /// Given options that take no value.
+    switches: Vec<String>,
+    json: bool,
+}
+
+impl CommandArgs
+{
+    fn parse(args: &[String], value_flags: &[&str], switch_flags: &[&str]) -> CommandArgs
+    {
+        let mut ret = CommandArgs {positional: Vec::new(), options: HashMap::new(),
+            switches: Vec::new(), json: false};
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next()
+        {
+            if arg == "--json"
+            {
+                ret.json = true;
+            }
+            else if switch_flags.contains(&arg.as_str())
+            {
+                ret.switches.push(arg.clone());
+            }
+            else if value_flags.contains(&arg.as_str())
+            {
+                match iter.next()
+                {
+                    Some(v) => ret.options.insert(arg.clone(), v.clone()),
+                    None => settings::usage_error(&format!("{} requires a value", arg)),
+                };
+            }
+            else if arg.starts_with("--")
+            {
+                settings::usage_error(&format!("Unknown option: {}", arg));
+            }
+            else
+            {
+                ret.positional.push(arg.clone());
+            }
+        }
+        ret
+    }
+
+    fn value<T>(&self, flag: &str, min: T, max: T) -> Option<T>
+        where T: std::str::FromStr + PartialOrd + ToString,
+    {
+        let raw = self.options.get(flag)?;
+        match raw.parse::<T>()
+        {
+            Ok(v) if v >= min && v <= max => Some(v),
+            Ok(_) => settings::usage_error(&format!("{} expects a number between {} and {}",
+                flag, min.to_string(), max.to_string())),
+            Err(_) => settings::usage_error(&format!("{}: invalid value '{}'", flag, raw)),
+        }
+    }
+
+    fn required<T>(&self, flag: &str, min: T, max: T) -> T
+        where T: std::str::FromStr + PartialOrd + ToString,
+    {
+        self.value(flag, min, max)
+            .unwrap_or_else(|| settings::usage_error(&format!("{} is required", flag)))
+    }
+
+    fn parsed<T>(&self, flag: &str) -> Option<T>
+        where T: std::str::FromStr<Err = String>,
+    {
+        let raw = self.options.get(flag)?;
+        match raw.parse::<T>()
+        {
+            Ok(v) => Some(v),
+            Err(e) => settings::usage_error(&format!("{}: {}", flag, e)),
+        }
+    }
+
+    /// Facility from the --structure, --rig, --te-rig, --security, --system and
+    /// --facility-tax options, each prefixed with `prefix` instead of `--`. Unset
+    /// options are taken from `default`.
+    fn facility(&self, prefix: &str, default: Facility) -> Facility
+    {
+        let flag = |name: &str| format!("{}{}", prefix, name);
+        Facility {
+            structure: self.parsed(&flag("structure")).unwrap_or(default.structure),
+            me_rig: self.parsed(&flag("rig")).unwrap_or(default.me_rig),
+            te_rig: self.parsed(&flag("te-rig")).unwrap_or(default.te_rig),
+            security: self.parsed(&flag("security")).unwrap_or(default.security),
+            solar_system: self.value(&flag("system"), 1, i64::MAX).or(default.solar_system),
+            facility_tax: self.value(&flag("facility-tax"), 0.0, 100.0)
+                .unwrap_or(default.facility_tax)}
+    }
+
+    fn single_positional(&self, what: &str) -> String
+    {
+        match self.positional.as_slice()
+        {
+            [x] => x.clone(),
+            _ => settings::usage_error(&format!("Expected exactly one {}", what)),
+        }
+    }
+
+    fn no_positional(&self)
+    {
+        if let Some(x) = self.positional.first()
+        {
+            settings::usage_error(&format!("Unexpected argument: {}", x));
+        }
+    }
+}
+
+/// Parses a subcommand and its arguments. Usage errors terminate the process.
+pub fn parse(command: &[String]) -> Invocation
+{
+    let name = command[0].as_str();
+    let rest = &command[1..];
+    let args = match name
+    {
+        "add-blueprint" => CommandArgs::parse(rest, &["--id", "--me", "--te", "--runs",
+            "--quantity"], &["--copy"]),
+        "set-ownership" => CommandArgs::parse(rest, &["--quantity", "--runs"], &[]),
+        "set-research" => CommandArgs::parse(rest, &["--me", "--te"], &[]),
+        "delete-blueprint" => CommandArgs::parse(rest, &[], &["--with-runs"]),
+        "delete-item" => CommandArgs::parse(rest, &[], &["--force"]),
+        "set-price" => CommandArgs::parse(rest, &["--buy", "--sell"], &[]),
+        "update-run" => CommandArgs::parse(rest, &["--runs", "--name", "--date"], &[]),
+        "add-run" => CommandArgs::parse(rest, &["--runs", "--install-cost", "--structure",
+            "--rig", "--te-rig", "--security", "--system", "--facility-tax", "--name", "--date",
+            "--refinery-structure", "--refinery-rig", "--refinery-te-rig", "--refinery-security",
+            "--refinery-system", "--refinery-facility-tax"], &["--build-components"]),
+        "add-invention" => CommandArgs::parse(rest, &["--id", "--decryptor", "--structure",
+            "--rig", "--te-rig", "--security", "--system", "--facility-tax"], &[]),
+        "research-plan" => CommandArgs::parse(rest, &["--me", "--te", "--copies", "--copy-runs",
+            "--structure", "--te-rig", "--security", "--system", "--facility-tax"], &[]),
+        "set-cost-index" => CommandArgs::parse(rest, &["--activity"], &[]),
+        "shopping-list" => CommandArgs::parse(rest, &["--location"], &["--all"]),
+        "list-stock" | "consume-materials" | "add-products" =>
+            CommandArgs::parse(rest, &["--location"], &[]),
+        "set-stock" => CommandArgs::parse(rest, &["--quantity", "--add", "--remove", "--location"],
+            &[]),
+        "import-market-logs" => CommandArgs::parse(rest, &["--price-point"], &[]),
+        "import-esi" => CommandArgs::parse(rest, &["--blueprints", "--assets", "--character",
+            "--base-url"], &[]),
+        "esi-update" => CommandArgs::parse(rest, &[], &["--prices", "--cost-indices"]),
+        "import-orders" => CommandArgs::parse(rest, &["--region", "--location"], &[]),
+        "search" => CommandArgs::parse(rest, &["--category", "--group", "--limit"], &["--all"]),
+        _ => CommandArgs::parse(rest, &[], &[]),
+    };
+
+    let command = match name
+    {
+        "list-blueprints" =>
+        {
+            args.no_positional();
+            Command::ListBlueprints
+        }
+        "list-items" =>
+        {
+            args.no_positional();
+            Command::ListItems
+        }
+        "list-runs" =>
+        {
+            args.no_positional();
+            Command::ListRuns
+        }
+        "search" =>
+        {
+            if args.positional.is_empty()
+            {
+                settings::usage_error("search needs a query");
+            }
+            let filter = SearchFilter {category: args.parsed("--category"),
+                group_id: args.value("--group", 0, i64::MAX),
+                include_unpublished: args.switches.iter().any(|s| s == "--all")};
+            Command::Search {query: args.positional.join(" "), filter,
+                limit: args.value("--limit", 1, usize::MAX).unwrap_or(20)}
+        }
+        "add-blueprint" =>
+        {
+            args.no_positional();
+            let copy = args.switches.iter().any(|s| s == "--copy");
+            let quantity = args.value("--quantity", 1, MAX_QUANTITY);
+            let ownership = match (copy, quantity)
+            {
+                (true, _) => Some((BlueprintKind::Copy, quantity.unwrap_or(1),
+                    args.required("--runs", 1, MAX_QUANTITY))),
+                (false, Some(quantity)) => Some((BlueprintKind::Original, quantity, 0)),
+                (false, None) => None,
+            };
+            Command::AddBlueprint {id: args.required("--id", 0, usize::MAX),
+                material_research: args.value("--me", 0, 10).unwrap_or(0),
+                time_research: args.value("--te", 0, 20).unwrap_or(0), ownership}
+        }
+        "set-ownership" =>
+        {
+            let (blueprint, kind) = match args.positional.as_slice()
+            {
+                [blueprint, kind] => (blueprint.clone(), kind.parse::<BlueprintKind>()
+                    .unwrap_or_else(|e| settings::usage_error(&e))),
+                _ => settings::usage_error("set-ownership needs a blueprint and original or copy"),
+            };
+            let runs = match kind
+            {
+                BlueprintKind::Original => 0,
+                BlueprintKind::Copy => args.required("--runs", 0, MAX_QUANTITY),
+            };
+            Command::SetOwnership {blueprint, kind, runs,
+                quantity: args.value("--quantity", 0, MAX_QUANTITY)}
+        }
+        "set-research" =>
+        {
+            let material_research = args.value("--me", 0, 10);
+            let time_research = args.value("--te", 0, 20);
+            if material_research.is_none() && time_research.is_none()
+            {
+                settings::usage_error("set-research needs --me and/or --te");
+            }
+            Command::SetResearch {blueprint: args.single_positional("blueprint name"),
+                material_research, time_research}
+        }
+        "delete-blueprint" => Command::DeleteBlueprint {
+            blueprint: args.single_positional("blueprint name"),
+            with_runs: args.switches.iter().any(|s| s == "--with-runs")},
+        "delete-item" => Command::DeleteItem {item: args.single_positional("item name"),
+            force: args.switches.iter().any(|s| s == "--force")},
+        "set-price" =>
+        {
+            let item = args.single_positional("item name");
+            let buy = args.value("--buy", Isk::ZERO, Isk::MAX);
+            let sell = args.value("--sell", Isk::ZERO, Isk::MAX);
+            if buy.is_none() && sell.is_none()
+            {
+                settings::usage_error("set-price needs --buy and/or --sell");
+            }
+            Command::SetPrice {item, buy, sell}
+        }
+        "add-run" =>
+        {
+            let facility = args.facility("--", Facility::default());
+            // The refinery is in the same solar system unless given otherwise
+            let refinery = args.options.keys().any(|k| k.starts_with("--refinery-"))
+                .then(|| args.facility("--refinery-", Facility {structure: Structure::Athanor,
+                    security: facility.security, solar_system: facility.solar_system,
+                    facility_tax: facility.facility_tax, ..Facility::default()}));
+            let installation_cost = args.value("--install-cost", Isk::ZERO, Isk::MAX);
+            if installation_cost.is_none() && facility.solar_system.is_none()
+            {
+                settings::usage_error("add-run needs --install-cost or --system");
+            }
+            let planned_date = args.options.get("--date").cloned();
+            if planned_date.as_deref().is_some_and(|d| !utils::is_valid_date(d))
+            {
+                settings::usage_error("--date expects a date like 2024-05-31");
+            }
+            Command::AddRun {blueprint: args.single_positional("blueprint name"),
+                jobruns: args.required("--runs", 1, MAX_QUANTITY), installation_cost, facility,
+                build_components: args.switches.iter().any(|s| s == "--build-components"),
+                refinery, name: args.options.get("--name").cloned(), planned_date}
+        }
+        "set-run-status" =>
+        {
+            let (run, status) = match args.positional.as_slice()
+            {
+                [run, status] => (run.clone(), status.parse::<RunStatus>()
+                    .unwrap_or_else(|e| settings::usage_error(&e))),
+                _ => settings::usage_error("set-run-status needs a production run and a status"),
+            };
+            Command::SetRunStatus {run, status}
+        }
+        "update-run" =>
+        {
+            let planned_date = match args.options.get("--date").map(String::as_str)
+            {
+                None => None,
+                Some("none") => Some(None),
+                Some(d) if utils::is_valid_date(d) => Some(Some(d.to_string())),
+                Some(_) => settings::usage_error("--date expects a date like 2024-05-31 or none"),
+            };
+            let jobruns = args.value("--runs", 1, MAX_QUANTITY);
+            let name = args.options.get("--name").cloned();
+            if jobruns.is_none() && name.is_none() && planned_date.is_none()
+            {
+                settings::usage_error("update-run needs --runs, --name or --date");
+            }
+            Command::UpdateRun {run: args.single_positional("production run"), jobruns, name,
+                planned_date}
+        }
+        "delete-run" => Command::DeleteRun {run: args.single_positional("production run")},
+        "build-tree" => Command::BuildTree {run: args.single_positional("production run")},
+        "set-build-choice" =>
+        {
+            let (run, item, choice) = match args.positional.as_slice()
+            {
+                [run, item, choice] => (run.clone(), item.clone(), choice.as_str()),
+                _ => settings::usage_error("set-build-choice needs a production run, an item \
+                    and build, buy or auto"),
+            };
+            let choice = match choice
+            {
+                "auto" => None,
+                _ => Some(choice.parse::<BuildChoice>()
+                    .unwrap_or_else(|e| settings::usage_error(&e))),
+            };
+            Command::SetBuildChoice {run, item, choice}
+        }
+        "add-invention" =>
+        {
+            args.no_positional();
+            let decryptor = match args.options.get("--decryptor").map(String::as_str)
+            {
+                None | Some("none") => None,
+                Some(_) => args.parsed("--decryptor"),
+            };
+            Command::AddInvention {id: args.required("--id", 0, i64::MAX), decryptor,
+                facility: args.facility("--", Facility::default())}
+        }
+        "invention" => Command::Invention {blueprints: args.positional.clone()},
+        "research-plan" =>
+        {
+            let target_te = args.value("--te", 0, research::MAX_TIME_RESEARCH)
+                .unwrap_or(research::MAX_TIME_RESEARCH);
+            if let Err(e) = research::check_target_te(target_te)
+            {
+                settings::usage_error(&e.to_string());
+            }
+            let copies = args.value("--copies", 1, MAX_QUANTITY)
+                .map(|copies| (copies, args.value("--copy-runs", 1, MAX_QUANTITY).unwrap_or(1)));
+            Command::ResearchPlan {blueprint: args.single_positional("blueprint name"),
+                target_me: args.value("--me", 0, research::MAX_MATERIAL_RESEARCH)
+                    .unwrap_or(research::MAX_MATERIAL_RESEARCH),
+                target_te, copies, facility: args.facility("--", Facility::default())}
+        }
+        "import-adjusted-prices" =>
+        {
+            Command::ImportAdjustedPrices {path: args.single_positional("file").into()}
+        }
+        "import-cost-indices" =>
+        {
+            Command::ImportCostIndices {path: args.single_positional("file").into()}
+        }
+        "import-market-logs" =>
+        {
+            let dir = match args.positional.as_slice()
+            {
+                [] => None,
+                [dir] => Some(dir.into()),
+                _ => settings::usage_error("Expected at most one directory"),
+            };
+            Command::ImportMarketLogs {dir, price_point: args.parsed("--price-point")}
+        }
+        "import-esi" =>
+        {
+            args.no_positional();
+            Command::ImportEsi {blueprints: args.options.get("--blueprints").map(PathBuf::from),
+                assets: args.options.get("--assets").map(PathBuf::from),
+                character_id: args.value("--character", 1, i64::MAX),
+                base_url: args.options.get("--base-url").cloned()}
+        }
+        "import-orders" =>
+        {
+            let (path, region) = match (args.positional.as_slice(),
+                args.value("--region", 1, i64::MAX))
+            {
+                ([path], None) => (Some(path.into()), None),
+                ([], Some(region)) => (None, Some(region)),
+                _ => settings::usage_error("import-orders needs either a file or --region"),
+            };
+            Command::ImportOrders {path, region, location: args.value("--location", 1, i64::MAX)}
+        }
+        "esi-login" =>
+        {
+            args.no_positional();
+            Command::EsiLogin
+        }
+        "esi-logout" =>
+        {
+            args.no_positional();
+            Command::EsiLogout
+        }
+        "esi-update" =>
+        {
+            args.no_positional();
+            let prices = args.switches.iter().any(|s| s == "--prices");
+            let cost_indices = args.switches.iter().any(|s| s == "--cost-indices");
+            Command::EsiUpdate {prices: prices || !cost_indices,
+                cost_indices: cost_indices || !prices}
+        }
+        "set-cost-index" =>
+        {
+            let (solar_system, index) = match args.positional.as_slice()
+            {
+                [system, index] => (system.parse::<i64>().ok(), index.parse::<f64>().ok()),
+                _ => settings::usage_error("set-cost-index needs a solar system ID and an index"),
+            };
+            match (solar_system, index)
+            {
+                (Some(solar_system), Some(index)) if index >= 0.0 =>
+                {
+                    Command::SetCostIndex {solar_system, index,
+                        activity: args.options.get("--activity").cloned()
+                            .unwrap_or_else(|| "manufacturing".to_string())}
+                }
+                _ => settings::usage_error("set-cost-index needs a solar system ID and an index"),
+            }
+        }
+        "profit" => Command::Profit {runs: args.positional.clone()},
+        "shopping-list" =>
+        {
+            if args.positional.is_empty()
+            {
+                settings::usage_error("shopping-list needs at least one production run");
+            }
+            Command::ShoppingList {runs: args.positional.clone(),
+                location: args.options.get("--location").cloned(),
+                all: args.switches.iter().any(|s| s == "--all")}
+        }
+        "list-stock" =>
+        {
+            let item = match args.positional.as_slice()
+            {
+                [] => None,
+                [item] => Some(item.clone()),
+                _ => settings::usage_error("Expected at most one item"),
+            };
+            Command::ListStock {item, location: args.options.get("--location").cloned()}
+        }
+        "set-stock" =>
+        {
+            let change = match (args.value("--quantity", 0, MAX_QUANTITY),
+                args.value("--add", 1, MAX_QUANTITY), args.value("--remove", 1, MAX_QUANTITY))
+            {
+                (Some(n), None, None) => StockChange::Set(n),
+                (None, Some(n), None) => StockChange::Add(n),
+                (None, None, Some(n)) => StockChange::Remove(n),
+                _ => settings::usage_error("set-stock needs one of --quantity, --add and --remove"),
+            };
+            Command::SetStock {item: args.single_positional("item name"), change,
+                location: args.options.get("--location").cloned()
+                    .unwrap_or_else(|| DEFAULT_LOCATION.to_string())}
+        }
+        "consume-materials" => Command::ConsumeMaterials {
+            run: args.single_positional("production run"),
+            location: args.options.get("--location").cloned()},
+        "add-products" => Command::AddProducts {run: args.single_positional("production run"),
+            location: args.options.get("--location").cloned()
+                .unwrap_or_else(|| DEFAULT_LOCATION.to_string())},
+        _ => settings::usage_error(&format!("Unknown command: {}", name)),
+    };
+    if args.json && matches!(command, Command::EsiLogin | Command::EsiLogout)
+    {
+        settings::usage_error(&format!("{} has no JSON output", name));
+    }
+    Invocation {command, json: args.json}
+}
+
+#[derive(Serialize)]
+struct ProfitRow<'a>
+{
+    run: &'a str,
+    id: u64,
+    jobruns: u64,
+    units_produced: Vec<(String, u64)>,
+    /// `None` if the run needs a blueprint copy whose invention can never succeed.
+    #[serde(flatten)]
+    profit: Option<Profit>,
+}
+
+#[derive(Serialize)]
+struct InventionRow<'a>
+{
+    blueprint: &'a str,
+    decryptor: Option<Decryptor>,
+    #[serde(flatten)]
+    cost: InventionCost,
+}
+
+#[derive(Serialize)]
+struct ResearchReport<'a>
+{
+    blueprint: &'a str,
+    steps: Vec<ResearchStep>,
+    copying: Option<CopyJob>,
+}
+
+#[derive(Serialize)]
+struct ShoppingRow<'a>
+{
+    item: &'a str,
+    /// Quantity still to buy.
+    quantity: u64,
+    needed: u64,
+    in_stock: u64,
+    /// Average unit price, walking the sell orders when buying instantly from an order book.
+    /// `None` if the order book could not fill anything.
+    buy_price: Option<Isk>,
+    /// `None` if the order book could not fill the whole quantity.
+    total: Option<Isk>,
+    /// Quantity the order book could not fill.
+    short: u64,
+    order_book: bool,
+}
+
+#[derive(Serialize, Default)]
+struct EsiImportReport
+{
+    blueprints: Vec<String>,
+    /// Item and location pairs whose stock was set.
+    stocked_items: usize,
+    skipped: Vec<String>,
+    /// Asset type IDs missing from the SDE.
+    unknown_types: Vec<i64>,
+}
+
+#[derive(Serialize)]
+struct DeletedBlueprint<'a>
+{
+    blueprint: &'a str,
+    /// Production runs deleted with the blueprint.
+    runs: Vec<String>,
+}
+
+/// Number of prices, solar systems or order books an import stored.
+#[derive(Serialize)]
+struct ImportCount
+{
+    imported: usize,
+}
+
+#[derive(Serialize, Default)]
+struct EsiUpdateReport
+{
+    adjusted_prices: Option<usize>,
+    /// Solar systems whose cost indices were imported.
+    solar_systems: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct CostIndexRow<'a>
+{
+    solar_system: i64,
+    activity: &'a str,
+    cost_index: f64,
+}
+
+#[derive(Serialize)]
+struct StockRow<'a>
+{
+    item: &'a str,
+    location: &'a str,
+    quantity: u64,
+}
+
+/// How `set-stock` changes the quantity at a location.
+pub enum StockChange
+{
+    Set(u64),
+    Add(u64),
+    Remove(u64),
+}
+
+pub fn execute(invocation: Invocation, db: &mut Database, settings: &Settings) -> Result<()>
+{
+    let json = invocation.json;
+    let locale = settings::locale();
+    let num = |v: u64| v.to_formatted_string(locale);
+    let isk = |v: Isk| v.to_string();
+
+    match invocation.command
+    {
+        Command::ListBlueprints =>
+        {
+            let mut bps: Vec<_> = db.known_blueprints.values().collect();
+            bps.sort_by(|a, b| a.name.cmp(&b.name));
+            if json
+            {
+                return print_json(&bps);
+            }
+            print_table(&["Blueprint", "ID", "Kind", "Owned", "Runs left", "ME", "TE",
+                "Produces"], bps.iter()
+                .map(|bp| vec![bp.name.clone(), bp.bp_id.to_string(), bp.kind.to_string(),
+                    bp.quantity.to_string(),
+                    db.available_runs(&bp.name, None).map_or(String::new(), |r| r.to_string()),
+                    bp.material_research.to_string(), bp.time_research.to_string(),
+                    format_products(&bp.products)])
+                .collect());
+        }
+        Command::ListItems =>
+        {
+            let mut items: Vec<_> = db.known_items.values().collect();
+            items.sort_by(|a, b| a.name.cmp(&b.name));
+            if json
+            {
+                return print_json(&items);
+            }
+            print_table(&["Item", "ID", "Buy price", "Sell price", "Produced"], items.iter()
+                .map(|item| vec![item.name.clone(), item.id.to_string(), isk(item.buy_price),
+                    isk(item.sell_price), item.produced.to_string()])
+                .collect());
+        }
+        Command::ListRuns =>
+        {
+            let mut runs: Vec<_> = db.productionruns.values().collect();
+            runs.sort_by_key(|pr| pr.id);
+            if json
+            {
+                return print_json(&runs);
+            }
+            print_table(&["Name", "ID", "Blueprint", "Status", "Date", "Runs",
+                "Installation cost", "Produces", "Facility"], runs.iter()
+                .map(|pr| vec![pr.name.clone(), pr.id.to_string(), pr.blueprint.clone(),
+                    pr.status.to_string(), pr.planned_date.clone().unwrap_or_default(),
+                    pr.jobruns.to_string(), pr.get_installation_cost(db, &settings.fees)
+                        .map(isk).unwrap_or_else(|_| "n/a".to_string()),
+                    format_products(&pr.get_units_produced()),
+                    pr.facility.to_string()])
+                .collect());
+        }
+        Command::Search {query, filter, limit} =>
+        {
+            let hits = db.search(&query, &filter, limit);
+            if json
+            {
+                return print_json(&hits);
+            }
+            print_table(&["Name", "ID", "Group"], hits.iter()
+                .map(|hit| vec![hit.name.to_string(), hit.id.to_string(),
+                    hit.group.unwrap_or("").to_string()])
+                .collect());
+        }
+        Command::AddBlueprint {id, material_research, time_research, ownership} =>
+        {
+            let known_items: HashSet<String> = db.known_items.keys().cloned().collect();
+            let name = db.add_blueprint(id, material_research, time_research)?;
+            if let Some((kind, quantity, runs)) = ownership
+            {
+                // Do not keep the blueprint or the items added for it if its runs are invalid
+                if let Err(e) = db.set_blueprint_ownership(&name, kind, quantity, runs)
+                {
+                    db.delete_blueprint(&name, false)?;
+                    let added: Vec<String> = db.known_items.keys()
+                        .filter(|item| !known_items.contains(*item))
+                        .cloned()
+                        .collect();
+                    for item in added
+                    {
+                        db.delete_item(&item, false)?;
+                    }
+                    return Err(e);
+                }
+            }
+            let bp = db.get_blueprint(&name).ok_or(Error::UnknownBlueprint(name.clone()))?;
+            if json
+            {
+                return print_json(bp);
+            }
+            println!("Added {} to known blueprints", name);
+        }
+        Command::SetResearch {blueprint, material_research, time_research} =>
+        {
+            let bp = db.get_blueprint(&blueprint)
+                .ok_or_else(|| Error::UnknownBlueprint(blueprint.clone()))?;
+            db.set_blueprint_research(&blueprint,
+                material_research.unwrap_or(bp.material_research),
+                time_research.unwrap_or(bp.time_research))?;
+            let bp = db.get_blueprint(&blueprint)
+                .ok_or_else(|| Error::UnknownBlueprint(blueprint.clone()))?;
+            if json
+            {
+                return print_json(bp);
+            }
+            println!("{}: ME {}, TE {}", bp.name, bp.material_research, bp.time_research);
+        }
+        Command::SetOwnership {blueprint, kind, quantity, runs} =>
+        {
+            let bp = db.get_blueprint(&blueprint)
+                .ok_or_else(|| Error::UnknownBlueprint(blueprint.clone()))?;
+            db.set_blueprint_ownership(&blueprint, kind, quantity.unwrap_or(bp.quantity), runs)?;
+            let bp = db.get_blueprint(&blueprint)
+                .ok_or_else(|| Error::UnknownBlueprint(blueprint.clone()))?;
+            if json
+            {
+                return print_json(bp);
+            }
+            match kind
+            {
+                BlueprintKind::Original => println!("{}: {} original(s)", bp.name, bp.quantity),
+                BlueprintKind::Copy => println!("{}: {} copies with {} runs left", bp.name,
+                    bp.quantity, bp.runs),
+            }
+        }
+        Command::DeleteBlueprint {blueprint, with_runs} =>
+        {
+            let runs = db.delete_blueprint(&blueprint, with_runs)?;
+            if json
+            {
+                return print_json(&DeletedBlueprint {blueprint: &blueprint, runs});
+            }
+            for run in runs
+            {
+                println!("Deleted production run {}", run);
+            }
+            println!("Deleted blueprint {}", blueprint);
+        }
+        Command::DeleteItem {item, force} =>
+        {
+            let item = db.delete_item(&item, force)?;
+            if json
+            {
+                return print_json(&item);
+            }
+            println!("Deleted item {}", item.name);
+        }
+        Command::SetPrice {item, buy, sell} =>
+        {
+            if let Some(price) = buy
+            {
+                db.set_item_buy_price(&item, price)?;
+            }
+            if let Some(price) = sell
+            {
+                db.set_item_sell_price(&item, price)?;
+            }
+            let item = db.get_item(&item).ok_or(Error::UnknownItem(item.clone()))?;
+            if json
+            {
+                return print_json(item);
+            }
+            println!("{}", item);
+        }
+        Command::AddRun {blueprint, jobruns, installation_cost, facility, build_components,
+            refinery, name, planned_date} =>
+        {
+            let mut pr = ProductionRun::new(&blueprint, jobruns, installation_cost, facility,
+                build_components, db)?;
+            pr.refinery = refinery;
+            pr.name = name.unwrap_or_default();
+            pr.planned_date = planned_date;
+            let id = db.add_productionrun(pr)?;
+            let pr = db.get_productionrun(id).ok_or(Error::UnknownProductionRun(id.to_string()))?;
+            if json
+            {
+                return print_json(pr);
+            }
+            println!("Added production run {} ({})", pr.name, id);
+        }
+        Command::SetRunStatus {run, status} =>
+        {
+            let id = db.find_productionrun(&run)?.id;
+            db.set_productionrun_status(id, status)?;
+            let pr = db.get_productionrun(id).ok_or(Error::UnknownProductionRun(run.clone()))?;
+            if json
+            {
+                return print_json(pr);
+            }
+            println!("{} is {}", run, status);
+        }
+        Command::UpdateRun {run, jobruns, name, planned_date} =>
+        {
+            let id = db.find_productionrun(&run)?.id;
+            if let Some(jobruns) = jobruns
+            {
+                db.set_productionrun_jobruns(id, jobruns)?;
+            }
+            if let Some(name) = name
+            {
+                db.rename_productionrun(id, &name)?;
+            }
+            if let Some(planned_date) = planned_date
+            {
+                db.set_productionrun_date(id, planned_date)?;
+            }
+            let pr = db.get_productionrun(id).ok_or(Error::UnknownProductionRun(run.clone()))?;
+            if json
+            {
+                return print_json(pr);
+            }
+            println!("Updated production run {} ({})", pr.name, id);
+        }
+        Command::DeleteRun {run} =>
+        {
+            let id = db.find_productionrun(&run)?.id;
+            let pr = db.delete_productionrun(id)?;
+            if json
+            {
+                return print_json(&pr);
+            }
+            println!("Deleted production run {} ({})", pr.name, id);
+        }
+        Command::BuildTree {run} =>
+        {
+            let pr = db.find_productionrun(&run)?;
+            let tree = pr.get_build_tree(db, &settings.fees)?;
+            if json
+            {
+                return print_json(&tree);
+            }
+            println!("{} x {}", pr.blueprint, pr.jobruns);
+            print!("{}", buildtree::format_tree(&tree));
+            println!("Materials: {}", tree.iter().map(|n| n.cost()).sum::<Isk>());
+        }
+        Command::SetBuildChoice {run, item, choice} =>
+        {
+            let id = db.find_productionrun(&run)?.id;
+            db.set_build_choice(id, &item, choice)?;
+            let pr = db.get_productionrun(id).ok_or(Error::UnknownProductionRun(run.clone()))?;
+            if json
+            {
+                return print_json(pr);
+            }
+            println!("{} in {}: {}", item, run,
+                choice.map_or("recommended".to_string(), |c| c.to_string()));
+        }
+        Command::AddInvention {id, decryptor, facility} =>
+        {
+            let name = db.set_invention(id, decryptor, facility)?;
+            let bp = db.get_blueprint(&name).ok_or(Error::UnknownBlueprint(name.clone()))?;
+            if json
+            {
+                return print_json(bp);
+            }
+            println!("Set up invention of {} (ME {}, TE {})", name, bp.material_research,
+                bp.time_research);
+        }
+        Command::Invention {blueprints} =>
+        {
+            let mut names = blueprints;
+            if names.is_empty()
+            {
+                names = db.known_blueprints.values().filter(|bp| bp.invention.is_some())
+                    .map(|bp| bp.name.clone()).collect();
+                names.sort();
+            }
+            let mut rows = Vec::<InventionRow>::new();
+            for name in names.iter()
+            {
+                let bp = db.get_blueprint(name)
+                    .ok_or_else(|| Error::UnknownBlueprint(name.clone()))?;
+                let invention = bp.invention.as_ref()
+                    .ok_or_else(|| Error::NotInventable(name.clone()))?;
+                rows.push(InventionRow {blueprint: name, decryptor: invention.decryptor,
+                    cost: invention.cost(db, settings)?});
+            }
+            if json
+            {
+                return print_json(&rows);
+            }
+            print_table(&["Blueprint", "Decryptor", "Chance", "Runs", "Datacores", "Decryptor",
+                "Jobs", "Per attempt", "Per copy", "Per run"], rows.iter()
+                .map(|r| vec![r.blueprint.to_string(),
+                    r.decryptor.map_or("none".to_string(), |d| d.to_string()),
+                    format!("{:.1}%", r.cost.probability * 100.0), r.cost.runs_per_copy.to_string(),
+                    isk(r.cost.datacore_cost), isk(r.cost.decryptor_cost),
+                    isk(r.cost.invention_job_cost + r.cost.copy_job_cost),
+                    isk(r.cost.cost_per_attempt), impossible(r.cost.cost_per_success),
+                    impossible(r.cost.cost_per_run())])
+                .collect());
+        }
+        Command::ResearchPlan {blueprint, target_me, target_te, copies, facility} =>
+        {
+            let bp = db.get_blueprint(&blueprint)
+                .ok_or_else(|| Error::UnknownBlueprint(blueprint.clone()))?;
+            let steps = research::research_steps(db, settings, bp, target_me, target_te,
+                &facility)?;
+            let copying = match copies
+            {
+                Some((copies, runs)) =>
+                    Some(research::copy_job(db, settings, bp, copies, runs, &facility)?),
+                None => None,
+            };
+            if json
+            {
+                return print_json(&ResearchReport {blueprint: &blueprint, steps, copying});
+            }
+            print_table(&["Activity", "Level", "Time", "Job cost", "Total time", "Total cost",
+                "Saved per run", "Payback runs"], steps.iter()
+                .map(|s| vec![s.activity.to_string(), s.level.to_string(),
+                    utils::format_duration(s.time), isk(s.job_cost),
+                    utils::format_duration(s.total_time), isk(s.total_cost),
+                    match (s.savings_per_run, s.time_saved_per_run)
+                    {
+                        (Some(savings), _) => isk(savings),
+                        (None, Some(time)) => utils::format_duration(time),
+                        (None, None) => String::new(),
+                    },
+                    s.payback_runs.map_or(String::new(), |r| r.to_string())])
+                .collect());
+            if let Some(c) = copying
+            {
+                println!("\nCopying {} x {} run(s): {}, job cost {}", c.copies, c.runs,
+                    utils::format_duration(c.time), isk(c.job_cost));
+            }
+        }
+        Command::ImportAdjustedPrices {path} =>
+        {
+            let count = db.import_adjusted_prices(&path)?;
+            if json
+            {
+                return print_json(&ImportCount {imported: count});
+            }
+            println!("Imported {} adjusted prices", count);
+        }
+        Command::ImportCostIndices {path} =>
+        {
+            let count = db.import_cost_indices(&path)?;
+            if json
+            {
+                return print_json(&ImportCount {imported: count});
+            }
+            println!("Imported cost indices of {} solar systems", count);
+        }
+        Command::ImportMarketLogs {dir, price_point} =>
+        {
+            let dir = dir.unwrap_or_else(|| settings.marketlogs_dir.clone());
+            let count = db.import_market_logs(&dir,
+                price_point.unwrap_or(settings.price_point))?;
+            if json
+            {
+                return print_json(&ImportCount {imported: count});
+            }
+            println!("Updated prices of {} items", count);
+        }
+        Command::ImportEsi {blueprints, assets, character_id, base_url} =>
+        {
+            let (blueprints, assets) = if blueprints.is_some() || assets.is_some()
+            {
+                (blueprints.map(|path| esi_import::read_blueprints(&path)).transpose()?,
+                    assets.map(|path| esi_import::read_assets(&path)).transpose()?)
+            }
+            else
+            {
+                let mut client = EsiClient::new(settings);
+                if let Some(url) = base_url
+                {
+                    client.set_base_url(&url);
+                }
+                let character_id = match character_id.or(settings.character_id)
+                {
+                    Some(id) => id,
+                    None => client.token()?.map(|token| token.character_id)
+                        .unwrap_or_else(|| settings::usage_error("import-esi needs --blueprints, \
+                            --assets, a character ID or an ESI login")),
+                };
+                let endpoint = |name| format!("characters/{}/{}/", character_id, name);
+                (Some(client.get_authorized_pages(&endpoint("blueprints"))?),
+                Some(client.get_authorized_pages(&endpoint("assets"))?))
+            };
+            let mut report = EsiImportReport::default();
+            if let Some(blueprints) = blueprints
+            {
+                (report.blueprints, report.skipped) = db.import_esi_blueprints(&blueprints)?;
+            }
+            if let Some(assets) = assets
+            {
+                (report.stocked_items, report.unknown_types) = db.import_esi_assets(&assets)?;
+            }
+            if json
+            {
+                return print_json(&report);
+            }
+            println!("Imported {} blueprints and stock of {} items", report.blueprints.len(),
+                report.stocked_items);
+            for skipped in report.skipped.iter()
+            {
+                println!("Skipped blueprint {}", skipped);
+            }
+            for type_id in report.unknown_types.iter()
+            {
+                println!("Skipped assets of unknown type ID {}", type_id);
+            }
+        }
+        Command::ImportOrders {path, region, location} =>
+        {
+            let count = match (path, region)
+            {
+                (Some(path), _) => db.import_order_books(&path, location)?,
+                (None, Some(region)) =>
+                {
+                    let mut type_ids: Vec<i64> = db.known_items.values().map(|i| i.id).collect();
+                    type_ids.sort_unstable();
+                    let books = esi_import::fetch_order_books(&EsiClient::new(settings), region,
+                        &type_ids, location)?;
+                    db.set_order_books(books)?
+                }
+                (None, None) => 0,
+            };
+            if json
+            {
+                return print_json(&ImportCount {imported: count});
+            }
+            println!("Imported order books of {} types", count);
+        }
+        Command::EsiLogin =>
+        {
+            let client = EsiClient::new(settings);
+            let request = client.login_request()?;
+            println!("Log in with this URL in a browser:\n{}\n", request.url);
+            let redirect = utils::read_input("Paste the URL the browser was redirected to: ");
+            let token = client.finish_login(&request, &redirect)?;
+            println!("Logged in as {} ({})", token.character_name, token.character_id);
+        }
+        Command::EsiLogout =>
+        {
+            if EsiClient::new(settings).logout()?
+            {
+                println!("Logged out");
+            }
+            else
+            {
+                println!("No character is logged in");
+            }
+        }
+        Command::EsiUpdate {prices, cost_indices} =>
+        {
+            let client = EsiClient::new(settings);
+            let mut report = EsiUpdateReport::default();
+            if prices
+            {
+                report.adjusted_prices = Some(db.set_adjusted_prices(
+                    esi_import::adjusted_prices(client.get_pages("markets/prices/")?))?);
+            }
+            if cost_indices
+            {
+                report.solar_systems = Some(db.merge_cost_indices(
+                    esi_import::cost_indices(client.get_pages("industry/systems/")?))?);
+            }
+            if json
+            {
+                return print_json(&report);
+            }
+            if let Some(count) = report.adjusted_prices
+            {
+                println!("Imported {} adjusted prices", count);
+            }
+            if let Some(count) = report.solar_systems
+            {
+                println!("Imported cost indices of {} solar systems", count);
+            }
+        }
+        Command::SetCostIndex {solar_system, activity, index} =>
+        {
+            db.set_cost_index(solar_system, &activity, index)?;
+            if json
+            {
+                return print_json(&CostIndexRow {solar_system, activity: &activity,
+                    cost_index: index});
+            }
+            println!("Cost index of {} in solar system {}: {}", activity, solar_system, index);
+        }
+        Command::Profit {runs} =>
+        {
+            let ids = if runs.is_empty()
+            {
+                db.get_productionrun_vec()
+            }
+            else
+            {
+                find_runs(db, &runs)?
+            };
+            let mut rows = Vec::<ProfitRow>::new();
+            for id in ids.iter()
+            {
+                let pr = db.get_productionrun(*id)
+                    .ok_or_else(|| Error::UnknownProductionRun(id.to_string()))?;
+                rows.push(ProfitRow {run: &pr.name, id: *id, jobruns: pr.jobruns,
+                    units_produced: pr.get_units_produced(),
+                    profit: pr.get_profit(db, settings)?});
+            }
+            if json
+            {
+                return print_json(&rows);
+            }
+            print_table(&["Run", "Runs", "Produces", "Cost", "Sell value", "Raw profit", "Fees",
+                "Profit", "Duration", "Profit/h"], rows.iter()
+                .map(|r| match &r.profit
+                {
+                    Some(p) => vec![r.run.to_string(), r.jobruns.to_string(),
+                        format_products(&r.units_produced), isk(p.production_cost),
+                        isk(p.sell_value), isk(p.raw_profit), isk(p.fees), isk(p.taxed_profit),
+                        utils::format_duration(p.job_time), isk(p.profit_per_hour)],
+                    None => vec![r.run.to_string(), r.jobruns.to_string(),
+                        format_products(&r.units_produced), "impossible".to_string()],
+                })
+                .collect());
+        }
+        Command::ShoppingList {runs, location, all} =>
+        {
+            let list = db.shopping_list(&find_runs(db, &runs)?, &settings.fees,
+                location.as_deref())?;
+            let mut rows = Vec::<ShoppingRow>::new();
+            for entry in list.iter().filter(|e| all || e.to_buy > 0)
+            {
+                let item = db.get_item(&entry.item)
+                    .ok_or_else(|| Error::UnknownItem(entry.item.clone()))?;
+                let cost = db.purchase_cost(item, entry.to_buy, &settings.fees);
+                rows.push(ShoppingRow {item: &entry.item, quantity: entry.to_buy,
+                    needed: entry.needed, in_stock: entry.in_stock,
+                    buy_price: cost.average_price, total: cost.total, short: cost.short,
+                    order_book: cost.order_book});
+            }
+            if json
+            {
+                return print_json(&rows);
+            }
+            let mut headers = vec!["Item", "Needed", "In stock", "To buy", "Buy price", "Total"];
+            let depth = rows.iter().any(|r| r.order_book);
+            if depth
+            {
+                headers.push("Order book");
+            }
+            print_table(&headers, rows.iter()
+                .map(|r|
+                {
+                    let mut row = vec![r.item.to_string(), num(r.needed), num(r.in_stock),
+                        num(r.quantity), r.buy_price.map_or_else(String::new, isk),
+                        r.total.map_or_else(|| "unknown".to_string(), isk)];
+                    if depth
+                    {
+                        row.push(match (r.order_book, r.short)
+                        {
+                            (false, _) => String::new(),
+                            (true, 0) => "deep enough".to_string(),
+                            (true, short) =>
+                                format!("insufficient market depth, {} short", num(short)),
+                        });
+                    }
+                    row
+                })
+                .collect());
+        }
+        Command::ListStock {item, location} =>
+        {
+            let mut rows = Vec::<StockRow>::new();
+            for it in db.known_items.values().filter(|i| item.as_ref().is_none_or(|n| *n == i.name))
+            {
+                for (loc, quantity) in it.stock.iter()
+                    .filter(|(l, _)| location.as_ref().is_none_or(|wanted| wanted == *l))
+                {
+                    rows.push(StockRow {item: &it.name, location: loc, quantity: *quantity});
+                }
+            }
+            rows.sort_by(|a, b| a.item.cmp(b.item).then_with(|| a.location.cmp(b.location)));
+            if json
+            {
+                return print_json(&rows);
+            }
+            print_table(&["Item", "Location", "Quantity"], rows.iter()
+                .map(|r| vec![r.item.to_string(), r.location.to_string(), num(r.quantity)])
+                .collect());
+        }
+        Command::SetStock {item, location, change} =>
+        {
+            match change
+            {
+                StockChange::Set(quantity) => db.set_stock(&item, &location, quantity)?,
+                StockChange::Add(quantity) => db.add_stock(&item, &location, quantity)?,
+                StockChange::Remove(quantity) =>
+                    db.remove_stock(&item, Some(&location), quantity)?,
+            }
+            let quantity = db.stock_of(&item, Some(&location));
+            if json
+            {
+                return print_json(&StockRow {item: &item, location: &location, quantity});
+            }
+            println!("{}: {} at {}", item, num(quantity), location);
+        }
+        Command::ConsumeMaterials {run, location} =>
+        {
+            let id = db.find_productionrun(&run)?.id;
+            let materials = db.consume_materials(id, location.as_deref(), &settings.fees)?;
+            print_stock_change(json, "Consumed", &materials)?;
+        }
+        Command::AddProducts {run, location} =>
+        {
+            let id = db.find_productionrun(&run)?.id;
+            let products = db.add_products(id, &location)?;
+            print_stock_change(json, "Added", &products)?;
+        }
+    }
+    Ok(())
+}
+
+fn print_stock_change(json: bool, action: &str, items: &[(String, u64)]) -> Result<()>
+{
+    if json
+    {
+        return print_json(&items);
+    }
+    for (name, quantity) in items.iter()
+    {
+        println!("{} {} x {}", action, name, quantity.to_formatted_string(settings::locale()));
+    }
+    Ok(())
+}
+
+/// Resolves production run IDs or names to IDs.
+fn find_runs(db: &Database, runs: &[String]) -> Result<Vec<u64>>
+{
+    runs.iter().map(|run| db.find_productionrun(run).map(|pr| pr.id)).collect()
+}
+
+/// Formats a cost that is `None` when an invention can never succeed.
+fn impossible(cost: Option<Isk>) -> String
+{
+    cost.map_or_else(|| "impossible".to_string(), |c| c.to_string())
+}
+
+fn format_products(products: &[(String, u64)]) -> String
+{
+    products.iter().map(|p| format!("{} x {}", p.0, p.1)).collect::<Vec<_>>().join(", ")
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<()>
+{
+    let out = serde_json::to_string_pretty(value)
+        .map_err(|e| Error::Json("<stdout>".into(), e))?;
+    println!("{}", out);
+    Ok(())
+}
+
+/// Prints rows as a text table. The first column is left aligned, all others right aligned.
+fn print_table(headers: &[&str], rows: Vec<Vec<String>>)
+{
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows.iter()
+    {
+        for (i, cell) in row.iter().enumerate()
+        {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+
+    let format_row = |cells: Vec<&str>| -> String
+    {
+        let mut line = String::new();
+        for (i, cell) in cells.iter().enumerate()
+        {
+            if i == 0
+            {
+                line.push_str(&format!("{:<width$}", cell, width = widths[i]));
+            }
+            else
+            {
+                line.push_str(&format!("  {:>width$}", cell, width = widths[i]));
+            }
+        }
+        line
+    };
+
+    println!("{}", format_row(headers.to_vec()));
+    for row in rows.iter()
+    {
+        println!("{}", format_row(row.iter().map(|c| c.as_str()).collect()));
+    }
+}
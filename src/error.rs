@@ -0,0 +1,130 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use yaml_rust::ScanError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error
+{
+    Io(PathBuf, io::Error),
+    Yaml(PathBuf, ScanError),
+    EmptyYaml(PathBuf),
+    Json(PathBuf, serde_json::Error),
+    JsonLine(PathBuf, usize, serde_json::Error),
+    Sqlite(PathBuf, rusqlite::Error),
+    UnknownTypeId(i64),
+    MissingActivity(i64, &'static str),
+    MissingField(i64, &'static str),
+    UnknownBlueprint(String),
+    UnknownItem(String),
+    UnknownProductionRun(String),
+    DuplicateBlueprint(String),
+    DuplicateProductionRun(String),
+    MissingCostIndex(i64, String),
+    MissingAdjustedPrice(String),
+    NoInstallationCost(String),
+    NotInventable(String),
+    InvalidFacility(String, String),
+    NotResearchable(String),
+    OddTimeResearch(u8),
+    BlueprintCopy(String),
+    NotEnoughRuns(String, u64),
+    RunLimitExceeded(String, u64),
+    RunDone(String),
+    CopyLimitExceeded(String, u64),
+    NotEnoughStock(String, u64, u64),
+    MaterialsConsumed(String),
+    ProductsAdded(String),
+    BlueprintInUse(String, Vec<String>),
+    ItemInUse(String, Vec<String>),
+    ItemInStock(String, u64),
+    MarketLog(PathBuf, String),
+    Http(String, String),
+    Sso(String),
+}
+
+impl fmt::Display for Error
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            Error::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            Error::Yaml(path, e) => write!(f, "{}: invalid YAML: {}", path.display(), e),
+            Error::EmptyYaml(path) => write!(f, "{}: YAML file has no documents", path.display()),
+            Error::Json(path, e) => write!(f, "{}: invalid JSON: {}", path.display(), e),
+            Error::JsonLine(path, line, e) =>
+                write!(f, "{}:{}: invalid JSON: {}", path.display(), line, e),
+            Error::Sqlite(path, e) => write!(f, "{}: {}", path.display(), e),
+            Error::UnknownTypeId(id) => write!(f, "Type ID {} is not in the SDE", id),
+            Error::MissingActivity(id, activity) =>
+                write!(f, "Blueprint {} has no {} activity", id, activity),
+            Error::MissingField(id, field) =>
+                write!(f, "SDE entry {} is missing field '{}'", id, field),
+            Error::UnknownBlueprint(name) => write!(f, "Unknown blueprint: {}", name),
+            Error::UnknownItem(name) => write!(f, "Unknown item: {}", name),
+            Error::UnknownProductionRun(name) => write!(f, "Unknown production run: {}", name),
+            Error::DuplicateBlueprint(name) => write!(f, "Blueprint {} is already known", name),
+            Error::DuplicateProductionRun(name) =>
+                write!(f, "Production run {} already exists", name),
+            Error::MissingCostIndex(system, activity) =>
+                write!(f, "No {} cost index known for solar system {}", activity, system),
+            Error::MissingAdjustedPrice(name) => write!(f, "No adjusted price known for {}", name),
+            Error::NoInstallationCost(name) => write!(f, "Production run {} has neither a solar \
+                system nor a manual installation cost", name),
+            Error::NotInventable(name) =>
+                write!(f, "{} cannot be invented from any blueprint", name),
+            Error::NotResearchable(name) =>
+                write!(f, "{} is a reaction formula and cannot be researched", name),
+            Error::OddTimeResearch(te) =>
+                write!(f, "TE {} is odd, time research goes in steps of 2", te),
+            Error::BlueprintCopy(name) => write!(f, "{} is a blueprint copy, only originals \
+                can be researched and copied", name),
+            Error::NotEnoughRuns(name, runs) =>
+                write!(f, "Blueprint copy {} has only {} runs available", name, runs),
+            Error::RunLimitExceeded(name, limit) =>
+                write!(f, "{} allows at most {} runs", name, limit),
+            Error::CopyLimitExceeded(name, limit) =>
+                write!(f, "A copy job of {} makes at most {} copies", name, limit),
+            Error::RunDone(name) => write!(f, "Production run {} is done, set it back to \
+                planned or running to change its runs", name),
+            Error::NotEnoughStock(name, available, needed) =>
+                write!(f, "Not enough {} in stock: {} of {}", name, available, needed),
+            Error::MaterialsConsumed(name) =>
+                write!(f, "Materials of production run {} were already consumed", name),
+            Error::ProductsAdded(name) =>
+                write!(f, "Products of production run {} were already added", name),
+            Error::InvalidFacility(name, msg) =>
+                write!(f, "Invalid facility for {}: {}", name, msg),
+            Error::BlueprintInUse(name, runs) => write!(f, "Blueprint {} is used by production \
+                run(s) {}", name, runs.join(", ")),
+            Error::ItemInUse(name, users) =>
+                write!(f, "Item {} is used by {}", name, users.join(", ")),
+            Error::ItemInStock(name, quantity) =>
+                write!(f, "Item {} still has {} in stock", name, quantity),
+            Error::MarketLog(path, msg) =>
+                write!(f, "{}: invalid market export: {}", path.display(), msg),
+            Error::Http(url, msg) => write!(f, "{}: {}", url, msg),
+            Error::Sso(msg) => write!(f, "EVE SSO: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)>
+    {
+        match self
+        {
+            Error::Io(_, e) => Some(e),
+            Error::Yaml(_, e) => Some(e),
+            Error::Json(_, e) => Some(e),
+            Error::JsonLine(_, _, e) => Some(e),
+            Error::Sqlite(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,540 @@
+use std::cell::Cell;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use chacha20poly1305::aead::Aead;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::error::{Error, Result};
+use crate::settings::Settings;
+use crate::utils;
+
+pub const DEFAULT_BASE_URL: &str = "https://esi.evetech.net/latest";
+pub const DEFAULT_SSO_URL: &str = "https://login.eveonline.com";
+pub const DEFAULT_CALLBACK_URL: &str = "http://localhost/callback";
+/// Scopes requested at login, enough for `import-esi`.
+pub const SCOPES: &str = "esi-characters.read_blueprints.v1 esi-assets.read_assets.v1";
+
+/// Once fewer errors than this remain in the ESI error limit window, requests wait for the
+/// window to reset.
+const ERROR_LIMIT_MARGIN: u32 = 10;
+/// Retries of a request that failed with a server or connection error. The delay starts at
+/// one second and doubles with every retry.
+const MAX_RETRIES: u32 = 3;
+/// Access tokens are refreshed this many seconds before they expire.
+const TOKEN_EXPIRY_MARGIN: u64 = 60;
+
+/// Default name of the token encryption key file.
+pub const KEY_FILE: &str = "esi.key";
+const TOKEN_FILE: &str = "esi_token.bin";
+const CACHE_DIR: &str = "esi_cache";
+const NONCE_LEN: usize = 12;
+
+/// SSO tokens of the logged in character.
+#[derive(Serialize, Deserialize)]
+pub struct Token
+{
+    pub character_id: i64,
+    pub character_name: String,
+    access_token: String,
+    refresh_token: String,
+    /// Unix time the access token expires.
+    expires_at: u64,
+}
+
+/// A started login waiting for the browser to be redirected to the callback URL.
+pub struct LoginRequest
+{
+    /// Authorization URL to open in the browser.
+    pub url: String,
+    verifier: String,
+    state: String,
+}
+
+/// Cached response of one page of an ESI endpoint.
+#[derive(Serialize, Deserialize)]
+struct CacheEntry
+{
+    etag: Option<String>,
+    /// Unix time from the `Expires` header, until which the response is not requested again.
+    expires: u64,
+    pages: u32,
+    body: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse
+{
+    access_token: String,
+    expires_in: u64,
+    refresh_token: String,
+}
+
+#[derive(Deserialize)]
+struct JwtClaims
+{
+    /// `CHARACTER:EVE:<character ID>`
+    sub: String,
+    name: String,
+}
+
+/// Client for ESI and EVE SSO. Tokens are kept in the data directory, encrypted with a
+/// random key kept outside of it, and responses are cached there until they expire.
+/// The encryption only protects copies of the data directory, not a user who can read
+/// the key as well.
+pub struct EsiClient
+{
+    base_url: String,
+    sso_url: String,
+    client_id: Option<String>,
+    callback_url: String,
+    data_dir: PathBuf,
+    key_path: PathBuf,
+    agent: ureq::Agent,
+    /// Unix time until which requests wait for the error limit window to reset.
+    paused_until: Cell<u64>,
+}
+
+impl EsiClient
+{
+    pub fn new(settings: &Settings) -> EsiClient
+    {
+        EsiClient {base_url: settings.esi_base_url.trim_end_matches('/').to_string(),
+            sso_url: settings.sso_base_url.trim_end_matches('/').to_string(),
+            client_id: settings.esi_client_id.clone(),
+            callback_url: settings.esi_callback_url.clone(),
+            data_dir: settings.data_dir.clone(),
+            key_path: settings.esi_key_path.clone(),
+            agent: ureq::AgentBuilder::new()
+                .user_agent(concat!("eve_industry_terminal/", env!("CARGO_PKG_VERSION")))
+                .build(),
+            paused_until: Cell::new(0)}
+    }
+
+    /// Replaces the configured ESI base URL.
+    pub fn set_base_url(&mut self, url: &str)
+    {
+        self.base_url = url.trim_end_matches('/').to_string();
+    }
+
+    /// Starts an SSO login with PKCE. The browser is redirected to the callback URL after
+    /// the character was authorized, and that URL is passed to `finish_login`.
+    pub fn login_request(&self) -> Result<LoginRequest>
+    {
+        let client_id = self.client_id()?;
+        let verifier = URL_SAFE_NO_PAD.encode(random_bytes::<32>()?);
+        let state = URL_SAFE_NO_PAD.encode(random_bytes::<16>()?);
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        let url = Url::parse_with_params(&format!("{}/v2/oauth/authorize/", self.sso_url),
+            &[("response_type", "code"), ("redirect_uri", &self.callback_url),
+                ("client_id", client_id), ("scope", SCOPES), ("code_challenge", &challenge),
+                ("code_challenge_method", "S256"), ("state", &state)])
+            .map_err(|e| Error::Sso(format!("invalid SSO URL {}: {}", self.sso_url, e)))?;
+        Ok(LoginRequest {url: url.into(), verifier, state})
+    }
+
+    /// Exchanges the authorization code of the `redirect` URL for tokens and stores them.
+    pub fn finish_login(&self, request: &LoginRequest, redirect: &str) -> Result<Token>
+    {
+        let redirect = Url::parse(redirect.trim())
+            .map_err(|e| Error::Sso(format!("invalid callback URL: {}", e)))?;
+        let param = |name: &str| redirect.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned());
+        if param("state").as_deref() != Some(request.state.as_str())
+        {
+            return Err(Error::Sso("the callback URL belongs to a different login".to_string()));
+        }
+        let code = param("code")
+            .ok_or_else(|| Error::Sso("the callback URL has no authorization code".to_string()))?;
+
+        let token = self.request_token(&[("grant_type", "authorization_code"), ("code", &code),
+            ("client_id", self.client_id()?), ("code_verifier", &request.verifier)])?;
+        self.save_token(&token)?;
+        Ok(token)
+    }
+
+    /// The stored token, if a character is logged in.
+    pub fn token(&self) -> Result<Option<Token>>
+    {
+        let path = self.data_dir.join(TOKEN_FILE);
+        if !path.exists()
+        {
+            return Ok(None);
+        }
+        let data = fs::read(&path).map_err(|e| Error::Io(path.clone(), e))?;
+        let cipher = ChaCha20Poly1305::new(&self.key()?);
+        let plain = (data.len() > NONCE_LEN).then(|| data.split_at(NONCE_LEN))
+            .and_then(|(nonce, encrypted)| cipher.decrypt(Nonce::from_slice(nonce), encrypted)
+                .ok())
+            .ok_or_else(|| Error::Sso(format!("cannot decrypt {}, log in again",
+                path.display())))?;
+        serde_json::from_slice(&plain).map(Some).map_err(|e| Error::Json(path, e))
+    }
+
+    /// Deletes the stored token. Returns false if no character was logged in.
+    pub fn logout(&self) -> Result<bool>
+    {
+        let path = self.data_dir.join(TOKEN_FILE);
+        if !path.exists()
+        {
+            return Ok(false);
+        }
+        fs::remove_file(&path).map_err(|e| Error::Io(path, e))?;
+        Ok(true)
+    }
+
+    /// Fetches all pages of a public `endpoint` below the base URL, e.g. `markets/prices/`.
+    pub fn get_pages<T: DeserializeOwned>(&self, endpoint: &str) -> Result<Vec<T>>
+    {
+        self.fetch_pages(endpoint, None)
+    }
+
+    /// Like `get_pages`, with requests authorized by the logged in character's token if there
+    /// is one.
+    pub fn get_authorized_pages<T: DeserializeOwned>(&self, endpoint: &str) -> Result<Vec<T>>
+    {
+        self.fetch_pages(endpoint, self.access_token()?)
+    }
+
+    fn fetch_pages<T: DeserializeOwned>(&self, endpoint: &str, access_token: Option<String>)
+        -> Result<Vec<T>>
+    {
+        let url = format!("{}/{}", self.base_url, endpoint);
+        let mut ret = Vec::<T>::new();
+        let mut page = 1;
+        loop
+        {
+            let (body, pages) = self.get(&url, page, access_token.as_deref())?;
+            let entries: Vec<T> = serde_json::from_str(&body)
+                .map_err(|e| Error::Http(url.clone(), format!("invalid response: {}", e)))?;
+            ret.extend(entries);
+            if page >= pages
+            {
+                return Ok(ret);
+            }
+            page += 1;
+        }
+    }
+
+    /// Body and page count of one page, from the cache while it has not expired.
+    fn get(&self, url: &str, page: u32, access_token: Option<&str>) -> Result<(String, u32)>
+    {
+        let cache_path = self.cache_path(url, page);
+        let cached: Option<CacheEntry> = utils::read_optional_json(&cache_path)?;
+        if let Some(entry) = cached.as_ref().filter(|entry| entry.expires > now())
+        {
+            return Ok((entry.body.clone(), entry.pages));
+        }
+
+        let mut retries = 0;
+        let response = loop
+        {
+            self.wait_for_error_limit();
+            let mut request = self.agent.get(url).query("page", &page.to_string());
+            if let Some(token) = access_token
+            {
+                request = request.set("Authorization", &format!("Bearer {}", token));
+            }
+            if let Some(etag) = cached.as_ref().and_then(|entry| entry.etag.as_ref())
+            {
+                request = request.set("If-None-Match", etag);
+            }
+
+            let (delay, error) = match request.call()
+            {
+                Ok(response) =>
+                {
+                    self.update_error_limit(&response);
+                    break response;
+                }
+                Err(ureq::Error::Status(code, response)) =>
+                {
+                    self.update_error_limit(&response);
+                    let delay = match code
+                    {
+                        // Error limited: the window reset was already waited for above
+                        420 => Some(0),
+                        429 => response.header("Retry-After").and_then(|s| s.parse().ok()),
+                        500 | 502 | 503 | 504 => Some(1 << retries),
+                        _ => None,
+                    };
+                    (delay, http_error(url, ureq::Error::Status(code, response)))
+                }
+                Err(e) => (Some(1 << retries), http_error(url, e)),
+            };
+            match delay
+            {
+                Some(seconds) if retries < MAX_RETRIES =>
+                {
+                    retries += 1;
+                    thread::sleep(Duration::from_secs(seconds));
+                }
+                _ => return Err(error),
+            }
+        };
+
+        let expires = response.header("Expires").and_then(parse_http_date).unwrap_or(0);
+        if response.status() == 304
+        {
+            // Only requests with the ETag of a cached response can be answered this way
+            let mut entry = cached.ok_or_else(|| Error::Http(url.to_string(),
+                "304 Not Modified without a cached response".to_string()))?;
+            entry.expires = expires;
+            utils::write_json(&cache_path, &entry)?;
+            return Ok((entry.body, entry.pages));
+        }
+        let etag = response.header("ETag").map(str::to_string);
+        // Endpoints without pagination do not send the header
+        let pages = response.header("X-Pages").and_then(|p| p.parse().ok()).unwrap_or(1);
+        let body = response.into_string()
+            .map_err(|e| Error::Http(url.to_string(), e.to_string()))?;
+        if etag.is_some() || expires > now()
+        {
+            let dir = self.data_dir.join(CACHE_DIR);
+            fs::create_dir_all(&dir).map_err(|e| Error::Io(dir, e))?;
+            utils::write_json(&cache_path, &CacheEntry {etag, expires, pages,
+                body: body.clone()})?;
+        }
+        Ok((body, pages))
+    }
+
+    /// Access token of the logged in character, refreshed if it is about to expire.
+    fn access_token(&self) -> Result<Option<String>>
+    {
+        let token = match self.token()?
+        {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+        if token.expires_at > now() + TOKEN_EXPIRY_MARGIN
+        {
+            return Ok(Some(token.access_token));
+        }
+        let refreshed = self.request_token(&[("grant_type", "refresh_token"),
+            ("refresh_token", &token.refresh_token), ("client_id", self.client_id()?)])?;
+        self.save_token(&refreshed)?;
+        Ok(Some(refreshed.access_token))
+    }
+
+    fn request_token(&self, form: &[(&str, &str)]) -> Result<Token>
+    {
+        let url = format!("{}/v2/oauth/token", self.sso_url);
+        let response: TokenResponse = self.agent.post(&url).send_form(form)
+            .map_err(|e| http_error(&url, e))?
+            .into_json()
+            .map_err(|e| Error::Http(url.clone(), format!("invalid token response: {}", e)))?;
+
+        // The token comes straight from the SSO over TLS, so its claims are read without
+        // verifying the signature
+        let claims = response.access_token.split('.').nth(1)
+            .and_then(|payload| URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok())
+            .and_then(|payload| serde_json::from_slice::<JwtClaims>(&payload).ok())
+            .ok_or_else(|| Error::Sso("the access token is not a valid JWT".to_string()))?;
+        let character_id = claims.sub.rsplit(':').next().and_then(|id| id.parse().ok())
+            .ok_or_else(|| Error::Sso(format!("unexpected token subject '{}'", claims.sub)))?;
+
+        Ok(Token {character_id, character_name: claims.name,
+            access_token: response.access_token, refresh_token: response.refresh_token,
+            expires_at: now() + response.expires_in})
+    }
+
+    fn save_token(&self, token: &Token) -> Result<()>
+    {
+        let path = self.data_dir.join(TOKEN_FILE);
+        let plain = serde_json::to_vec(token).map_err(|e| Error::Json(path.clone(), e))?;
+        let nonce = random_bytes::<NONCE_LEN>()?;
+        let encrypted = ChaCha20Poly1305::new(&self.key()?)
+            .encrypt(Nonce::from_slice(&nonce), plain.as_slice())
+            .map_err(|_| Error::Sso("cannot encrypt the token".to_string()))?;
+        write_private(&path, &[nonce.as_slice(), encrypted.as_slice()].concat())
+    }
+
+    /// Token encryption key, created on first use. A key left in the data directory by
+    /// older versions is moved to the key path.
+    fn key(&self) -> Result<Key>
+    {
+        let path = &self.key_path;
+        if !path.exists()
+        {
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty())
+            {
+                fs::create_dir_all(dir).map_err(|e| Error::Io(dir.to_path_buf(), e))?;
+            }
+            let old_path = self.data_dir.join(KEY_FILE);
+            match fs::read(&old_path)
+            {
+                Ok(key) =>
+                {
+                    write_private(path, &key)?;
+                    fs::remove_file(&old_path).map_err(|e| Error::Io(old_path.clone(), e))?;
+                }
+                Err(_) => write_private(path, &random_bytes::<32>()?)?,
+            }
+        }
+        let key = fs::read(path).map_err(|e| Error::Io(path.clone(), e))?;
+        if key.len() != 32
+        {
+            return Err(Error::Sso(format!("{} is not a 32 byte key", path.display())));
+        }
+        Ok(*Key::from_slice(&key))
+    }
+
+    fn client_id(&self) -> Result<&str>
+    {
+        self.client_id.as_deref()
+            .ok_or_else(|| Error::Sso("esi_client_id is not set in the config file".to_string()))
+    }
+
+    fn cache_path(&self, url: &str, page: u32) -> PathBuf
+    {
+        let hash = Sha256::digest(format!("{}?page={}", url, page).as_bytes());
+        let name: String = hash.iter().map(|b| format!("{:02x}", b)).collect();
+        self.data_dir.join(CACHE_DIR).join(format!("{}.json", name))
+    }
+
+    fn update_error_limit(&self, response: &ureq::Response)
+    {
+        let header = |name| response.header(name).and_then(|s| s.parse::<u64>().ok());
+        if let (Some(remain), Some(reset)) =
+            (header("X-ESI-Error-Limit-Remain"), header("X-ESI-Error-Limit-Reset"))
+        {
+            if remain < ERROR_LIMIT_MARGIN as u64 || response.status() == 420
+            {
+                self.paused_until.set(now() + reset);
+            }
+        }
+    }
+
+    fn wait_for_error_limit(&self)
+    {
+        let seconds = self.paused_until.get().saturating_sub(now());
+        if seconds > 0
+        {
+            eprintln!("Waiting {}s for the ESI error limit to reset", seconds);
+            thread::sleep(Duration::from_secs(seconds));
+        }
+    }
+}
+
+fn http_error(url: &str, error: ureq::Error) -> Error
+{
+    let message = match error
+    {
+        ureq::Error::Status(code, response) =>
+        {
+            let status = format!("{} {}", code, response.status_text());
+            // ESI and the SSO explain errors in the body
+            match response.into_json::<serde_json::Value>().ok()
+                .and_then(|body| body.get("error").and_then(|e| e.as_str()).map(str::to_string))
+            {
+                Some(reason) => format!("{}: {}", status, reason),
+                None => status,
+            }
+        }
+        ureq::Error::Transport(transport) => match transport.message()
+        {
+            Some(message) => format!("{}: {}", transport.kind(), message),
+            None => transport.kind().to_string(),
+        },
+    };
+    Error::Http(url.to_string(), message)
+}
+
+/// Writes a file only the user can read. Permissions of an existing file are tightened
+/// as well, since the mode only applies to newly created files.
+fn write_private(path: &Path, data: &[u8]) -> Result<()>
+{
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).map_err(|e| Error::Io(path.to_path_buf(), e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .map_err(|e| Error::Io(path.to_path_buf(), e))?;
+    }
+    file.write_all(data).map_err(|e| Error::Io(path.to_path_buf(), e))
+}
+
+fn random_bytes<const N: usize>() -> Result<[u8; N]>
+{
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|e| Error::Sso(format!("no random numbers: {}", e)))?;
+    Ok(bytes)
+}
+
+fn now() -> u64
+{
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Parses an HTTP date like `Sun, 06 Nov 1994 08:49:37 GMT` into Unix time.
+fn parse_http_date(date: &str) -> Option<u64>
+{
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep",
+        "Oct", "Nov", "Dec"];
+    let parts: Vec<&str> = date.split_whitespace().collect();
+    if parts.len() != 6 || parts[5] != "GMT"
+    {
+        return None;
+    }
+    let day: i64 = parts[1].parse().ok()?;
+    let month = MONTHS.iter().position(|m| *m == parts[2])? as i64 + 1;
+    let year: i64 = parts[3].parse().ok()?;
+    let time: Vec<i64> = parts[4].split(':').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    if time.len() != 3
+    {
+        return None;
+    }
+
+    // Days since 1970-01-01 of the proleptic Gregorian calendar, with years starting in March
+    let (y, m) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * m + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    let seconds = days * 86400 + time[0] * 3600 + time[1] * 60 + time[2];
+    (seconds >= 0).then_some(seconds as u64)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn parses_http_dates()
+    {
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(784_111_777));
+        assert_eq!(parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(0));
+        assert_eq!(parse_http_date("Tue, 29 Feb 2000 00:00:00 GMT"), Some(951_782_400));
+        assert_eq!(parse_http_date("Wed, 01 Mar 2000 12:00:00 GMT"), Some(951_912_000));
+    }
+
+    #[test]
+    fn rejects_other_date_formats()
+    {
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 CET"), None);
+        assert_eq!(parse_http_date("Sun, 06 Foo 1994 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49 GMT"), None);
+        assert_eq!(parse_http_date("Wed, 31 Dec 1969 23:59:59 GMT"), None);
+    }
+}
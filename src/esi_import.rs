@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::error::Result;
+use crate::esi::EsiClient;
+use crate::isk::Isk;
+use crate::orderbook::OrderBook;
+use crate::utils;
+
+/// One entry of the ESI `/markets/prices/` response.
+#[derive(Deserialize)]
+pub struct MarketPrice
+{
+    type_id: i64,
+    adjusted_price: Option<f64>,
+}
+
+/// One entry of the ESI `/industry/systems/` response.
+#[derive(Deserialize)]
+pub struct IndustrySystem
+{
+    solar_system_id: i64,
+    cost_indices: Vec<CostIndex>,
+}
+
+#[derive(Deserialize)]
+struct CostIndex
+{
+    activity: String,
+    cost_index: f64,
+}
+
+/// One entry of the ESI `/markets/{region_id}/orders/` response.
+#[derive(Deserialize)]
+pub struct MarketOrder
+{
+    type_id: i64,
+    location_id: i64,
+    is_buy_order: bool,
+    price: f64,
+    volume_remain: u64,
+}
+
+/// One entry of the ESI `/characters/{id}/blueprints/` response.
+#[derive(Deserialize, Clone, Debug)]
+pub struct EsiBlueprint
+{
+    pub type_id: i64,
+    pub material_efficiency: u8,
+    pub time_efficiency: u8,
+    /// -1 for a single original, -2 for a copy, or the size of a stack of originals.
+    pub quantity: i64,
+    /// Runs left on a copy, -1 for originals.
+    pub runs: i64,
+}
+
+/// One entry of the ESI `/characters/{id}/assets/` response.
+#[derive(Deserialize, Clone, Debug)]
+pub struct EsiAsset
+{
+    pub item_id: i64,
+    pub type_id: i64,
+    /// Station or structure, or the item ID of the container the asset is in.
+    pub location_id: i64,
+    /// `station`, `solar_system`, `item` or `other`.
+    pub location_type: String,
+    pub quantity: u64,
+}
+
+impl EsiBlueprint
+{
+    pub fn is_copy(&self) -> bool
+    {
+        self.quantity == -2
+    }
+}
+
+/// Reads the order books by type ID from a saved `/markets/{region_id}/orders/` response,
+/// optionally only with the orders at station or structure `location`.
+pub fn read_order_books(path: &Path, location: Option<i64>) -> Result<HashMap<i64, OrderBook>>
+{
+    Ok(order_books(utils::read_json(path)?, location))
+}
+
+pub fn order_books(orders: Vec<MarketOrder>, location: Option<i64>)
+    -> HashMap<i64, OrderBook>
+{
+    let mut by_type = HashMap::<i64, Vec<(Isk, u64, bool)>>::new();
+    for order in orders.into_iter().filter(|o| location.is_none_or(|l| l == o.location_id))
+    {
+        by_type.entry(order.type_id).or_default()
+            .push((Isk::from_f64(order.price), order.volume_remain, order.is_buy_order));
+    }
+    by_type.into_iter().map(|(type_id, orders)| (type_id, OrderBook::new(orders))).collect()
+}
+
+/// Fetches the order books of `type_ids` in a region. Types without orders get an empty book.
+pub fn fetch_order_books(client: &EsiClient, region: i64, type_ids: &[i64],
+    location: Option<i64>) -> Result<HashMap<i64, OrderBook>>
+{
+    let mut orders = Vec::<MarketOrder>::new();
+    for type_id in type_ids.iter()
+    {
+        orders.extend(client.get_pages::<MarketOrder>(
+            &format!("markets/{}/orders/?order_type=all&type_id={}", region, type_id))?);
+    }
+    let mut books = order_books(orders, location);
+    for type_id in type_ids.iter()
+    {
+        books.entry(*type_id).or_default();
+    }
+    Ok(books)
+}
+
+/// Reads a saved `/characters/{id}/blueprints/` response.
+pub fn read_blueprints(path: &Path) -> Result<Vec<EsiBlueprint>>
+{
+    utils::read_json(path)
+}
+
+/// Reads a saved `/characters/{id}/assets/` response.
+pub fn read_assets(path: &Path) -> Result<Vec<EsiAsset>>
+{
+    utils::read_json(path)
+}
+
+/// Station or structure an asset is in, following containers up to the outermost one.
+pub fn root_location(asset: &EsiAsset, by_item_id: &HashMap<i64, &EsiAsset>) -> i64
+{
+    let mut current = asset;
+    // Bounded in case of broken data with a container cycle
+    for _ in 0..by_item_id.len()
+    {
+        match by_item_id.get(&current.location_id)
+        {
+            Some(parent) if current.location_type == "item" => current = parent,
+            _ => break,
+        }
+    }
+    current.location_id
+}
+
+/// Reads adjusted prices by type ID from a saved `/markets/prices/` response.
+pub fn read_adjusted_prices(path: &Path) -> Result<HashMap<i64, Isk>>
+{
+    Ok(adjusted_prices(utils::read_json(path)?))
+}
+
+/// Adjusted prices by type ID of the types that have one.
+pub fn adjusted_prices(prices: Vec<MarketPrice>) -> HashMap<i64, Isk>
+{
+    prices.into_iter()
+        .filter_map(|p| p.adjusted_price.map(|price| (p.type_id, Isk::from_f64(price))))
+        .collect()
+}
+
+/// Reads cost indices by solar system and activity from a saved `/industry/systems/` response.
+pub fn read_cost_indices(path: &Path) -> Result<HashMap<i64, HashMap<String, f64>>>
+{
+    Ok(cost_indices(utils::read_json(path)?))
+}
+
+/// Cost indices by solar system and activity.
+pub fn cost_indices(systems: Vec<IndustrySystem>) -> HashMap<i64, HashMap<String, f64>>
+{
+    systems.into_iter()
+        .map(|s| (s.solar_system_id,
+            s.cost_indices.into_iter().map(|c| (c.activity, c.cost_index)).collect()))
+        .collect()
+}
@@ -1,43 +1,170 @@
 use std::collections::HashMap;
 use std::fmt;
-use std::io::BufReader;
-use std::fs::{self, File};
-use std::path::Path;
-use std::convert::TryInto;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use yaml_rust::Yaml;
 use serde::{Serialize, Deserialize};
-use num_format::{Locale, ToFormattedString};
-
+use serde::de::DeserializeOwned;
+
+use crate::error::{Error, Result};
+use crate::esi_import::{self, EsiAsset, EsiBlueprint};
+use crate::fees::{BuyMode, FeeModel};
+use crate::marketlogs::{self, PricePoint};
+use crate::orderbook::{OrderBook, PurchaseCost};
+use crate::sde::{Activity, Sde};
+use crate::sde_source::SdeSource;
+use crate::search::{SearchFilter, SearchHit, SearchIndex};
+use crate::buildtree::{self, BuildChoice, BuildNode};
+use crate::industry::{self, Facility, JobActivity};
+use crate::invention::{Decryptor, Invention};
+use crate::settings::Settings;
+use crate::isk::Isk;
 use crate::utils;
 
+/// Location of stock when none is given.
+pub const DEFAULT_LOCATION: &str = "hangar";
+/// Largest quantity or number of runs accepted as input, far enough below the `u64` range
+/// that totals in ISK stay exact.
+pub const MAX_QUANTITY: u64 = 1_000_000_000_000;
+
 pub struct Database
 {
-    data_base_dir: &'static str,
-    pub blueprints:  Yaml,
-    pub type_ids:  Yaml,
-    pub known_blueprints: HashMap<String, T1Blueprint>,
+    data_base_dir: PathBuf,
+    pub sde: Sde,
+    search_index: SearchIndex,
+    /// Blueprint and activity producing each type ID.
+    producers: HashMap<i64, (i64, JobActivity)>,
+    pub known_blueprints: HashMap<String, Blueprint>,
     pub known_items: HashMap<String, Item>,
-    pub productionruns: HashMap<String, T1ProductionRun>,
+    pub productionruns: HashMap<u64, ProductionRun>,
+    /// Highest production run ID given out so far, kept so IDs of deleted runs are not reused.
+    last_productionrun_id: u64,
+    /// Adjusted prices by type ID as published by ESI.
+    pub adjusted_prices: HashMap<i64, Isk>,
+    /// Cost indices by solar system ID and industry activity.
+    pub cost_indices: HashMap<i64, HashMap<String, f64>>,
+    /// Latest order book snapshot by type ID.
+    pub order_books: HashMap<i64, OrderBook>,
 }
 
 #[allow(dead_code)]
 impl Database
 {
-    pub fn new(data_base_dir: &'static str) -> Database
+    pub fn new(data_base_dir: PathBuf, sde_source: &dyn SdeSource) -> Result<Database>
+    {
+        eprintln!("Loading resources");
+        let sde = Sde::load(sde_source, &data_base_dir.join("cache"))?;
+        let search_index = SearchIndex::new(&sde);
+        let producers = buildtree::producer_index(&sde);
+        for dir in ["blueprints", "items", "productionruns"].iter()
+        {
+            let path = data_base_dir.join(dir);
+            fs::create_dir_all(&path).map_err(|e| Error::Io(path, e))?;
+        }
+        let resources = load_resources(&data_base_dir)?;
+        resources.report_skipped();
+
+        let adjusted_prices = utils::read_optional_json(&data_base_dir.join("adjusted_prices.json"))?
+            .unwrap_or_default();
+        let cost_indices = utils::read_optional_json(&data_base_dir.join("cost_indices.json"))?
+            .unwrap_or_default();
+        let order_books = utils::read_optional_json(&data_base_dir.join("order_books.json"))?
+            .unwrap_or_default();
+        let last_productionrun_id =
+            utils::read_optional_json(&data_base_dir.join("last_run_id.json"))?.unwrap_or(0);
+
+        // Runs saved before they had IDs are keyed by their file until they get one
+        let mut legacy_runs = Vec::<(PathBuf, ProductionRun)>::new();
+        let mut productionruns = HashMap::<u64, ProductionRun>::new();
+        for (path, pr) in resources.productionruns
+        {
+            if pr.id == 0
+            {
+                legacy_runs.push((path, pr));
+            }
+            else
+            {
+                productionruns.insert(pr.id, pr);
+            }
+        }
+
+        let mut db = Database {data_base_dir, sde, search_index, producers,
+            known_blueprints: resources.known_blueprints,
+            known_items: resources.known_items,
+            productionruns, last_productionrun_id, adjusted_prices, cost_indices, order_books};
+        db.migrate_saved_data(legacy_runs)?;
+        Ok(db)
+    }
+
+    /// A database of only the given SDE, without a data directory.
+    #[cfg(test)]
+    pub fn from_sde(sde: Sde) -> Database
+    {
+        Database {data_base_dir: PathBuf::new(), search_index: SearchIndex::new(&sde),
+            producers: buildtree::producer_index(&sde), sde, known_blueprints: HashMap::new(),
+            known_items: HashMap::new(), productionruns: HashMap::new(), last_productionrun_id: 0,
+            adjusted_prices: HashMap::new(), cost_indices: HashMap::new(),
+            order_books: HashMap::new()}
+    }
+
+    /// Fills in SDE derived fields of blueprints and production runs saved before
+    /// those fields were recorded, and gives `legacy_runs` an ID and a name.
+    fn migrate_saved_data(&mut self, legacy_runs: Vec<(PathBuf, ProductionRun)>) -> Result<()>
     {
-        println!("Loading resources");
-        let blueprints = utils::load_yaml(&format!("{}/sde/fsd/blueprints.yaml",data_base_dir))
-            .remove(0);
-        let type_ids = utils::load_yaml(&format!("{}/sde/fsd/typeIDs.yaml",data_base_dir))
-            .remove(0);
-        let(known_blueprints, known_items, productionruns) = load_resources(data_base_dir);
+        let outdated: Vec<(String, i64, JobActivity)> = self.known_blueprints.values()
+            .filter(|bp| bp.products.is_empty() || bp.base_time == 0
+                || bp.max_production_limit == 0)
+            .map(|bp| (bp.name.clone(), bp.bp_id, bp.activity))
+            .collect();
+        for (bp_name, bp_id, activity) in outdated
+        {
+            let mut products = Vec::<(String, u64)>::new();
+            for (id, quantity) in self.sde_products(bp_id, activity)?
+            {
+                products.push((self.ensure_item(id, true)?, quantity));
+            }
+            let base_time = self.sde_activity_time(bp_id, activity.sde_name())?;
+            let max_production_limit = self.sde_max_production_limit(bp_id)?;
+            if let Some(bp) = self.known_blueprints.get_mut(&bp_name)
+            {
+                bp.products = products;
+                bp.base_time = base_time;
+                bp.max_production_limit = max_production_limit;
+            }
+            self.save_blueprint(&bp_name)?;
+        }
+
+        for (path, mut pr) in legacy_runs
+        {
+            pr.id = self.next_productionrun_id();
+            if pr.name.is_empty()
+            {
+                pr.name = self.default_productionrun_name(&pr.blueprint, pr.id);
+            }
+            self.insert_productionrun(pr)?;
+            fs::remove_file(&path).map_err(|e| Error::Io(path, e))?;
+        }
 
-        Database {data_base_dir, blueprints, type_ids, known_blueprints,
-            known_items, productionruns}
+        let outdated: Vec<u64> = self.productionruns.values()
+            .filter(|pr| pr.products.is_empty())
+            .map(|pr| pr.id)
+            .collect();
+        for id in outdated
+        {
+            let bp_name = self.productionruns[&id].blueprint.clone();
+            let products = self.get_blueprint(&bp_name)
+                .ok_or(Error::UnknownBlueprint(bp_name))?.products.clone();
+            if let Some(pr) = self.productionruns.get_mut(&id)
+            {
+                pr.products = products;
+            }
+            self.save_productionrun(id)?;
+        }
+        Ok(())
     }
 
-    pub fn get_blueprint(&self, name: &str) -> Option<&T1Blueprint>
+    pub fn get_blueprint(&self, name: &str) -> Option<&Blueprint>
     {
         self.known_blueprints.get(name)
     }
@@ -47,9 +174,17 @@ impl Database
         self.known_items.get(name)
     }
 
-    pub fn get_productionrun(&self, name: &str) -> Option<&T1ProductionRun>
+    pub fn get_productionrun(&self, id: u64) -> Option<&ProductionRun>
     {
-        self.productionruns.get(name)
+        self.productionruns.get(&id)
+    }
+
+    /// Looks up a production run by its ID or name.
+    pub fn find_productionrun(&self, id_or_name: &str) -> Result<&ProductionRun>
+    {
+        id_or_name.parse::<u64>().ok().and_then(|id| self.productionruns.get(&id))
+            .or_else(|| self.productionruns.values().find(|pr| pr.name == id_or_name))
+            .ok_or_else(|| Error::UnknownProductionRun(id_or_name.to_string()))
     }
 
     pub fn get_blueprint_vec(&self) -> Vec<String>
@@ -63,357 +198,1821 @@ impl Database
         ret
     }
 
-    pub fn get_productionrun_vec(&self) -> Vec<String>
+    /// IDs of all production runs in ascending order.
+    pub fn get_productionrun_vec(&self) -> Vec<u64>
     {
-        let mut ret = Vec::<String>::new();
-
-        for iter in self.productionruns.iter()
-        {
-            ret.push(iter.0.clone());
-        }
+        let mut ret: Vec<u64> = self.productionruns.keys().copied().collect();
+        ret.sort_unstable();
         ret
     }
 
-    pub fn get_item_iter(&self) -> std::collections::hash_map::Iter<String,Item>
+    pub fn get_item_iter(&self) -> std::collections::hash_map::Iter<'_, String,Item>
     {
         self.known_items.iter()
     }
 
-    pub fn get_productionrun_iter(&self) 
-        -> std::collections::hash_map::Iter<String,T1ProductionRun>
+    pub fn get_productionrun_iter(&self)
+        -> std::collections::hash_map::Iter<'_, u64, ProductionRun>
     {
         self.productionruns.iter()
     }
 
-    pub fn set_item_buy_price(&mut self,item_name: &str, new_price: u64)
+    pub fn set_item_buy_price(&mut self,item_name: &str, new_price: Isk) -> Result<()>
     {
-        let item = self.known_items.get_mut(item_name).unwrap();
+        let item = self.known_items.get_mut(item_name)
+            .ok_or_else(|| Error::UnknownItem(item_name.to_string()))?;
         item.buy_price = new_price;
-        self.save_item(item_name);
+        self.save_item(item_name)
     }
 
-    pub fn set_item_sell_price(&mut self,item_name: &str, new_price: u64)
+    pub fn set_item_sell_price(&mut self,item_name: &str, new_price: Isk) -> Result<()>
     {
-        let item = self.known_items.get_mut(item_name).unwrap();
+        let item = self.known_items.get_mut(item_name)
+            .ok_or_else(|| Error::UnknownItem(item_name.to_string()))?;
         item.sell_price = new_price;
-        self.save_item(item_name);
+        self.save_item(item_name)
     }
 
+    /// Adds the blueprint with the given type ID and returns its name.
     pub fn add_blueprint(&mut self, bp_id: usize, material_research: u8, time_research: u8)
+        -> Result<String>
     {
-        let bp = T1Blueprint::new(bp_id as usize, material_research, time_research, self);
+        let name = self.type_name(bp_id as i64)?;
+        if self.has_blueprint(name)
+        {
+            return Err(Error::DuplicateBlueprint(name.to_string()));
+        }
+        let bp = Blueprint::new(bp_id, material_research, time_research, self)?;
         let bp_name = bp.name.clone();
         self.known_blueprints.insert(bp.name.clone(), bp);
-        self.save_blueprint(&bp_name);
+        self.save_blueprint(&bp_name)?;
+        Ok(bp_name)
     }
 
-    pub fn add_productionrun(&mut self, pr_name: &str, jobruns: u64, installation_cost: u64)
+    /// Updates ME and TE of a blueprint, e.g. after researching it.
+    pub fn set_blueprint_research(&mut self, bp_name: &str, material_research: u8,
+        time_research: u8) -> Result<()>
     {
-        let pr = T1ProductionRun::new(pr_name, jobruns, installation_cost, self);
-        self.productionruns.insert(pr.blueprint.clone(), pr);
-        self.save_prodcutionrun(pr_name);
+        let bp = self.known_blueprints.get_mut(bp_name)
+            .ok_or_else(|| Error::UnknownBlueprint(bp_name.to_string()))?;
+        if bp.activity == JobActivity::Reaction && (material_research, time_research) != (0, 0)
+        {
+            return Err(Error::NotResearchable(bp_name.to_string()));
+        }
+        bp.material_research = material_research;
+        bp.time_research = time_research;
+        self.save_blueprint(bp_name)
     }
 
-    pub fn save_blueprint(&self, bp_name: &str)
+    /// Records whether originals or copies of a blueprint are owned. Originals have no run
+    /// limit, so `runs` is only kept for copies.
+    pub fn set_blueprint_ownership(&mut self, bp_name: &str, kind: BlueprintKind, quantity: u64,
+        runs: u64) -> Result<()>
     {
-        let base_path = &format!("{}/blueprints", self.data_base_dir); 
+        let bp = self.known_blueprints.get_mut(bp_name)
+            .ok_or_else(|| Error::UnknownBlueprint(bp_name.to_string()))?;
+        bp.kind = kind;
+        bp.quantity = quantity;
+        bp.runs = match kind
+        {
+            BlueprintKind::Original => 0,
+            BlueprintKind::Copy if runs > quantity.saturating_mul(bp.max_production_limit) =>
+                return Err(Error::RunLimitExceeded(bp_name.to_string(),
+                    quantity.saturating_mul(bp.max_production_limit))),
+            BlueprintKind::Copy => runs,
+        };
+        self.save_blueprint(bp_name)
+    }
 
-        if Path::new(&base_path).exists() == false
+    /// Runs of a blueprint copy not yet reserved by planned or running production runs,
+    /// leaving out the run `except`. `None` for originals, which have no limit.
+    pub fn available_runs(&self, bp_name: &str, except: Option<u64>) -> Option<u64>
+    {
+        let bp = self.get_blueprint(bp_name)?;
+        if bp.kind == BlueprintKind::Original
         {
-            eprintln!("Directory: {} does not exist", base_path);
-            panic!();
+            return None;
         }
-
-        let bp = self.known_blueprints.get(bp_name).expect("Blueprint not found in database");
-
-        let file_path = format!("{}/{}.json", base_path, bp.name);
-        let serialzed = serde_json::to_string(&bp).unwrap();
-        fs::write(&file_path, serialzed).expect("Could not write blueprint file");
+        let reserved: u64 = self.productionruns.values()
+            .filter(|pr| pr.blueprint == bp_name && Some(pr.id) != except
+                && matches!(pr.status, RunStatus::Planned | RunStatus::Running))
+            .map(|pr| pr.jobruns)
+            .sum();
+        Some(bp.runs.saturating_sub(reserved))
     }
 
-    pub fn save_item(&self, item_name: &str)
+    /// Most runs a job on a blueprint can have: its maximum production limit, further
+    /// limited by the runs left on copies apart from the production run `except`.
+    pub fn max_jobruns(&self, bp_name: &str, except: Option<u64>) -> u64
     {
-        let base_path = &format!("{}/items", self.data_base_dir); 
+        let limit = self.get_blueprint(bp_name).map_or(0, |bp| bp.max_production_limit);
+        self.available_runs(bp_name, except).map_or(limit, |available| available.min(limit))
+    }
 
-        if Path::new(&base_path).exists() == false
+    /// Checks that a single job on a blueprint can have `jobruns` runs.
+    fn check_run_limit(&self, bp_name: &str, jobruns: u64) -> Result<()>
+    {
+        let limit = self.get_blueprint(bp_name)
+            .ok_or_else(|| Error::UnknownBlueprint(bp_name.to_string()))?.max_production_limit;
+        if jobruns > limit
         {
-            eprintln!("Directory: {} does not exist", base_path);
-            panic!();
+            return Err(Error::RunLimitExceeded(bp_name.to_string(), limit));
         }
+        Ok(())
+    }
 
-        let item = self.known_items.get(item_name).expect("Item not found in database");
-
-        let file_path = format!("{}/{}.json", base_path, item.name);
-
-        let serialzed = serde_json::to_string(&item).unwrap();
-        fs::write(&file_path, serialzed).expect("Could not write item file");
+    /// Checks that a blueprint copy has `jobruns` runs left for the production run `id`.
+    fn check_available_runs(&self, bp_name: &str, id: u64, jobruns: u64) -> Result<()>
+    {
+        let available = match self.available_runs(bp_name, Some(id))
+        {
+            Some(available) => available,
+            None => return Ok(()),
+        };
+        if jobruns > available
+        {
+            return Err(Error::NotEnoughRuns(bp_name.to_string(), available));
+        }
+        Ok(())
     }
 
-    pub fn save_prodcutionrun(&self, pr_name: &str)
+    /// IDs of the production runs of a blueprint.
+    pub fn productionruns_of(&self, bp_name: &str) -> Vec<u64>
     {
-        let base_path = &format!("{}/productionruns", self.data_base_dir); 
+        let mut ret: Vec<u64> = self.productionruns.values()
+            .filter(|pr| pr.blueprint == bp_name)
+            .map(|pr| pr.id)
+            .collect();
+        ret.sort_unstable();
+        ret
+    }
 
-        if Path::new(&base_path).exists() == false
+    /// Deletes a blueprint. A blueprint that still has production runs is only deleted
+    /// together with them if `delete_runs` is set. Returns the names of deleted runs.
+    pub fn delete_blueprint(&mut self, bp_name: &str, delete_runs: bool) -> Result<Vec<String>>
+    {
+        if !self.has_blueprint(bp_name)
+        {
+            return Err(Error::UnknownBlueprint(bp_name.to_string()));
+        }
+        let runs = self.productionruns_of(bp_name);
+        if !runs.is_empty() && !delete_runs
+        {
+            return Err(Error::BlueprintInUse(bp_name.to_string(),
+                runs.iter().map(|id| self.productionruns[id].name.clone()).collect()));
+        }
+        let mut deleted = Vec::<String>::new();
+        for id in runs
         {
-            eprintln!("Directory: {} does not exist", base_path);
-            panic!();
+            deleted.push(self.delete_productionrun(id)?.name);
         }
+        self.known_blueprints.remove(bp_name);
+        remove_file(&self.data_base_dir.join("blueprints").join(format!("{}.json", bp_name)))?;
+        Ok(deleted)
+    }
 
-        let item = self.productionruns.get(pr_name).expect("Productionrun not found in database");
+    /// Blueprints and production runs that need an item, as descriptions like
+    /// `blueprint Rifter Blueprint`.
+    pub fn item_users(&self, item_name: &str) -> Vec<String>
+    {
+        let uses = |list: &[(String, u64)]| list.iter().any(|x| x.0 == item_name);
+        let mut ret = Vec::<String>::new();
+        for bp in self.known_blueprints.values()
+        {
+            let invention = bp.invention.as_ref().is_some_and(|inv| uses(&inv.datacores)
+                || inv.decryptor.is_some_and(|d| self.type_name(d.type_id()).ok()
+                    == Some(item_name)));
+            if uses(&bp.materials) || uses(&bp.products) || invention
+            {
+                ret.push(format!("blueprint {}", bp.name));
+            }
+        }
+        let id = self.get_item(item_name).map(|item| item.id);
+        for pr in self.productionruns.values()
+        {
+            // Intermediates of sub-builds are not listed in the run itself
+            let intermediate = pr.build_components && pr.materials.iter()
+                .filter_map(|m| self.get_item(&m.0))
+                .any(|m| id.is_some_and(|id| buildtree::material_ids(self, m.id).contains(&id)));
+            if uses(&pr.materials) || uses(&pr.products) || intermediate
+            {
+                ret.push(format!("production run {}", pr.name));
+            }
+        }
+        ret.sort();
+        ret
+    }
 
-        let file_path = format!("{}/{}.json", base_path, pr_name);
+    /// Deletes an item that no blueprint or production run needs anymore. An item that
+    /// still has stock is only deleted if `force` is set. Returns the deleted item.
+    pub fn delete_item(&mut self, item_name: &str, force: bool) -> Result<Item>
+    {
+        if !self.has_item(item_name)
+        {
+            return Err(Error::UnknownItem(item_name.to_string()));
+        }
+        let stock = self.stock_of(item_name, None);
+        if stock > 0 && !force
+        {
+            return Err(Error::ItemInStock(item_name.to_string(), stock));
+        }
+        let users = self.item_users(item_name);
+        if !users.is_empty()
+        {
+            return Err(Error::ItemInUse(item_name.to_string(), users));
+        }
+        remove_file(&self.data_base_dir.join("items").join(format!("{}.json", item_name)))?;
+        self.known_items.remove(item_name).ok_or_else(|| Error::UnknownItem(item_name.to_string()))
+    }
 
-        let serialzed = serde_json::to_string(&item).unwrap();
-        fs::write(&file_path, serialzed).expect("Could not write productionrun file");
+    /// Adds a production run, giving it the next free ID and, if it has none, a name
+    /// derived from its blueprint. Returns the ID.
+    pub fn add_productionrun(&mut self, mut pr: ProductionRun) -> Result<u64>
+    {
+        pr.id = self.next_productionrun_id();
+        if pr.name.is_empty()
+        {
+            pr.name = self.default_productionrun_name(&pr.blueprint, pr.id);
+        }
+        if self.productionruns.values().any(|other| other.name == pr.name)
+        {
+            return Err(Error::DuplicateProductionRun(pr.name));
+        }
+        if pr.installation_cost.is_none() && pr.facility.solar_system.is_none()
+        {
+            return Err(Error::NoInstallationCost(pr.name));
+        }
+        let activity = self.get_blueprint(&pr.blueprint)
+            .ok_or_else(|| Error::UnknownBlueprint(pr.blueprint.clone()))?.activity;
+        self.check_run_limit(&pr.blueprint, pr.jobruns)?;
+        if matches!(pr.status, RunStatus::Planned | RunStatus::Running)
+        {
+            self.check_available_runs(&pr.blueprint, pr.id, pr.jobruns)?;
+        }
+        let checked = pr.facility.check(activity)
+            .and(pr.refinery.map_or(Ok(()), |r| r.check(JobActivity::Reaction)));
+        if let Err(msg) = checked
+        {
+            return Err(Error::InvalidFacility(format!("production run {}", pr.name), msg));
+        }
+        if pr.build_components
+        {
+            // Intermediate materials have to be known items to be priced
+            let mut ids = Vec::<i64>::new();
+            for (name, _) in pr.materials.iter()
+            {
+                let item = self.get_item(name).ok_or_else(|| Error::UnknownItem(name.clone()))?;
+                ids.extend(buildtree::material_ids(self, item.id));
+            }
+            for id in ids
+            {
+                self.ensure_item(id, false)?;
+            }
+        }
+        self.insert_productionrun(pr)
     }
 
-    pub fn search_ids(&self, query: &str) -> Vec<(&str, i64)>
+    /// Stores and saves a production run with a new ID and records the ID as given out.
+    fn insert_productionrun(&mut self, pr: ProductionRun) -> Result<u64>
     {
-        let type_ids_hash = self.type_ids.as_hash().unwrap();
-        let mut ret = Vec::<(&str, i64)>::new();
+        let id = pr.id;
+        self.productionruns.insert(id, pr);
+        self.save_productionrun(id)?;
+        self.last_productionrun_id = self.last_productionrun_id.max(id);
+        let path = self.data_base_dir.join("last_run_id.json");
+        utils::write_json(&path, &self.last_productionrun_id)?;
+        Ok(id)
+    }
 
-        for iter in type_ids_hash.iter()
+    /// Sets the status of a production run. A run on a blueprint copy uses up its runs when
+    /// it is done and gets them back if it is set back from done.
+    pub fn set_productionrun_status(&mut self, id: u64, status: RunStatus) -> Result<()>
+    {
+        let pr = self.productionruns.get(&id)
+            .ok_or_else(|| Error::UnknownProductionRun(id.to_string()))?;
+        let (bp_name, jobruns, old) = (pr.blueprint.clone(), pr.jobruns, pr.status);
+        // A done run gives back exactly the runs it reserves again, a cancelled one needs them
+        if old == RunStatus::Cancelled && status != RunStatus::Cancelled
+        {
+            self.check_available_runs(&bp_name, id, jobruns)?;
+        }
+        if (old == RunStatus::Done) != (status == RunStatus::Done)
         {
-            match iter.1["name"]["en"].as_str()
+            if let Some(bp) = self.known_blueprints.get_mut(&bp_name)
+                .filter(|bp| bp.kind == BlueprintKind::Copy)
             {
-                Some(name) =>
+                bp.runs = match status
                 {
-                    if name.to_lowercase().contains(&query.to_lowercase())
-                    {
-                        ret.push((name, iter.0.as_i64().unwrap()));
-                    }
-                }
-                None => (),
+                    RunStatus::Done => bp.runs.saturating_sub(jobruns),
+                    _ => bp.runs + jobruns,
+                };
+                self.save_blueprint(&bp_name)?;
             }
         }
-        ret
+        if let Some(pr) = self.productionruns.get_mut(&id)
+        {
+            pr.status = status;
+        }
+        self.save_productionrun(id)
     }
 
-    pub fn print_blueprints(&self)
+    /// Changes the runs of a production run. Done runs have already used up their blueprint
+    /// copy runs and cannot be changed.
+    pub fn set_productionrun_jobruns(&mut self, id: u64, jobruns: u64) -> Result<()>
     {
-        println!("KNOWN BLUEPRINTS:");
-        for iter in self.known_blueprints.iter()
+        let pr = self.productionruns.get(&id)
+            .ok_or_else(|| Error::UnknownProductionRun(id.to_string()))?;
+        if pr.status == RunStatus::Done
         {
-            println!("{}", iter.1);
+            return Err(Error::RunDone(pr.name.clone()));
+        }
+        self.check_run_limit(&pr.blueprint, jobruns)?;
+        if matches!(pr.status, RunStatus::Planned | RunStatus::Running)
+        {
+            self.check_available_runs(&pr.blueprint.clone(), id, jobruns)?;
+        }
+        if let Some(pr) = self.productionruns.get_mut(&id)
+        {
+            pr.jobruns = jobruns;
         }
+        self.save_productionrun(id)
     }
 
-    pub fn print_items(&self)
+    pub fn set_productionrun_date(&mut self, id: u64, planned_date: Option<String>) -> Result<()>
     {
-        println!("KNOWN ITEMS:");
-        for iter in self.known_items.iter()
+        let pr = self.productionruns.get_mut(&id)
+            .ok_or_else(|| Error::UnknownProductionRun(id.to_string()))?;
+        pr.planned_date = planned_date;
+        self.save_productionrun(id)
+    }
+
+    /// Renames a production run. An empty name goes back to the default name.
+    pub fn rename_productionrun(&mut self, id: u64, name: &str) -> Result<()>
+    {
+        let blueprint = self.get_productionrun(id)
+            .ok_or_else(|| Error::UnknownProductionRun(id.to_string()))?.blueprint.clone();
+        let name = if name.is_empty()
         {
-            println!("{}", iter.1);
+            self.default_productionrun_name(&blueprint, id)
+        }
+        else
+        {
+            name.to_string()
+        };
+        if self.productionruns.values().any(|pr| pr.name == name && pr.id != id)
+        {
+            return Err(Error::DuplicateProductionRun(name));
+        }
+        if let Some(pr) = self.productionruns.get_mut(&id)
+        {
+            pr.name = name;
         }
+        self.save_productionrun(id)
     }
 
-    pub fn has_blueprint(&self, name: &str) -> bool
+    pub fn delete_productionrun(&mut self, id: u64) -> Result<ProductionRun>
     {
-        self.known_blueprints.contains_key(name)
+        let pr = self.productionruns.remove(&id)
+            .ok_or_else(|| Error::UnknownProductionRun(id.to_string()))?;
+        remove_file(&self.data_base_dir.join("productionruns").join(format!("{}.json", id)))?;
+        Ok(pr)
     }
 
-    pub fn has_item(&self, name: &str) -> bool
+    /// Runs saved before the last ID was recorded still count, so their IDs are not reused.
+    fn next_productionrun_id(&self) -> u64
     {
-        self.known_items.contains_key(name)
+        self.productionruns.keys().copied().fold(self.last_productionrun_id, u64::max) + 1
     }
 
-    pub fn has_productionrun(&self, name: &str) -> bool
+    /// The blueprint name, or the blueprint name with the run ID if another run already
+    /// has it.
+    fn default_productionrun_name(&self, blueprint: &str, id: u64) -> String
     {
-        self.productionruns.contains_key(name)
+        if self.productionruns.values().any(|pr| pr.name == blueprint)
+        {
+            format!("{} #{}", blueprint, id)
+        }
+        else
+        {
+            blueprint.to_string()
+        }
     }
-}
 
+    /// Sets whether an intermediate material of a production run is built or bought.
+    /// `None` goes back to the recommendation.
+    pub fn set_build_choice(&mut self, id: u64, item_name: &str,
+        choice: Option<BuildChoice>) -> Result<()>
+    {
+        if !self.has_item(item_name)
+        {
+            return Err(Error::UnknownItem(item_name.to_string()));
+        }
+        let pr = self.productionruns.get_mut(&id)
+            .ok_or_else(|| Error::UnknownProductionRun(id.to_string()))?;
+        match choice
+        {
+            Some(choice) => pr.choices.insert(item_name.to_string(), choice),
+            None => pr.choices.remove(item_name),
+        };
+        self.save_productionrun(id)
+    }
 
+    pub fn save_blueprint(&self, bp_name: &str) -> Result<()>
+    {
+        let bp = self.known_blueprints.get(bp_name)
+            .ok_or_else(|| Error::UnknownBlueprint(bp_name.to_string()))?;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct Item
-{
-    pub name: String,
-    pub id: i64,
-    pub buy_price: u64,
-    pub sell_price: u64,
-    pub produced: bool,
-}
+        let file_path = self.data_base_dir.join("blueprints").join(format!("{}.json", bp.name));
+        utils::write_json(&file_path, bp)
+    }
 
-impl fmt::Display for Item
-{
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    pub fn save_item(&self, item_name: &str) -> Result<()>
     {
-        write!(f, "{} ({})\n  buy price: {}ISK\n  sell price: {}ISK\n  produced: {}",
-            self.name, self.id, self.buy_price.to_formatted_string(&Locale::en), 
-            self.sell_price.to_formatted_string(&Locale::en), self.produced)
+        let item = self.known_items.get(item_name)
+            .ok_or_else(|| Error::UnknownItem(item_name.to_string()))?;
+
+        let file_path = self.data_base_dir.join("items").join(format!("{}.json", item.name));
+        utils::write_json(&file_path, item)
     }
-}
 
+    pub fn save_productionrun(&self, id: u64) -> Result<()>
+    {
+        let pr = self.productionruns.get(&id)
+            .ok_or_else(|| Error::UnknownProductionRun(id.to_string()))?;
 
+        let file_path = self.data_base_dir.join("productionruns").join(format!("{}.json", id));
+        utils::write_json(&file_path, pr)
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct T1Blueprint
-{
-    pub name: String,
-    pub bp_id: i64,
-    pub manufacturing_mats: Vec<(String, u64)>,
-    pub material_research: u8,
-    pub time_research: u8,
-    pub produced_item: (String, i64),
-}
+    /// Replaces the adjusted prices with the ones from a saved ESI `/markets/prices/`
+    /// response and returns how many were imported.
+    pub fn import_adjusted_prices(&mut self, path: &Path) -> Result<usize>
+    {
+        self.set_adjusted_prices(esi_import::read_adjusted_prices(path)?)
+    }
 
-impl T1Blueprint
-{
-    pub fn new(id: usize, material_research: u8, time_research: u8, db: &mut Database) -> T1Blueprint 
+    /// Replaces the adjusted prices and returns how many there are.
+    pub fn set_adjusted_prices(&mut self, prices: HashMap<i64, Isk>) -> Result<usize>
     {
-        let name = db.type_ids[id]["name"]["en"].as_str().unwrap().to_string();
-        let bp_id = id as i64;
-        let mut manufacturing_mats = Vec::<(String, u64)>::new();
-        let mats_vec = &db.blueprints[id]["activities"]["manufacturing"]["materials"]
-                .as_vec().unwrap();
+        self.adjusted_prices = prices;
+        utils::write_json(&self.data_base_dir.join("adjusted_prices.json"), &self.adjusted_prices)?;
+        Ok(self.adjusted_prices.len())
+    }
 
-        for i in mats_vec.iter()
+    /// Adds or updates the known blueprints from ESI blueprints. Originals of a type are
+    /// merged into one entry, or if there are none its copies with their runs added up;
+    /// the entry gets the highest ME and TE among them. Returns the imported blueprint
+    /// names and the types that were skipped with the reason.
+    pub fn import_esi_blueprints(&mut self, entries: &[EsiBlueprint])
+        -> Result<(Vec<String>, Vec<String>)>
+    {
+        let mut by_type = HashMap::<i64, Vec<&EsiBlueprint>>::new();
+        for entry in entries.iter()
         {
-            let quantity = i["quantity"].as_i64().unwrap();
-            let id = i["typeID"].as_i64().unwrap();
-            let name = db.type_ids[id as usize]["name"]["en"].as_str().unwrap();
-            if db.known_items.contains_key(name) == false
-            {
-                let item = Item{name: name.to_string(), id, buy_price: 0, sell_price: 0,
-                        produced: false};
-                db.known_items.insert(name.to_string(), item);
-                println!("{}", name);
-                db.save_item(name);
-            }
-            manufacturing_mats.push((name.to_string(), quantity.try_into().unwrap()));
+            by_type.entry(entry.type_id).or_default().push(entry);
         }
+        let mut type_ids: Vec<i64> = by_type.keys().copied().collect();
+        type_ids.sort_unstable();
 
-        let produces = &db.blueprints[id]["activities"]["manufacturing"]["products"]
-                .as_vec().unwrap();
-        let id2 = &produces[0]["typeID"];
-        let produced_name = db.type_ids[id2.as_i64().unwrap() as usize]["name"]["en"]
-                .as_str().unwrap();
-        if db.known_items.contains_key(produced_name) == false
+        let (mut imported, mut skipped) = (Vec::<String>::new(), Vec::<String>::new());
+        for type_id in type_ids
         {
-            let item = Item{name: produced_name.to_string(), id: id2.as_i64().unwrap(),
-                buy_price: 0, sell_price: 0, produced: true};
-            db.known_items.insert(produced_name.to_string(), item);
-            db.save_item(&produced_name);
+            let (originals, copies): (Vec<&EsiBlueprint>, Vec<&EsiBlueprint>) =
+                by_type[&type_id].iter().partition(|bp| !bp.is_copy());
+            let (kind, quantity, runs, owned) = if originals.is_empty()
+            {
+                (BlueprintKind::Copy, copies.len() as u64,
+                    copies.iter().map(|bp| bp.runs.max(0) as u64).sum(), copies)
+            }
+            else
+            {
+                (BlueprintKind::Original,
+                    originals.iter().map(|bp| bp.quantity.max(1) as u64).sum(), 0, originals)
+            };
+            let (me, te) = owned.iter().map(|bp| (bp.material_efficiency, bp.time_efficiency))
+                .max().unwrap_or_default();
+
+            let result = self.type_name(type_id).map(str::to_string).and_then(|name|
+            {
+                if self.has_blueprint(&name)
+                {
+                    self.set_blueprint_research(&name, me, te)?;
+                }
+                else
+                {
+                    self.add_blueprint(type_id as usize, me, te)?;
+                }
+                self.set_blueprint_ownership(&name, kind, quantity, runs)?;
+                Ok(name)
+            });
+            match result
+            {
+                Ok(name) => imported.push(name),
+                Err(e) => skipped.push(format!("{}: {}", type_id, e)),
+            }
         }
-        T1Blueprint {name, bp_id, manufacturing_mats, material_research, time_research,
-            produced_item: (produced_name.to_string(),id2.as_i64().unwrap())}
+        Ok((imported, skipped))
     }
-}
 
-impl fmt::Display for T1Blueprint
-{
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    /// Replaces the stock of known items at every station or structure that appears in
+    /// `assets` with the quantities found there. Assets in containers count towards the
+    /// station the container is in. Types that are not known items yet are added from the
+    /// SDE. Returns the number of item and location pairs stocked and the type IDs missing
+    /// from the SDE, whose assets were left out.
+    pub fn import_esi_assets(&mut self, assets: &[EsiAsset]) -> Result<(usize, Vec<i64>)>
     {
-        let mut message = format!("{} ({})\n  Material research: {}%\n  Time research: {}%\n",
-            self.name, self.bp_id, self.material_research, self.time_research);
+        let by_item_id: HashMap<i64, &EsiAsset> = assets.iter().map(|a| (a.item_id, a)).collect();
+        let mut names: HashMap<i64, String> = self.known_items.values()
+            .map(|item| (item.id, item.name.clone()))
+            .collect();
+        let mut unknown_types = Vec::<i64>::new();
+        for asset in assets.iter()
+        {
+            if names.contains_key(&asset.type_id) || unknown_types.contains(&asset.type_id)
+            {
+                continue;
+            }
+            if self.sde.types.contains_key(&asset.type_id)
+            {
+                names.insert(asset.type_id, self.ensure_item(asset.type_id, false)?);
+            }
+            else
+            {
+                unknown_types.push(asset.type_id);
+            }
+        }
+        unknown_types.sort_unstable();
 
-        message.push_str(&format!("  Manufacturing materials:\n"));
-        for i in 0..self.manufacturing_mats.len()
+        let mut locations = Vec::<String>::new();
+        let mut found = HashMap::<(String, String), u64>::new();
+        for asset in assets.iter()
         {
-            message.push_str(&format!("    {}: {}\n", self.manufacturing_mats[i].0,
-                self.manufacturing_mats[i].1));
+            let location = esi_import::root_location(asset, &by_item_id).to_string();
+            if let Some(name) = names.get(&asset.type_id)
+            {
+                *found.entry((name.clone(), location.clone())).or_insert(0) += asset.quantity;
+            }
+            locations.push(location);
         }
+        locations.sort();
+        locations.dedup();
 
-        write!(f, "{}", message)
+        let mut changed = Vec::<String>::new();
+        for item in self.known_items.values_mut()
+        {
+            let before = item.stock.len();
+            item.stock.retain(|location, _| locations.binary_search(location).is_err());
+            let mut stocked = false;
+            for location in locations.iter()
+            {
+                if let Some(quantity) = found.get(&(item.name.clone(), location.clone()))
+                {
+                    item.stock.insert(location.clone(), *quantity);
+                    stocked = true;
+                }
+            }
+            if stocked || item.stock.len() != before
+            {
+                changed.push(item.name.clone());
+            }
+        }
+        for name in changed.iter()
+        {
+            self.save_item(name)?;
+        }
+        Ok((found.len(), unknown_types))
     }
-}
-
 
+    /// Merges the cost indices from a saved ESI `/industry/systems/` response and returns
+    /// the number of solar systems imported.
+    pub fn import_cost_indices(&mut self, path: &Path) -> Result<usize>
+    {
+        self.merge_cost_indices(esi_import::read_cost_indices(path)?)
+    }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct T1ProductionRun
-{
-    pub blueprint: String,
-    pub materials: Vec<(String, u64)>,
-    pub produces: String,
-    pub jobruns: u64,
-    pub installation_cost: u64,
-}
+    /// Merges cost indices by solar system and activity and returns the number of solar
+    /// systems imported.
+    pub fn merge_cost_indices(&mut self, imported: HashMap<i64, HashMap<String, f64>>)
+        -> Result<usize>
+    {
+        let count = imported.len();
+        self.cost_indices.extend(imported);
+        self.save_cost_indices()?;
+        Ok(count)
+    }
 
-impl T1ProductionRun
-{
-    pub fn new(blueprint: &str, jobruns: u64, installation_cost: u64, db: &mut Database) 
-        -> T1ProductionRun
+    /// Sets the buy and sell prices of all known items found in the market exports in `dir`
+    /// and returns how many items were updated. Unreadable exports are reported and skipped.
+    pub fn import_market_logs(&mut self, dir: &Path, point: PricePoint) -> Result<usize>
     {
-        let bp = db.get_blueprint(blueprint).expect("Blueprint was not found");
-        let produces = bp.produced_item.0.clone();
-        let materials = bp.manufacturing_mats.clone();
+        let logs = marketlogs::read_market_logs(dir)?;
+        logs.report_skipped();
+        let books = logs.books;
+        let mut updated = Vec::new();
+        for item in self.known_items.values_mut()
+        {
+            if let Some(p) = books.get(&item.id).map(|book| book.prices(point))
+            {
+                if let Some(buy) = p.buy
+                {
+                    item.buy_price = buy;
+                }
+                if let Some(sell) = p.sell
+                {
+                    item.sell_price = sell;
+                }
+                updated.push(item.name.clone());
+            }
+        }
+        for name in updated.iter()
+        {
+            self.save_item(name)?;
+        }
+        self.set_order_books(books)?;
+        Ok(updated.len())
+    }
 
-        T1ProductionRun {blueprint: blueprint.to_string(), materials, produces,
-            jobruns, installation_cost}
+    /// Replaces order books with the ones from a saved ESI `/markets/{region_id}/orders/`
+    /// response, optionally only with the orders at `location`, and returns how many types
+    /// were imported.
+    pub fn import_order_books(&mut self, path: &Path, location: Option<i64>) -> Result<usize>
+    {
+        self.set_order_books(esi_import::read_order_books(path, location)?)
     }
 
-    pub fn get_production_materials(&self, db: &Database) -> Vec<(String, u64)>
+    /// Replaces the order books of the types in `books` and returns how many there are.
+    pub fn set_order_books(&mut self, books: HashMap<i64, OrderBook>) -> Result<usize>
     {
-        let mut ret = Vec::<(String, u64)>::new();
-        let bp = db.get_blueprint(&self.blueprint).unwrap();
+        let count = books.len();
+        self.order_books.extend(books);
+        utils::write_json(&self.data_base_dir.join("order_books.json"), &self.order_books)?;
+        Ok(count)
+    }
 
-        for iter in self.materials.iter()
+    /// Cost of buying `quantity` of an item. Bought instantly, the sell orders of its order
+    /// book are taken cheapest first. Buy orders do not trade against other buyers, so they
+    /// are placed at the best bid of the order book, or else at the item's buy price.
+    pub fn purchase_cost(&self, item: &Item, quantity: u64, fees: &FeeModel) -> PurchaseCost
+    {
+        let book = self.order_books.get(&item.id);
+        match (fees.buy_mode, book)
         {
-            let count = ((iter.1 as f64 * self.jobruns as f64) / 100 as f64) 
-                * (100 - bp.material_research as u64)as f64;
-            ret.push((iter.0.clone(), count as u64));
+            (BuyMode::InstantBuy, Some(book)) => book.purchase_cost(quantity),
+            (BuyMode::InstantBuy, None) => PurchaseCost::flat(item.sell_price, quantity),
+            (BuyMode::BuyOrder, _) => PurchaseCost::flat(book.and_then(|b| b.buy.first())
+                .map_or(item.buy_price, |o| o.price), quantity),
         }
-        ret
     }
 
-    pub fn get_production_cost(&self, db: &Database) -> u64
+    /// Sets up invention for the T2 blueprint `bp_id`, adding it to the known blueprints
+    /// if needed, and sets its ME and TE to the ones of invented copies. Returns its name.
+    pub fn set_invention(&mut self, bp_id: i64, decryptor: Option<Decryptor>,
+        facility: Facility) -> Result<String>
     {
-        let mut ret: u64 = 0;
-        for iter in self.get_production_materials(db).iter()
+        let name = self.type_name(bp_id)?.to_string();
+        let (source_bp_id, probability, base_runs, datacore_ids) = self.sde.blueprints.iter()
+            .find_map(|(id, bp)|
+            {
+                let invention = bp.activities.get("invention")?;
+                let product = invention.products.iter().find(|p| p.type_id == bp_id)?;
+                Some((*id, product.probability, product.quantity, invention.materials.clone()))
+            })
+            .ok_or_else(|| Error::NotInventable(name.clone()))?;
+        let base_probability = probability.ok_or(Error::MissingField(source_bp_id, "probability"))?;
+
+        for id in datacore_ids.iter().map(|d| d.0).chain(decryptor.map(|d| d.type_id()))
+        {
+            self.type_name(id)?;
+        }
+        let mut datacores = Vec::<(String, u64)>::new();
+        for (id, quantity) in datacore_ids
+        {
+            datacores.push((self.ensure_item(id, false)?, quantity));
+        }
+        if let Some(decryptor) = decryptor
+        {
+            self.ensure_item(decryptor.type_id(), false)?;
+        }
+
+        let invention = Invention {source_bp_id, datacores, base_probability, base_runs,
+            decryptor, facility};
+        if !self.has_blueprint(&name)
+        {
+            self.add_blueprint(bp_id as usize, invention.material_research(),
+                invention.time_research())?;
+        }
+        if let Some(bp) = self.known_blueprints.get_mut(&name)
         {
-            let item = db.get_item(&iter.0).unwrap();
-            ret += item.buy_price * iter.1
+            bp.material_research = invention.material_research();
+            bp.time_research = invention.time_research();
+            bp.invention = Some(invention);
         }
-        ret + self.installation_cost
+        self.save_blueprint(&name)?;
+        Ok(name)
     }
 
-    pub fn get_sell_value(&self, db: &Database) -> u64
+    pub fn set_cost_index(&mut self, solar_system: i64, activity: &str, index: f64) -> Result<()>
     {
-        let item = db.get_item(&self.produces).unwrap();
-        item.sell_price * self.jobruns
+        self.cost_indices.entry(solar_system).or_default().insert(activity.to_string(), index);
+        self.save_cost_indices()
     }
 
+    pub fn get_cost_index(&self, solar_system: i64, activity: &str) -> Result<f64>
+    {
+        self.cost_indices.get(&solar_system).and_then(|a| a.get(activity)).copied()
+            .ok_or_else(|| Error::MissingCostIndex(solar_system, activity.to_string()))
+    }
 
-}
-
+    fn save_cost_indices(&self) -> Result<()>
+    {
+        utils::write_json(&self.data_base_dir.join("cost_indices.json"), &self.cost_indices)
+    }
+
+    /// Searches SDE types by name or type ID, best matches first.
+    pub fn search(&self, query: &str, filter: &SearchFilter, limit: usize) -> Vec<SearchHit<'_>>
+    {
+        self.search_index.search(&self.sde, query, filter, limit)
+    }
+
+    /// Returns the given activity of a blueprint from the SDE.
+    pub fn sde_activity(&self, bp_id: i64, activity: &'static str) -> Result<&Activity>
+    {
+        self.sde.blueprints.get(&bp_id).and_then(|bp| bp.activities.get(activity))
+            .ok_or(Error::MissingActivity(bp_id, activity))
+    }
+
+    /// Maximum runs per job and per blueprint copy from the SDE.
+    pub fn sde_max_production_limit(&self, bp_id: i64) -> Result<u64>
+    {
+        self.sde.blueprints.get(&bp_id).map(|bp| bp.max_production_limit)
+            .ok_or(Error::UnknownTypeId(bp_id))
+    }
+
+    /// Base duration of one run of the given activity in seconds.
+    pub fn sde_activity_time(&self, bp_id: i64, activity: &'static str) -> Result<u64>
+    {
+        Ok(self.sde_activity(bp_id, activity)?.time)
+    }
+
+    /// Type IDs and quantities per run of everything a blueprint produces.
+    pub fn sde_products(&self, bp_id: i64, activity: JobActivity) -> Result<Vec<(i64, u64)>>
+    {
+        let ret: Vec<(i64, u64)> = self.sde_activity(bp_id, activity.sde_name())?.products.iter()
+            .map(|p| (p.type_id, p.quantity))
+            .collect();
+        if ret.is_empty()
+        {
+            return Err(Error::MissingField(bp_id, "products"));
+        }
+        Ok(ret)
+    }
+
+    /// Estimated item value of one run of a blueprint: its materials at ME 0 valued at
+    /// adjusted prices.
+    pub fn sde_estimated_item_value(&self, bp_id: i64, activity: JobActivity) -> Result<Isk>
+    {
+        let mut ret = Isk::ZERO;
+        for (id, quantity) in self.sde_activity(bp_id, activity.sde_name())?.materials.iter()
+        {
+            let price = self.adjusted_prices.get(id)
+                .ok_or_else(|| Error::MissingAdjustedPrice(self.type_name(*id)
+                    .unwrap_or("unknown type").to_string()))?;
+            ret += *price * *quantity;
+        }
+        Ok(ret)
+    }
+
+    /// Blueprint and activity producing a type, if any.
+    pub fn sde_producer(&self, type_id: i64) -> Option<(i64, JobActivity)>
+    {
+        self.producers.get(&type_id).copied()
+    }
 
-pub fn load_resources(data_base_dir: &str) 
-    -> (HashMap<String,T1Blueprint>, HashMap<String,Item>, HashMap<String, T1ProductionRun>)
+    /// Looks up the english name of a type ID in the SDE.
+    pub fn type_name(&self, id: i64) -> Result<&str>
+    {
+        self.sde.types.get(&id).map(|t| t.name.as_str()).ok_or(Error::UnknownTypeId(id))
+    }
+
+    /// Returns the known item with the given type ID, creating and saving it if needed.
+    fn ensure_item(&mut self, id: i64, produced: bool) -> Result<String>
+    {
+        let name = self.type_name(id)?.to_string();
+        if !self.known_items.contains_key(&name)
+        {
+            let item = Item{name: name.clone(), id, buy_price: Isk::ZERO, sell_price: Isk::ZERO,
+                produced, stock: HashMap::new()};
+            self.known_items.insert(name.clone(), item);
+            self.save_item(&name)?;
+        }
+        Ok(name)
+    }
+
+    pub fn print_blueprints(&self)
+    {
+        println!("KNOWN BLUEPRINTS:");
+        for iter in self.known_blueprints.iter()
+        {
+            println!("{}", iter.1);
+        }
+    }
+
+    pub fn print_items(&self)
+    {
+        println!("KNOWN ITEMS:");
+        for iter in self.known_items.iter()
+        {
+            println!("{}", iter.1);
+        }
+    }
+
+    pub fn has_blueprint(&self, name: &str) -> bool
+    {
+        self.known_blueprints.contains_key(name)
+    }
+
+    pub fn has_item(&self, name: &str) -> bool
+    {
+        self.known_items.contains_key(name)
+    }
+
+    /// Sums up the materials needed for the given production runs and subtracts the stock
+    /// at `location`, or at all locations. Runs whose materials were already consumed need
+    /// nothing. Sorted by item name.
+    pub fn shopping_list(&self, ids: &[u64], fees: &FeeModel, location: Option<&str>)
+        -> Result<Vec<ShoppingEntry>>
+    {
+        let mut item_list = HashMap::<String, u64>::new();
+        for id in ids.iter()
+        {
+            let pr = self.get_productionrun(*id)
+                .ok_or_else(|| Error::UnknownProductionRun(id.to_string()))?;
+            if pr.materials_consumed
+            {
+                continue;
+            }
+            for (name, count) in pr.get_production_materials(self, fees)?
+            {
+                *item_list.entry(name).or_insert(0) += count;
+            }
+        }
+        let mut ret: Vec<ShoppingEntry> = item_list.into_iter()
+            .map(|(item, needed)|
+            {
+                let in_stock = self.stock_of(&item, location);
+                ShoppingEntry {to_buy: needed.saturating_sub(in_stock), item, needed, in_stock}
+            })
+            .collect();
+        ret.sort_by(|a, b| a.item.cmp(&b.item));
+        Ok(ret)
+    }
+
+    /// Quantity of an item on hand at `location`, or at all locations.
+    pub fn stock_of(&self, item_name: &str, location: Option<&str>) -> u64
+    {
+        let stock = match self.get_item(item_name)
+        {
+            Some(item) => &item.stock,
+            None => return 0,
+        };
+        match location
+        {
+            Some(location) => stock.get(location).copied().unwrap_or(0),
+            None => stock.values().sum(),
+        }
+    }
+
+    /// Sets the quantity of an item at a location. Locations without stock are dropped.
+    pub fn set_stock(&mut self, item_name: &str, location: &str, quantity: u64) -> Result<()>
+    {
+        let item = self.known_items.get_mut(item_name)
+            .ok_or_else(|| Error::UnknownItem(item_name.to_string()))?;
+        if quantity == 0
+        {
+            item.stock.remove(location);
+        }
+        else
+        {
+            item.stock.insert(location.to_string(), quantity);
+        }
+        self.save_item(item_name)
+    }
+
+    pub fn add_stock(&mut self, item_name: &str, location: &str, quantity: u64) -> Result<()>
+    {
+        let current = self.stock_of(item_name, Some(location));
+        self.set_stock(item_name, location, current + quantity)
+    }
+
+    /// Takes `quantity` of an item from `location`, or from all locations in alphabetical
+    /// order. Nothing is taken if there is not enough.
+    pub fn remove_stock(&mut self, item_name: &str, location: Option<&str>, quantity: u64)
+        -> Result<()>
+    {
+        let available = self.stock_of(item_name, location);
+        if available < quantity
+        {
+            return Err(Error::NotEnoughStock(item_name.to_string(), available, quantity));
+        }
+        let mut locations: Vec<(String, u64)> = match location
+        {
+            Some(location) => vec![(location.to_string(), available)],
+            None => self.known_items[item_name].stock.iter()
+                .map(|(l, q)| (l.clone(), *q))
+                .collect(),
+        };
+        locations.sort();
+        let mut left = quantity;
+        for (location, have) in locations
+        {
+            let taken = have.min(left);
+            self.set_stock(item_name, &location, have - taken)?;
+            left -= taken;
+        }
+        Ok(())
+    }
+
+    /// Takes the bought materials of a production run out of the stock at `location`, or at
+    /// all locations, and returns them. Fails without taking anything if some are missing.
+    pub fn consume_materials(&mut self, id: u64, location: Option<&str>, fees: &FeeModel)
+        -> Result<Vec<(String, u64)>>
+    {
+        let pr = self.get_productionrun(id)
+            .ok_or_else(|| Error::UnknownProductionRun(id.to_string()))?;
+        if pr.materials_consumed
+        {
+            return Err(Error::MaterialsConsumed(pr.name.clone()));
+        }
+        let materials = pr.get_production_materials(self, fees)?;
+        for (name, quantity) in materials.iter()
+        {
+            let available = self.stock_of(name, location);
+            if available < *quantity
+            {
+                return Err(Error::NotEnoughStock(name.clone(), available, *quantity));
+            }
+        }
+        for (name, quantity) in materials.iter()
+        {
+            self.remove_stock(name, location, *quantity)?;
+        }
+        if let Some(pr) = self.productionruns.get_mut(&id)
+        {
+            pr.materials_consumed = true;
+        }
+        self.save_productionrun(id)?;
+        Ok(materials)
+    }
+
+    /// Adds the products of a production run to the stock at `location` and returns them.
+    pub fn add_products(&mut self, id: u64, location: &str) -> Result<Vec<(String, u64)>>
+    {
+        let pr = self.get_productionrun(id)
+            .ok_or_else(|| Error::UnknownProductionRun(id.to_string()))?;
+        if pr.products_added
+        {
+            return Err(Error::ProductsAdded(pr.name.clone()));
+        }
+        let products: Vec<(String, u64)> = pr.products.iter()
+            .map(|(name, quantity)| (name.clone(), quantity * pr.jobruns))
+            .collect();
+        for (name, quantity) in products.iter()
+        {
+            self.add_stock(name, location, *quantity)?;
+        }
+        if let Some(pr) = self.productionruns.get_mut(&id)
+        {
+            pr.products_added = true;
+        }
+        self.save_productionrun(id)?;
+        Ok(products)
+    }
+}
+
+
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Item
+{
+    pub name: String,
+    pub id: i64,
+    pub buy_price: Isk,
+    pub sell_price: Isk,
+    pub produced: bool,
+    /// Quantity on hand by location.
+    #[serde(default)]
+    pub stock: HashMap<String, u64>,
+}
+
+/// Material of a shopping list, with the stock on hand subtracted.
+#[derive(Serialize, Clone, Debug)]
+pub struct ShoppingEntry
+{
+    pub item: String,
+    pub needed: u64,
+    pub in_stock: u64,
+    pub to_buy: u64,
+}
+
+impl fmt::Display for Item
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{} ({})\n  buy price: {}ISK\n  sell price: {}ISK\n  produced: {}",
+            self.name, self.id, self.buy_price, self.sell_price, self.produced)
+    }
+}
+
+
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Blueprint
+{
+    pub name: String,
+    pub bp_id: i64,
+    /// Manufacturing blueprint or reaction formula.
+    #[serde(default)]
+    pub activity: JobActivity,
+    #[serde(alias = "manufacturing_mats")]
+    pub materials: Vec<(String, u64)>,
+    pub material_research: u8,
+    pub time_research: u8,
+    /// Produced items with their quantity per run.
+    #[serde(default)]
+    pub products: Vec<(String, u64)>,
+    /// Base time of one run in seconds.
+    #[serde(default, alias = "manufacturing_time")]
+    pub base_time: u64,
+    /// Set for T2 blueprints whose copies are invented rather than bought.
+    #[serde(default)]
+    pub invention: Option<Invention>,
+    #[serde(default)]
+    pub kind: BlueprintKind,
+    /// Number of originals or copies owned.
+    #[serde(default = "one")]
+    pub quantity: u64,
+    /// Copies only: runs left over all owned copies. Runs of production runs are taken
+    /// off when they are done.
+    #[serde(default)]
+    pub runs: u64,
+    /// Most runs a copy can have, from the SDE.
+    #[serde(default)]
+    pub max_production_limit: u64,
+}
+
+/// Blueprint original (BPO) with unlimited runs or copy (BPC) with a limited number.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum BlueprintKind
+{
+    #[default]
+    Original,
+    Copy,
+}
+
+impl Blueprint
 {
-    let mut known_blueprints = HashMap::<String,T1Blueprint>::new();
-    let bp_path = &format!("{}/blueprints", data_base_dir);
-    let bp_files = fs::read_dir(bp_path).unwrap();
+    pub fn new(id: usize, material_research: u8, time_research: u8, db: &mut Database)
+        -> Result<Blueprint>
+    {
+        let bp_id = id as i64;
+        let name = db.type_name(bp_id)?.to_string();
+        let activity = if db.sde_activity(bp_id, JobActivity::Reaction.sde_name()).is_ok()
+            && db.sde_activity(bp_id, JobActivity::Manufacturing.sde_name()).is_err()
+        {
+            JobActivity::Reaction
+        }
+        else
+        {
+            JobActivity::Manufacturing
+        };
+        if activity == JobActivity::Reaction && (material_research, time_research) != (0, 0)
+        {
+            return Err(Error::NotResearchable(name));
+        }
+        let mats = db.sde_activity(bp_id, activity.sde_name())?.materials.clone();
+        if mats.is_empty()
+        {
+            return Err(Error::MissingField(bp_id, "materials"));
+        }
+
+        let produced = db.sde_products(bp_id, activity)?;
+        let base_time = db.sde_activity_time(bp_id, activity.sde_name())?;
+
+        // Check all types before creating any items
+        for iter in mats.iter().chain(produced.iter())
+        {
+            db.type_name(iter.0)?;
+        }
+
+        let mut materials = Vec::<(String, u64)>::new();
+        for (id, quantity) in mats
+        {
+            let name = db.ensure_item(id, false)?;
+            materials.push((name, quantity));
+        }
+        let mut products = Vec::<(String, u64)>::new();
+        for (id, quantity) in produced
+        {
+            products.push((db.ensure_item(id, true)?, quantity));
+        }
+
+        let max_production_limit = db.sde_max_production_limit(bp_id)?;
+        Ok(Blueprint {name, bp_id, activity, materials, material_research, time_research,
+            products, base_time, invention: None, kind: BlueprintKind::Original, quantity: 1,
+            runs: 0, max_production_limit})
+    }
+}
+
+impl fmt::Display for Blueprint
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        let mut message = format!("{} ({})\n  Material research: {}%\n  Time research: {}%\n  \
+            Base time per run: {}\n", self.name, self.bp_id, self.material_research,
+            self.time_research, utils::format_duration(self.base_time));
+        match self.kind
+        {
+            BlueprintKind::Original => message.push_str(&format!("  Originals owned: {}\n",
+                self.quantity)),
+            BlueprintKind::Copy => message.push_str(&format!("  Copies owned: {}, {} runs \
+                left (at most {} per copy)\n", self.quantity, self.runs,
+                self.max_production_limit)),
+        }
+
+        message.push_str(&format!("  Materials ({}):\n", self.activity));
+        for mat in self.materials.iter()
+        {
+            message.push_str(&format!("    {}: {}\n", mat.0, mat.1));
+        }
+
+        message.push_str("  Products per run:\n");
+        for product in self.products.iter()
+        {
+            message.push_str(&format!("    {}: {}\n", product.0, product.1));
+        }
+
+        if let Some(invention) = &self.invention
+        {
+            message.push_str(&format!("  Invented with {} decryptor, {} runs per copy, \
+                base chance {:.1}%\n", invention.decryptor.map_or("no".to_string(),
+                |d| d.to_string()), invention.runs_per_copy(),
+                invention.base_probability * 100.0));
+        }
+
+        write!(f, "{}", message)
+    }
+}
+
+
+
+/// Progress of a production run.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum RunStatus
+{
+    #[default]
+    Planned,
+    Running,
+    Done,
+    Cancelled,
+}
+
+impl RunStatus
+{
+    pub const ALL: [RunStatus; 4] = [RunStatus::Planned, RunStatus::Running, RunStatus::Done,
+        RunStatus::Cancelled];
+}
+
+impl BlueprintKind
+{
+    pub const ALL: [BlueprintKind; 2] = [BlueprintKind::Original, BlueprintKind::Copy];
+}
+
+impl fmt::Display for BlueprintKind
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            BlueprintKind::Original => write!(f, "original"),
+            BlueprintKind::Copy => write!(f, "copy"),
+        }
+    }
+}
+
+impl FromStr for BlueprintKind
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<BlueprintKind, String>
+    {
+        BlueprintKind::ALL.iter().copied().find(|x| x.to_string() == s)
+            .ok_or_else(|| format!("unknown blueprint kind '{}'", s))
+    }
+}
+
+impl fmt::Display for RunStatus
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            RunStatus::Planned => write!(f, "planned"),
+            RunStatus::Running => write!(f, "running"),
+            RunStatus::Done => write!(f, "done"),
+            RunStatus::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl FromStr for RunStatus
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<RunStatus, String>
+    {
+        RunStatus::ALL.iter().copied().find(|x| x.to_string() == s)
+            .ok_or_else(|| format!("unknown status '{}'", s))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProductionRun
+{
+    /// Assigned when the run is added to the database.
+    #[serde(default)]
+    pub id: u64,
+    /// Unique name, the blueprint name for the first run of a blueprint.
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub status: RunStatus,
+    /// Day the job is planned to be installed, as YYYY-MM-DD.
+    #[serde(default)]
+    pub planned_date: Option<String>,
+    pub blueprint: String,
+    pub materials: Vec<(String, u64)>,
+    /// Produced items with their quantity per run.
+    #[serde(default)]
+    pub products: Vec<(String, u64)>,
+    pub jobruns: u64,
+    /// Installation cost entered by hand, including all sub-builds. When not set it is
+    /// computed from the adjusted prices and the cost index of the facility's solar system.
+    #[serde(default)]
+    pub installation_cost: Option<Isk>,
+    #[serde(default)]
+    pub facility: Facility,
+    /// Expand materials that can be manufactured or reacted into sub-builds. Each of
+    /// them is built or bought as chosen in `choices`, or else as recommended.
+    #[serde(default)]
+    pub build_components: bool,
+    /// Build or buy choices for intermediate materials by item name.
+    #[serde(default)]
+    pub choices: HashMap<String, BuildChoice>,
+    /// Where reaction sub-builds are installed.
+    #[serde(default)]
+    pub refinery: Option<Facility>,
+    /// Whether the materials were taken out of and the products put into the inventory.
+    #[serde(default)]
+    pub materials_consumed: bool,
+    #[serde(default)]
+    pub products_added: bool,
+}
+
+impl ProductionRun
+{
+    /// A run without ID and name, to be passed to `Database::add_productionrun`.
+    pub fn new(blueprint: &str, jobruns: u64, installation_cost: Option<Isk>, facility: Facility,
+        build_components: bool, db: &Database) -> Result<ProductionRun>
+    {
+        let bp = db.get_blueprint(blueprint)
+            .ok_or_else(|| Error::UnknownBlueprint(blueprint.to_string()))?;
+        let products = bp.products.clone();
+        let materials = bp.materials.clone();
+
+        Ok(ProductionRun {id: 0, name: String::new(), status: RunStatus::default(),
+            planned_date: None, blueprint: blueprint.to_string(), materials, products,
+            jobruns, installation_cost, facility, build_components, choices: HashMap::new(),
+            refinery: None, materials_consumed: false, products_added: false})
+    }
+
+    /// Materials of the job. If components are built, the ones that can be produced are
+    /// expanded into their sub-builds.
+    pub fn get_build_tree(&self, db: &Database, fees: &FeeModel) -> Result<Vec<BuildNode>>
+    {
+        let bp = db.get_blueprint(&self.blueprint)
+            .ok_or_else(|| Error::UnknownBlueprint(self.blueprint.clone()))?;
+
+        let mut ret = Vec::<BuildNode>::new();
+        for iter in self.materials.iter()
+        {
+            let item = db.get_item(&iter.0).ok_or_else(|| Error::UnknownItem(iter.0.clone()))?;
+            let count = industry::material_quantity(iter.1, self.jobruns,
+                bp.material_research, &self.facility, bp.activity);
+            ret.push(if self.build_components
+            {
+                buildtree::expand(db, item.id, count, self, fees)?
+            }
+            else
+            {
+                BuildNode::leaf(db, &iter.0, item.id, count, fees)
+            });
+        }
+        Ok(ret)
+    }
+
+    /// Materials that have to be bought for the job and all of its sub-builds.
+    pub fn get_production_materials(&self, db: &Database, fees: &FeeModel)
+        -> Result<Vec<(String, u64)>>
+    {
+        let mut ret = Vec::<(String, u64)>::new();
+        for node in self.get_build_tree(db, fees)?.iter()
+        {
+            node.add_bought_materials(&mut ret);
+        }
+        Ok(ret)
+    }
+
+    pub fn get_material_cost(&self, db: &Database, fees: &FeeModel) -> Result<Isk>
+    {
+        let mut ret = Isk::ZERO;
+        for iter in self.get_production_materials(db, fees)?.iter()
+        {
+            let item = db.get_item(&iter.0).ok_or_else(|| Error::UnknownItem(iter.0.clone()))?;
+            ret += fees.material_price(item) * iter.1
+        }
+        Ok(ret)
+    }
+
+    /// Estimated item value of the job: the materials at ME 0 valued at adjusted prices.
+    pub fn get_estimated_item_value(&self, db: &Database) -> Result<Isk>
+    {
+        let mut ret = Isk::ZERO;
+        for iter in self.materials.iter()
+        {
+            let item = db.get_item(&iter.0).ok_or_else(|| Error::UnknownItem(iter.0.clone()))?;
+            let price = db.adjusted_prices.get(&item.id)
+                .ok_or_else(|| Error::MissingAdjustedPrice(iter.0.clone()))?;
+            let quantity = iter.1.checked_mul(self.jobruns).ok_or_else(||
+                Error::RunLimitExceeded(self.blueprint.clone(),
+                    db.get_blueprint(&self.blueprint).map_or(0, |bp| bp.max_production_limit)))?;
+            ret += *price * quantity;
+        }
+        Ok(ret)
+    }
+
+    /// Installation cost of the job and all sub-builds.
+    pub fn get_installation_cost(&self, db: &Database, fees: &FeeModel) -> Result<Isk>
+    {
+        if let Some(cost) = self.installation_cost
+        {
+            return Ok(cost);
+        }
+        let bp = db.get_blueprint(&self.blueprint)
+            .ok_or_else(|| Error::UnknownBlueprint(self.blueprint.clone()))?;
+        let system = self.facility.solar_system
+            .ok_or_else(|| Error::NoInstallationCost(self.name.clone()))?;
+        let cost_index = db.get_cost_index(system, bp.activity.sde_name())?;
+        let mut ret = industry::installation_cost(self.get_estimated_item_value(db)?, cost_index,
+            &self.facility);
+        for node in self.get_build_tree(db, fees)?.iter()
+        {
+            ret += node.built_jobs().iter().map(|job| job.installation_cost).sum::<Isk>();
+        }
+        Ok(ret)
+    }
+
+    /// Units of every product this run yields over all job runs.
+    pub fn get_units_produced(&self) -> Vec<(String, u64)>
+    {
+        self.products.iter().map(|p| (p.0.clone(), p.1 * self.jobruns)).collect()
+    }
+
+    pub fn get_sell_value(&self, db: &Database, fees: &FeeModel) -> Result<Isk>
+    {
+        let mut ret = Isk::ZERO;
+        for iter in self.get_units_produced().iter()
+        {
+            let item = db.get_item(&iter.0).ok_or_else(|| Error::UnknownItem(iter.0.clone()))?;
+            ret += fees.product_price(item) * iter.1;
+        }
+        Ok(ret)
+    }
+
+    /// Expected cost of inventing the blueprint copies this run consumes, zero if the
+    /// blueprint is not invented or `None` if the invention can never succeed.
+    pub fn get_invention_cost(&self, db: &Database, settings: &Settings) -> Result<Option<Isk>>
+    {
+        let bp = db.get_blueprint(&self.blueprint)
+            .ok_or_else(|| Error::UnknownBlueprint(self.blueprint.clone()))?;
+        let invention = match &bp.invention
+        {
+            Some(invention) => invention,
+            None => return Ok(Some(Isk::ZERO)),
+        };
+        let cost = invention.cost(db, settings)?;
+        Ok(cost.cost_per_run().map(|c| c * self.jobruns))
+    }
+
+    /// Duration of the whole job in seconds.
+    pub fn get_job_time(&self, db: &Database, settings: &Settings) -> Result<u64>
+    {
+        let bp = db.get_blueprint(&self.blueprint)
+            .ok_or_else(|| Error::UnknownBlueprint(self.blueprint.clone()))?;
+        Ok(industry::job_time(bp.base_time, self.jobruns, bp.time_research,
+            &settings.skills, &self.facility, bp.activity))
+    }
+
+    /// Costs and profit of the run, or `None` if it needs a blueprint copy whose invention
+    /// can never succeed.
+    pub fn get_profit(&self, db: &Database, settings: &Settings) -> Result<Option<Profit>>
+    {
+        let material_cost = self.get_material_cost(db, &settings.fees)?;
+        let invention_cost = match self.get_invention_cost(db, settings)?
+        {
+            Some(cost) => cost,
+            None => return Ok(None),
+        };
+        let production_cost = material_cost + self.get_installation_cost(db, &settings.fees)?
+            + invention_cost;
+        let sell_value = self.get_sell_value(db, &settings.fees)?;
+        let raw_profit = sell_value - production_cost;
+        let fees = settings.fees.fees(material_cost, sell_value);
+        let taxed_profit = raw_profit - fees.total();
+        let job_time = self.get_job_time(db, settings)?;
+        let profit_per_hour = if job_time == 0
+        {
+            Isk::ZERO
+        }
+        else
+        {
+            Isk::from_f64(taxed_profit.as_f64() * 3600.0 / job_time as f64)
+        };
+        Ok(Some(Profit {production_cost, invention_cost, sell_value, raw_profit,
+            material_broker_fee: fees.material_broker_fee, sales_tax: fees.sales_tax,
+            sell_broker_fee: fees.sell_broker_fee, fees: fees.total(), taxed_profit, job_time,
+            profit_per_hour}))
+    }
+}
+
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Profit
+{
+    /// Materials, installation and invention costs.
+    pub production_cost: Isk,
+    pub invention_cost: Isk,
+    pub sell_value: Isk,
+    pub raw_profit: Isk,
+    pub material_broker_fee: Isk,
+    pub sales_tax: Isk,
+    pub sell_broker_fee: Isk,
+    /// Sum of all fees and taxes.
+    pub fees: Isk,
+    pub taxed_profit: Isk,
+    /// Job duration in seconds.
+    pub job_time: u64,
+    pub profit_per_hour: Isk,
+}
+
+
+/// Saved data read from the data directory. Files that could not be read or parsed
+/// are moved to the quarantine directory and listed in `skipped`.
+pub struct Resources
+{
+    pub known_blueprints: HashMap<String, Blueprint>,
+    pub known_items: HashMap<String, Item>,
+    /// Production runs by the file they were read from.
+    pub productionruns: HashMap<PathBuf, ProductionRun>,
+    pub skipped: Vec<(PathBuf, Error)>,
+}
+
+impl Resources
+{
+    pub fn report_skipped(&self)
+    {
+        if self.skipped.is_empty()
+        {
+            return;
+        }
+        eprintln!("Ignored {} unreadable data file(s):", self.skipped.len());
+        for (moved_to, e) in self.skipped.iter()
+        {
+            eprintln!("  {}\n    moved to {}", e, moved_to.display());
+        }
+    }
+}
+
+pub fn load_resources(data_base_dir: &Path) -> Result<Resources>
+{
+    let mut skipped = Vec::<(PathBuf, Error)>::new();
+
+    let known_blueprints = load_dir(data_base_dir, "blueprints", &mut skipped,
+        |_, bp: &Blueprint| bp.name.clone())?;
+    let known_items = load_dir(data_base_dir, "items", &mut skipped,
+        |_, item: &Item| item.name.clone())?;
+    let productionruns = load_dir(data_base_dir, "productionruns", &mut skipped,
+        |path, _: &ProductionRun| path.to_path_buf())?;
+
+    Ok(Resources {known_blueprints, known_items, productionruns, skipped})
+}
+
+fn load_dir<K, T, F>(data_base_dir: &Path, sub_dir: &str, skipped: &mut Vec<(PathBuf, Error)>,
+    key: F) -> Result<HashMap<K, T>>
+    where K: Eq + std::hash::Hash, T: DeserializeOwned, F: Fn(&Path, &T) -> K,
+{
+    let mut ret = HashMap::<K, T>::new();
+    let dir = data_base_dir.join(sub_dir);
+    let files = fs::read_dir(&dir).map_err(|e| Error::Io(dir.clone(), e))?;
+
+    for entry in files
+    {
+        let path = entry.map_err(|e| Error::Io(dir.clone(), e))?.path();
+        if !path.is_file()
+        {
+            continue;
+        }
+        match utils::read_json::<T>(&path)
+        {
+            Ok(x) =>
+            {
+                ret.insert(key(&path, &x), x);
+            }
+            Err(e) =>
+            {
+                let moved_to = quarantine(data_base_dir, sub_dir, &path).unwrap_or(path);
+                skipped.push((moved_to, e));
+            }
+        }
+    }
+    Ok(ret)
+}
+
+fn remove_file(path: &Path) -> Result<()>
+{
+    match fs::remove_file(path)
+    {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Error::Io(path.to_path_buf(), e)),
+        _ => Ok(()),
+    }
+}
+
+/// Moves a bad data file out of the way so it is not loaded again. A file quarantined
+/// earlier under the same name is kept by numbering the new one, e.g. `Rifter.json.1`.
+fn quarantine(data_base_dir: &Path, sub_dir: &str, path: &Path) -> Option<PathBuf>
+{
+    let target_dir = data_base_dir.join("quarantine").join(sub_dir);
+    fs::create_dir_all(&target_dir).ok()?;
+    let file_name = path.file_name()?.to_string_lossy().into_owned();
+    let target = (0..).map(|n| match n
+        {
+            0 => target_dir.join(&file_name),
+            n => target_dir.join(format!("{}.{}", file_name, n)),
+        })
+        .find(|target| !target.exists())?;
+    fs::rename(path, &target).ok()?;
+    Some(target)
+}
+
+fn one() -> u64
+{
+    1
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::fees::SellMode;
+    use crate::sde::{Product, SdeBlueprint, SdeType};
+
+    use super::*;
+
+    /// A database of `sde` saving to an empty directory of its own under the system temp
+    /// directory.
+    fn temp_database(name: &str, sde: Sde) -> Database
+    {
+        let dir = std::env::temp_dir()
+            .join(format!("eve_industry_terminal_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        for sub_dir in ["items", "blueprints", "productionruns"].iter()
+        {
+            fs::create_dir_all(dir.join(sub_dir)).unwrap();
+        }
+        let mut db = Database::from_sde(sde);
+        db.data_base_dir = dir;
+        db
+    }
+
+    fn sde_with_types(types: &[(i64, &str)]) -> Sde
+    {
+        let mut sde = Sde::default();
+        for (id, name) in types.iter()
+        {
+            sde.types.insert(*id, SdeType {name: name.to_string(), group_id: 0, published: true});
+        }
+        sde
+    }
+
+    /// Database with a copy of the Rifter Blueprint with 10 runs left, which makes one
+    /// Rifter from 100 Tritanium.
+    fn rifter_database(name: &str) -> Database
+    {
+        let mut sde = sde_with_types(&[(34, "Tritanium"), (587, "Rifter"),
+            (691, "Rifter Blueprint")]);
+        let manufacturing = Activity {time: 6000, materials: vec![(34, 100)],
+            products: vec![Product {type_id: 587, quantity: 1, probability: None}],
+            skills: Vec::new()};
+        sde.blueprints.insert(691, SdeBlueprint {max_production_limit: 300,
+            activities: vec![("manufacturing".to_string(), manufacturing)].into_iter().collect()});
+        let mut db = temp_database(name, sde);
+        db.add_blueprint(691, 10, 20).unwrap();
+        let bp = db.known_blueprints.get_mut("Rifter Blueprint").unwrap();
+        bp.kind = BlueprintKind::Copy;
+        bp.runs = 10;
+        db
+    }
+
+    fn add_run(db: &mut Database, jobruns: u64, status: RunStatus) -> u64
+    {
+        let mut pr = ProductionRun::new("Rifter Blueprint", jobruns, Some(Isk::ZERO),
+            Facility::default(), false, db).unwrap();
+        pr.status = status;
+        db.add_productionrun(pr).unwrap()
+    }
+
+    fn copy_runs(db: &Database) -> u64
+    {
+        db.known_blueprints["Rifter Blueprint"].runs
+    }
+
+    #[test]
+    fn done_runs_use_up_copy_runs_until_set_back()
+    {
+        let mut db = rifter_database("run_status");
+        let id = add_run(&mut db, 4, RunStatus::Planned);
+        assert_eq!(db.available_runs("Rifter Blueprint", None), Some(6));
+
+        db.set_productionrun_status(id, RunStatus::Done).unwrap();
+        assert_eq!(copy_runs(&db), 6);
+        assert_eq!(db.available_runs("Rifter Blueprint", None), Some(6));
+        // Setting a done run back refunds its runs, which it then reserves again
+        db.set_productionrun_status(id, RunStatus::Running).unwrap();
+        assert_eq!(copy_runs(&db), 10);
+        assert_eq!(db.available_runs("Rifter Blueprint", None), Some(6));
+        db.set_productionrun_status(id, RunStatus::Done).unwrap();
+        db.set_productionrun_status(id, RunStatus::Cancelled).unwrap();
+        assert_eq!(copy_runs(&db), 10);
+        assert_eq!(db.available_runs("Rifter Blueprint", None), Some(10));
+        let _ = fs::remove_dir_all(&db.data_base_dir);
+    }
+
+    #[test]
+    fn cancelled_runs_need_copy_runs_to_resume()
+    {
+        let mut db = rifter_database("run_status_cancelled");
+        let cancelled = add_run(&mut db, 6, RunStatus::Cancelled);
+        add_run(&mut db, 6, RunStatus::Planned);
+
+        for status in [RunStatus::Planned, RunStatus::Running, RunStatus::Done].iter()
+        {
+            assert!(matches!(db.set_productionrun_status(cancelled, *status),
+                Err(Error::NotEnoughRuns(_, 4))));
+        }
+        assert_eq!(db.productionruns[&cancelled].status, RunStatus::Cancelled);
+        assert_eq!(copy_runs(&db), 10);
+        let _ = fs::remove_dir_all(&db.data_base_dir);
+    }
+
+    #[test]
+    fn ids_of_deleted_runs_are_not_reused()
+    {
+        let mut db = rifter_database("run_ids");
+        let first = add_run(&mut db, 1, RunStatus::Planned);
+        let second = add_run(&mut db, 1, RunStatus::Planned);
+        assert_eq!((first, second), (1, 2));
+        db.delete_productionrun(second).unwrap();
+        assert_eq!(add_run(&mut db, 1, RunStatus::Planned), 3);
+
+        // A database loaded from the same directory continues from the saved ID
+        let saved: u64 = utils::read_json(&db.data_base_dir.join("last_run_id.json")).unwrap();
+        assert_eq!(saved, 3);
+        db.delete_productionrun(3).unwrap();
+        db.productionruns.clear();
+        db.last_productionrun_id = saved;
+        assert_eq!(add_run(&mut db, 1, RunStatus::Planned), 4);
+        let _ = fs::remove_dir_all(&db.data_base_dir);
+    }
+
+    #[test]
+    fn items_with_stock_are_only_deleted_by_force()
+    {
+        let mut db = rifter_database("delete_item");
+        db.known_blueprints.clear();
+        db.set_stock("Tritanium", "Jita", 5).unwrap();
+        assert!(matches!(db.delete_item("Tritanium", false), Err(Error::ItemInStock(_, 5))));
+        assert!(db.has_item("Tritanium"));
+        db.delete_item("Tritanium", true).unwrap();
+        assert!(!db.has_item("Tritanium"));
+        assert!(!db.data_base_dir.join("items").join("Tritanium.json").exists());
+        db.delete_item("Rifter", false).unwrap();
+        let _ = fs::remove_dir_all(&db.data_base_dir);
+    }
+
+    const FEES: FeeModel = FeeModel {base_sales_tax: 7.5, accounting: 0, broker_relations: 0,
+        faction_standing: 0.0, corp_standing: 0.0, structure_broker_fee: None,
+        sell_mode: SellMode::SellOrder, buy_mode: BuyMode::BuyOrder};
+
+    #[test]
+    fn shopping_list_nets_stock()
+    {
+        let mut db = rifter_database("shopping_list");
+        // 4 runs at ME 10 need 360 Tritanium
+        let id = add_run(&mut db, 4, RunStatus::Planned);
+        db.set_stock("Tritanium", "Jita", 100).unwrap();
+        db.set_stock("Tritanium", "Amarr", 50).unwrap();
+
+        let entry = |location| db.shopping_list(&[id], &FEES, location).unwrap().remove(0);
+        let all = entry(None);
+        assert_eq!((all.item.as_str(), all.needed, all.in_stock, all.to_buy),
+            ("Tritanium", 360, 150, 210));
+        let jita = entry(Some("Jita"));
+        assert_eq!((jita.in_stock, jita.to_buy), (100, 260));
+        assert_eq!(entry(Some("Dodixie")).to_buy, 360);
+        db.set_stock("Tritanium", "Jita", 500).unwrap();
+        let stocked = db.shopping_list(&[id], &FEES, None).unwrap().remove(0);
+        assert_eq!((stocked.in_stock, stocked.to_buy), (550, 0));
+        let _ = fs::remove_dir_all(&db.data_base_dir);
+    }
 
-    for path in bp_files
+    #[test]
+    fn runs_consume_materials_and_add_products_once()
     {
-        let file = File::open(path.unwrap().path()).unwrap();
-        let reader = BufReader::new(file);
-        let bp: T1Blueprint = serde_json::from_reader(reader).unwrap();
-        known_blueprints.insert(bp.name.clone(), bp);
+        let mut db = rifter_database("consume_materials");
+        let id = add_run(&mut db, 4, RunStatus::Planned);
+        db.set_stock("Tritanium", "Jita", 300).unwrap();
+        db.set_stock("Tritanium", "Amarr", 50).unwrap();
+        assert!(matches!(db.consume_materials(id, None, &FEES),
+            Err(Error::NotEnoughStock(_, 350, 360))));
+        assert_eq!(db.stock_of("Tritanium", None), 350);
+
+        // Taken from all locations in alphabetical order
+        db.set_stock("Tritanium", "Jita", 400).unwrap();
+        assert_eq!(db.consume_materials(id, None, &FEES).unwrap(),
+            vec![("Tritanium".to_string(), 360)]);
+        assert_eq!(db.stock_of("Tritanium", Some("Amarr")), 0);
+        assert_eq!(db.stock_of("Tritanium", Some("Jita")), 90);
+        assert!(!db.known_items["Tritanium"].stock.contains_key("Amarr"));
+        assert!(db.shopping_list(&[id], &FEES, None).unwrap().is_empty());
+        assert!(matches!(db.consume_materials(id, None, &FEES),
+            Err(Error::MaterialsConsumed(_))));
+        assert_eq!(db.stock_of("Tritanium", None), 90);
+
+        db.set_stock("Rifter", "Jita", 1).unwrap();
+        assert_eq!(db.add_products(id, "Jita").unwrap(), vec![("Rifter".to_string(), 4)]);
+        assert_eq!(db.stock_of("Rifter", Some("Jita")), 5);
+        assert!(matches!(db.add_products(id, "Jita"), Err(Error::ProductsAdded(_))));
+        assert_eq!(db.stock_of("Rifter", Some("Jita")), 5);
+        // Both are recorded in the saved run
+        let saved: ProductionRun = utils::read_json(&db.data_base_dir.join("productionruns")
+            .join(format!("{}.json", id))).unwrap();
+        assert!(saved.materials_consumed && saved.products_added);
+        let _ = fs::remove_dir_all(&db.data_base_dir);
     }
 
-    let mut known_items = HashMap::<String,Item>::new();
-    let item_path = &format!("{}/items", data_base_dir);
-    let item_files = fs::read_dir(item_path).unwrap();
-    for path in item_files
+    fn asset(item_id: i64, type_id: i64, location_id: i64, location_type: &str, quantity: u64)
+        -> EsiAsset
     {
-        let file = File::open(path.unwrap().path()).unwrap();
-        let reader = BufReader::new(file);
-        let bp: Item = serde_json::from_reader(reader).unwrap();
-        known_items.insert(bp.name.clone(), bp);
+        EsiAsset {item_id, type_id, location_id, location_type: location_type.to_string(),
+            quantity}
     }
 
-    let mut productionruns = HashMap::<String, T1ProductionRun>::new();
-    let productionrun_path = &format!("{}/productionruns", data_base_dir);
-    let productionrun_files = fs::read_dir(productionrun_path).unwrap();
-    for path in productionrun_files
+    #[test]
+    fn assets_in_nested_containers_count_at_the_station()
     {
-        let file = File::open(path.unwrap().path()).unwrap();
-        let reader = BufReader::new(file);
-        let x: T1ProductionRun = serde_json::from_reader(reader).unwrap();
-        productionruns.insert(x.blueprint.clone(), x);
+        let mut db = temp_database("assets", sde_with_types(&[(34, "Tritanium"),
+            (587, "Rifter"), (3467, "Small Secure Container")]));
+        db.known_items.insert("Tritanium".to_string(), Item {name: "Tritanium".to_string(),
+            id: 34, buy_price: Isk::ZERO, sell_price: Isk::ZERO, produced: false,
+            stock: vec![("60003760".to_string(), 5), ("hangar".to_string(), 7)].into_iter()
+                .collect()});
+        // A container in the cargo of a ship, all docked at station 60003760
+        let assets = vec![
+            asset(1, 587, 60003760, "station", 1),
+            asset(2, 3467, 1, "item", 1),
+            asset(3, 34, 2, "item", 100),
+            asset(4, 34, 60003760, "station", 50),
+            asset(5, 99999, 2, "item", 3),
+        ];
+        let (stocked, unknown_types) = db.import_esi_assets(&assets).unwrap();
+
+        assert_eq!(stocked, 3);
+        assert_eq!(unknown_types, vec![99999]);
+        let tritanium = &db.known_items["Tritanium"];
+        assert_eq!(tritanium.stock.get("60003760"), Some(&150));
+        assert_eq!(tritanium.stock.get("hangar"), Some(&7));
+        // Types in the SDE that were not known items are added
+        assert_eq!(db.known_items["Rifter"].stock.get("60003760"), Some(&1));
+        assert_eq!(db.known_items["Small Secure Container"].id, 3467);
+        assert!(db.data_base_dir.join("items").join("Rifter.json").exists());
+        let _ = fs::remove_dir_all(&db.data_base_dir);
     }
-    (known_blueprints, known_items, productionruns)
 }
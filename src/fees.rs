@@ -0,0 +1,230 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::evedata::Item;
+use crate::isk::Isk;
+
+/// How products are sold.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SellMode
+{
+    /// Place sell orders at the item's sell price, paying broker fee and sales tax.
+    SellOrder,
+    /// Sell into buy orders at the item's buy price, paying only sales tax.
+    InstantSell,
+}
+
+/// How materials are bought.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuyMode
+{
+    /// Place buy orders at the item's buy price, paying broker fee.
+    BuyOrder,
+    /// Buy from sell orders at the item's sell price without any fee.
+    InstantBuy,
+}
+
+/// Market fees of a character, derived from skills and standings.
+#[derive(Clone, Copy, Debug)]
+pub struct FeeModel
+{
+    /// Sales tax in percent before the Accounting reduction.
+    pub base_sales_tax: f64,
+    pub accounting: u8,
+    pub broker_relations: u8,
+    pub faction_standing: f64,
+    pub corp_standing: f64,
+    /// Broker fee in percent set by a player structure. Replaces the NPC station fee.
+    pub structure_broker_fee: Option<f64>,
+    pub sell_mode: SellMode,
+    pub buy_mode: BuyMode,
+}
+
+/// Fees of a production run split by where they are paid.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Fees
+{
+    pub material_broker_fee: Isk,
+    pub sales_tax: Isk,
+    pub sell_broker_fee: Isk,
+}
+
+impl FeeModel
+{
+    /// Effective sales tax in percent. Every Accounting level reduces it by 11%.
+    pub fn sales_tax(&self) -> f64
+    {
+        self.base_sales_tax * (1.0 - 0.11 * self.accounting as f64)
+    }
+
+    /// Effective broker fee in percent. The NPC station fee is 3% reduced by
+    /// Broker Relations and standings towards the station owner, but never below 1%.
+    pub fn broker_fee(&self) -> f64
+    {
+        match self.structure_broker_fee
+        {
+            Some(fee) => fee,
+            None => (3.0 - 0.3 * self.broker_relations as f64 - 0.03 * self.faction_standing
+                - 0.02 * self.corp_standing).max(1.0),
+        }
+    }
+
+    /// Unit price paid for a material.
+    pub fn material_price(&self, item: &Item) -> Isk
+    {
+        match self.buy_mode
+        {
+            BuyMode::BuyOrder => item.buy_price,
+            BuyMode::InstantBuy => item.sell_price,
+        }
+    }
+
+    /// Unit price received for a product.
+    pub fn product_price(&self, item: &Item) -> Isk
+    {
+        match self.sell_mode
+        {
+            SellMode::SellOrder => item.sell_price,
+            SellMode::InstantSell => item.buy_price,
+        }
+    }
+
+    /// Fees for buying materials worth `material_cost` and selling products worth
+    /// `sell_value`.
+    pub fn fees(&self, material_cost: Isk, sell_value: Isk) -> Fees
+    {
+        let material_broker_fee = match self.buy_mode
+        {
+            BuyMode::BuyOrder => material_cost.percent(self.broker_fee()),
+            BuyMode::InstantBuy => Isk::ZERO,
+        };
+        let sell_broker_fee = match self.sell_mode
+        {
+            SellMode::SellOrder => sell_value.percent(self.broker_fee()),
+            SellMode::InstantSell => Isk::ZERO,
+        };
+        Fees {material_broker_fee, sales_tax: sell_value.percent(self.sales_tax()),
+            sell_broker_fee}
+    }
+}
+
+impl Fees
+{
+    pub fn total(&self) -> Isk
+    {
+        self.material_broker_fee + self.sales_tax + self.sell_broker_fee
+    }
+}
+
+impl fmt::Display for SellMode
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            SellMode::SellOrder => write!(f, "sell-order"),
+            SellMode::InstantSell => write!(f, "instant-sell"),
+        }
+    }
+}
+
+impl fmt::Display for BuyMode
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            BuyMode::BuyOrder => write!(f, "buy-order"),
+            BuyMode::InstantBuy => write!(f, "instant-buy"),
+        }
+    }
+}
+
+impl FromStr for SellMode
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<SellMode, String>
+    {
+        [SellMode::SellOrder, SellMode::InstantSell].iter().copied()
+            .find(|x| x.to_string() == s)
+            .ok_or_else(|| format!("unknown sell mode '{}'", s))
+    }
+}
+
+impl FromStr for BuyMode
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<BuyMode, String>
+    {
+        [BuyMode::BuyOrder, BuyMode::InstantBuy].iter().copied()
+            .find(|x| x.to_string() == s)
+            .ok_or_else(|| format!("unknown buy mode '{}'", s))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn fee_model(sell_mode: SellMode, buy_mode: BuyMode) -> FeeModel
+    {
+        FeeModel {base_sales_tax: 7.5, accounting: 5, broker_relations: 5, faction_standing: 5.0,
+            corp_standing: 5.0, structure_broker_fee: None, sell_mode, buy_mode}
+    }
+
+    fn assert_close(actual: f64, expected: f64)
+    {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn skills_and_standings_reduce_fees()
+    {
+        let fees = fee_model(SellMode::SellOrder, BuyMode::BuyOrder);
+        assert_close(fees.sales_tax(), 3.375);
+        assert_close(fees.broker_fee(), 1.25);
+        // The NPC broker fee never drops below 1%
+        let standings = FeeModel {faction_standing: 10.0, corp_standing: 10.0,
+            broker_relations: 5, ..fees};
+        assert_close(standings.broker_fee(), 1.0);
+        let structure = FeeModel {structure_broker_fee: Some(0.5), ..fees};
+        assert_close(structure.broker_fee(), 0.5);
+    }
+
+    #[test]
+    fn fees_depend_on_order_modes()
+    {
+        let material_cost = Isk::from_f64(1_000_000.0);
+        let sell_value = Isk::from_f64(2_000_000.0);
+        let orders = fee_model(SellMode::SellOrder, BuyMode::BuyOrder)
+            .fees(material_cost, sell_value);
+        assert_eq!(orders.material_broker_fee, Isk::from_f64(12_500.0));
+        assert_eq!(orders.sales_tax, Isk::from_f64(67_500.0));
+        assert_eq!(orders.sell_broker_fee, Isk::from_f64(25_000.0));
+        assert_eq!(orders.total(), Isk::from_f64(105_000.0));
+
+        let instant = fee_model(SellMode::InstantSell, BuyMode::InstantBuy)
+            .fees(material_cost, sell_value);
+        assert_eq!(instant.material_broker_fee, Isk::ZERO);
+        assert_eq!(instant.sell_broker_fee, Isk::ZERO);
+        assert_eq!(instant.total(), Isk::from_f64(67_500.0));
+    }
+
+    #[test]
+    fn prices_depend_on_order_modes()
+    {
+        let item = Item {name: "Tritanium".to_string(), id: 34, buy_price: Isk::from_cents(400),
+            sell_price: Isk::from_cents(500), produced: false, stock: HashMap::new()};
+        let orders = fee_model(SellMode::SellOrder, BuyMode::BuyOrder);
+        assert_eq!(orders.material_price(&item), item.buy_price);
+        assert_eq!(orders.product_price(&item), item.sell_price);
+        let instant = fee_model(SellMode::InstantSell, BuyMode::InstantBuy);
+        assert_eq!(instant.material_price(&item), item.sell_price);
+        assert_eq!(instant.product_price(&item), item.buy_price);
+    }
+}
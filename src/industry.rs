@@ -0,0 +1,513 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Serialize, Deserialize};
+
+use crate::isk::Isk;
+
+/// SCC surcharge added to every job in percent of the estimated item value.
+pub const SCC_SURCHARGE: f64 = 4.0;
+
+/// Copying, invention and research jobs are charged on this percentage of the estimated
+/// item value of the blueprint's manufacturing job.
+pub const SCIENCE_JOB_EIV_PERCENT: f64 = 2.0;
+
+/// Industry activity that turns a blueprint's materials into its products.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum JobActivity
+{
+    #[default]
+    Manufacturing,
+    /// Moon material reactions, installed in refineries from reaction formulas.
+    Reaction,
+}
+
+/// Structure the job is installed in. Engineering Complexes have manufacturing role
+/// bonuses; reactions can only be run in Refineries.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Structure
+{
+    Station,
+    #[serde(alias = "EngineeringComplex")]
+    Raitaru,
+    Azbel,
+    Sotiyo,
+    Athanor,
+    Tatara,
+}
+
+/// Material or time efficiency rig fitted to the structure.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Rig
+{
+    #[default]
+    None,
+    T1,
+    T2,
+}
+
+/// Security band of the solar system, used to scale rig bonuses.
+/// Wormhole space uses the same multiplier as null security.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Security
+{
+    High,
+    Low,
+    Null,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Facility
+{
+    pub structure: Structure,
+    pub me_rig: Rig,
+    #[serde(default)]
+    pub te_rig: Rig,
+    pub security: Security,
+    /// Solar system used to look up the cost index. Without one the installation cost
+    /// has to be entered by hand.
+    #[serde(default)]
+    pub solar_system: Option<i64>,
+    /// Facility tax in percent of the estimated item value.
+    #[serde(default = "default_facility_tax")]
+    pub facility_tax: f64,
+}
+
+fn default_facility_tax() -> f64
+{
+    0.25
+}
+
+/// Character skills and implants that affect industry jobs.
+#[derive(Clone, Copy, Debug)]
+pub struct Skills
+{
+    pub industry: u8,
+    pub advanced_industry: u8,
+    /// Manufacturing time reduction of the implant in percent.
+    pub implant_time_bonus: f64,
+    /// Encryption Methods skill of the invented blueprint's race.
+    pub encryption: u8,
+    /// Level of both science skills required by the datacores.
+    pub science: u8,
+    pub reactions: u8,
+    /// Metallurgy, reduces material research time.
+    pub metallurgy: u8,
+    /// Research, reduces time research time.
+    pub research: u8,
+    /// The Science skill itself, which reduces copying time.
+    pub copying: u8,
+}
+
+impl JobActivity
+{
+    pub const ALL: [JobActivity; 2] = [JobActivity::Manufacturing, JobActivity::Reaction];
+
+    /// Name of the activity in the SDE and in ESI cost indices.
+    pub fn sde_name(self) -> &'static str
+    {
+        match self
+        {
+            JobActivity::Manufacturing => "manufacturing",
+            JobActivity::Reaction => "reaction",
+        }
+    }
+}
+
+impl Structure
+{
+    pub const ALL: [Structure; 6] = [Structure::Station, Structure::Raitaru, Structure::Azbel,
+        Structure::Sotiyo, Structure::Athanor, Structure::Tatara];
+
+    pub fn is_refinery(self) -> bool
+    {
+        matches!(self, Structure::Athanor | Structure::Tatara)
+    }
+
+    pub fn material_multiplier(self, activity: JobActivity) -> f64
+    {
+        match (activity, self)
+        {
+            (JobActivity::Manufacturing, Structure::Raitaru | Structure::Azbel
+                | Structure::Sotiyo) => 0.99,
+            _ => 1.0,
+        }
+    }
+
+    pub fn time_multiplier(self, activity: JobActivity) -> f64
+    {
+        match (activity, self)
+        {
+            (JobActivity::Manufacturing, Structure::Raitaru) => 0.85,
+            (JobActivity::Manufacturing, Structure::Azbel) => 0.80,
+            (JobActivity::Manufacturing, Structure::Sotiyo) => 0.70,
+            (JobActivity::Reaction, Structure::Tatara) => 0.75,
+            _ => 1.0,
+        }
+    }
+
+    /// Reduction of the system cost index part of the job cost.
+    pub fn job_cost_multiplier(self) -> f64
+    {
+        match self
+        {
+            Structure::Station => 1.0,
+            Structure::Raitaru => 0.97,
+            Structure::Azbel => 0.96,
+            Structure::Sotiyo => 0.95,
+            Structure::Athanor | Structure::Tatara => 1.0,
+        }
+    }
+}
+
+impl Rig
+{
+    pub const ALL: [Rig; 3] = [Rig::None, Rig::T1, Rig::T2];
+
+    /// Material reduction in percent before the security scaling.
+    pub fn material_bonus(self) -> f64
+    {
+        match self
+        {
+            Rig::None => 0.0,
+            Rig::T1 => 2.0,
+            Rig::T2 => 2.4,
+        }
+    }
+
+    /// Time reduction in percent before the security scaling.
+    pub fn time_bonus(self) -> f64
+    {
+        match self
+        {
+            Rig::None => 0.0,
+            Rig::T1 => 20.0,
+            Rig::T2 => 24.0,
+        }
+    }
+}
+
+impl Security
+{
+    pub const ALL: [Security; 3] = [Security::High, Security::Low, Security::Null];
+
+    /// Scaling of rig bonuses. Reaction rigs only work in low and null security space and
+    /// scale less than manufacturing rigs.
+    pub fn rig_multiplier(self, activity: JobActivity) -> f64
+    {
+        match (activity, self)
+        {
+            (JobActivity::Manufacturing, Security::High) => 1.0,
+            (JobActivity::Manufacturing, Security::Low) => 1.9,
+            (JobActivity::Manufacturing, Security::Null) => 2.1,
+            (JobActivity::Reaction, Security::High) => 0.0,
+            (JobActivity::Reaction, Security::Low) => 1.0,
+            (JobActivity::Reaction, Security::Null) => 1.1,
+        }
+    }
+}
+
+impl Default for Facility
+{
+    fn default() -> Facility
+    {
+        Facility {structure: Structure::Station, me_rig: Rig::None, te_rig: Rig::None,
+            security: Security::High, solar_system: None, facility_tax: default_facility_tax()}
+    }
+}
+
+impl Facility
+{
+    /// Combined structure and rig material multiplier.
+    pub fn material_multiplier(&self, activity: JobActivity) -> f64
+    {
+        let rig = 1.0 - self.me_rig.material_bonus() * self.security.rig_multiplier(activity)
+            / 100.0;
+        self.structure.material_multiplier(activity) * rig
+    }
+
+    /// Combined structure and rig time multiplier.
+    pub fn time_multiplier(&self, activity: JobActivity) -> f64
+    {
+        let rig = 1.0 - self.te_rig.time_bonus() * self.security.rig_multiplier(activity) / 100.0;
+        self.structure.time_multiplier(activity) * rig
+    }
+
+    /// Research and copying get the same structure and rig time bonuses as manufacturing.
+    pub fn science_time_multiplier(&self) -> f64
+    {
+        self.time_multiplier(JobActivity::Manufacturing)
+    }
+
+    /// Explains why the activity cannot be installed in this facility, if it cannot.
+    pub fn check(&self, activity: JobActivity) -> std::result::Result<(), String>
+    {
+        match activity
+        {
+            JobActivity::Manufacturing if self.structure.is_refinery() =>
+                Err(format!("{} cannot manufacture", self.structure)),
+            JobActivity::Reaction if !self.structure.is_refinery() =>
+                Err("reactions need an athanor or tatara".to_string()),
+            JobActivity::Reaction if self.security == Security::High =>
+                Err("reactions cannot be run in high security space".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Skills
+{
+    pub fn time_multiplier(&self, activity: JobActivity) -> f64
+    {
+        match activity
+        {
+            JobActivity::Manufacturing => (1.0 - 0.04 * self.industry as f64)
+                * (1.0 - 0.03 * self.advanced_industry as f64)
+                * (1.0 - self.implant_time_bonus / 100.0),
+            JobActivity::Reaction => 1.0 - 0.04 * self.reactions as f64,
+        }
+    }
+}
+
+/// Quantity of one material consumed by a job, following the in-game formula:
+/// the reduced amount is rounded to two decimals and then rounded up for the whole job,
+/// and a job never uses less than one unit per run.
+pub fn material_quantity(base_quantity: u64, runs: u64, material_research: u8,
+    facility: &Facility, activity: JobActivity) -> u64
+{
+    let me_multiplier = 1.0 - material_research as f64 / 100.0;
+    let reduced = base_quantity as f64 * runs as f64 * me_multiplier
+        * facility.material_multiplier(activity);
+    let rounded = ((reduced * 100.0).round() / 100.0).ceil() as u64;
+    rounded.max(runs)
+}
+
+/// Duration of a job in seconds from the blueprint's base time per run.
+pub fn job_time(base_time: u64, runs: u64, time_research: u8, skills: &Skills,
+    facility: &Facility, activity: JobActivity) -> u64
+{
+    let te_multiplier = 1.0 - time_research as f64 / 100.0;
+    (base_time as f64 * runs as f64 * te_multiplier * skills.time_multiplier(activity)
+        * facility.time_multiplier(activity)).round() as u64
+}
+
+/// Installation cost of a job: the estimated item value times the system cost index
+/// (reduced by the structure bonus) plus facility tax and SCC surcharge.
+pub fn installation_cost(estimated_item_value: Isk, cost_index: f64, facility: &Facility) -> Isk
+{
+    let gross = Isk::from_f64(estimated_item_value.as_f64() * cost_index
+        * facility.structure.job_cost_multiplier());
+    gross + estimated_item_value.percent(facility.facility_tax)
+        + estimated_item_value.percent(SCC_SURCHARGE)
+}
+
+impl fmt::Display for JobActivity
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{}", self.sde_name())
+    }
+}
+
+impl fmt::Display for Structure
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            Structure::Station => write!(f, "station"),
+            Structure::Raitaru => write!(f, "raitaru"),
+            Structure::Azbel => write!(f, "azbel"),
+            Structure::Sotiyo => write!(f, "sotiyo"),
+            Structure::Athanor => write!(f, "athanor"),
+            Structure::Tatara => write!(f, "tatara"),
+        }
+    }
+}
+
+impl fmt::Display for Rig
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            Rig::None => write!(f, "none"),
+            Rig::T1 => write!(f, "t1"),
+            Rig::T2 => write!(f, "t2"),
+        }
+    }
+}
+
+impl fmt::Display for Security
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            Security::High => write!(f, "high"),
+            Security::Low => write!(f, "low"),
+            Security::Null => write!(f, "null"),
+        }
+    }
+}
+
+impl fmt::Display for Facility
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{}, ME rig: {}, TE rig: {}, {}sec", self.structure, self.me_rig, self.te_rig,
+            self.security)?;
+        if let Some(system) = self.solar_system
+        {
+            write!(f, ", system {}, tax {}%", system, self.facility_tax)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Structure
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Structure, String>
+    {
+        Structure::ALL.iter().copied().find(|x| x.to_string() == s)
+            .ok_or_else(|| format!("unknown structure '{}'", s))
+    }
+}
+
+impl FromStr for Rig
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Rig, String>
+    {
+        Rig::ALL.iter().copied().find(|x| x.to_string() == s)
+            .ok_or_else(|| format!("unknown rig '{}'", s))
+    }
+}
+
+impl FromStr for Security
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Security, String>
+    {
+        Security::ALL.iter().copied().find(|x| x.to_string() == s)
+            .ok_or_else(|| format!("unknown security '{}'", s))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn facility(structure: Structure, rig: Rig, security: Security) -> Facility
+    {
+        Facility {structure, me_rig: rig, te_rig: rig, security, ..Facility::default()}
+    }
+
+    fn assert_close(actual: f64, expected: f64)
+    {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    const NO_SKILLS: Skills = Skills {industry: 0, advanced_industry: 0, implant_time_bonus: 0.0,
+        encryption: 0, science: 0, reactions: 0, metallurgy: 0, research: 0, copying: 0};
+
+    #[test]
+    fn material_quantity_is_rounded_up_per_job()
+    {
+        let station = Facility::default();
+        let manufacturing = JobActivity::Manufacturing;
+        // 7 * 0.9 = 6.3 per run would be 21 if every run was rounded up
+        assert_eq!(material_quantity(7, 3, 10, &station, manufacturing), 19);
+        // 211 * 0.9 * 0.99 = 188.001 is rounded to two decimals before rounding up
+        let raitaru = facility(Structure::Raitaru, Rig::None, Security::High);
+        assert_eq!(material_quantity(211, 1, 10, &raitaru, manufacturing), 188);
+        // 1 * 10 * 0.9 = 9, but every run needs at least one unit
+        assert_eq!(material_quantity(1, 10, 10, &station, manufacturing), 10);
+    }
+
+    #[test]
+    fn material_research_levels()
+    {
+        let station = Facility::default();
+        assert_eq!(material_quantity(100, 10, 0, &station, JobActivity::Manufacturing), 1000);
+        assert_eq!(material_quantity(100, 10, 10, &station, JobActivity::Manufacturing), 900);
+    }
+
+    #[test]
+    fn engineering_complex_role_bonuses()
+    {
+        let manufacturing = JobActivity::Manufacturing;
+        for (structure, time, job_cost) in [(Structure::Raitaru, 0.85, 0.97),
+            (Structure::Azbel, 0.80, 0.96), (Structure::Sotiyo, 0.70, 0.95)].iter().copied()
+        {
+            let f = facility(structure, Rig::None, Security::High);
+            assert_close(f.material_multiplier(manufacturing), 0.99);
+            assert_close(f.time_multiplier(manufacturing), time);
+            assert_close(structure.job_cost_multiplier(), job_cost);
+        }
+        let station = Facility::default();
+        assert_close(station.material_multiplier(manufacturing), 1.0);
+        assert_close(station.time_multiplier(manufacturing), 1.0);
+
+        // 3600s at TE 20 with Industry V and Advanced Industry V in a Sotiyo
+        let skills = Skills {industry: 5, advanced_industry: 5, ..NO_SKILLS};
+        let sotiyo = facility(Structure::Sotiyo, Rig::None, Security::High);
+        assert_eq!(job_time(3600, 1, 20, &skills, &sotiyo, manufacturing), 1371);
+    }
+
+    #[test]
+    fn manufacturing_rigs_scale_with_security()
+    {
+        let manufacturing = JobActivity::Manufacturing;
+        for (rig, security, material, time) in [
+            (Rig::T1, Security::High, 0.99 * 0.98, 0.85 * 0.80),
+            (Rig::T1, Security::Low, 0.99 * 0.962, 0.85 * 0.62),
+            (Rig::T1, Security::Null, 0.99 * 0.958, 0.85 * 0.58),
+            (Rig::T2, Security::High, 0.99 * 0.976, 0.85 * 0.76),
+            (Rig::T2, Security::Low, 0.99 * 0.9544, 0.85 * 0.544),
+            (Rig::T2, Security::Null, 0.99 * 0.9496, 0.85 * 0.496)].iter().copied()
+        {
+            let f = facility(Structure::Raitaru, rig, security);
+            assert_close(f.material_multiplier(manufacturing), material);
+            assert_close(f.time_multiplier(manufacturing), time);
+        }
+    }
+
+    #[test]
+    fn tatara_reaction_rigs()
+    {
+        let reaction = JobActivity::Reaction;
+        for (rig, security, material, time) in [
+            (Rig::None, Security::Low, 1.0, 0.75),
+            (Rig::T1, Security::Low, 0.98, 0.75 * 0.80),
+            (Rig::T1, Security::Null, 0.978, 0.75 * 0.78),
+            (Rig::T2, Security::Low, 0.976, 0.75 * 0.76),
+            (Rig::T2, Security::Null, 0.9736, 0.75 * 0.736)].iter().copied()
+        {
+            let f = facility(Structure::Tatara, rig, security);
+            assert_close(f.material_multiplier(reaction), material);
+            assert_close(f.time_multiplier(reaction), time);
+        }
+        // Engineering complexes give reactions no bonus
+        let raitaru = facility(Structure::Raitaru, Rig::None, Security::Null);
+        assert_close(raitaru.time_multiplier(reaction), 1.0);
+        assert!(facility(Structure::Tatara, Rig::T2, Security::High).check(reaction).is_err());
+    }
+
+    #[test]
+    fn installation_cost_adds_tax_and_scc_surcharge()
+    {
+        let eiv = Isk::from_f64(1_000_000.0);
+        // 1,000,000 * 0.05 + 0.25% facility tax + 4% SCC
+        assert_eq!(installation_cost(eiv, 0.05, &Facility::default()), Isk::from_f64(92_500.0));
+        // The Azbel reduces only the cost index part
+        let azbel = Facility {facility_tax: 1.0,
+            ..facility(Structure::Azbel, Rig::None, Security::High)};
+        assert_eq!(installation_cost(eiv, 0.05, &azbel), Isk::from_f64(98_000.0));
+    }
+}
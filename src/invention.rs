@@ -0,0 +1,389 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Serialize, Deserialize};
+
+use crate::error::{Error, Result};
+use crate::evedata::Database;
+use crate::industry::{self, Facility, JobActivity, Skills};
+use crate::isk::Isk;
+use crate::settings::Settings;
+
+/// ME and TE of every successfully invented blueprint copy before decryptor modifiers.
+pub const BASE_MATERIAL_RESEARCH: i8 = 2;
+pub const BASE_TIME_RESEARCH: i8 = 4;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decryptor
+{
+    Accelerant,
+    Attainment,
+    Augmentation,
+    OptimizedAttainment,
+    OptimizedAugmentation,
+    Parity,
+    Process,
+    Symmetry,
+}
+
+/// Invention of a T2 blueprint copy from copies of its T1 blueprint.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Invention
+{
+    /// T1 blueprint that is copied and invented on.
+    pub source_bp_id: i64,
+    /// Datacores consumed per invention run.
+    pub datacores: Vec<(String, u64)>,
+    pub base_probability: f64,
+    /// Runs of an invented copy before decryptor modifiers.
+    pub base_runs: u64,
+    pub decryptor: Option<Decryptor>,
+    /// Where the copy and invention jobs are installed. Without a solar system their
+    /// installation costs are not included.
+    pub facility: Facility,
+}
+
+/// Expected costs of inventing one T2 blueprint copy.
+#[derive(Serialize, Clone, Debug)]
+pub struct InventionCost
+{
+    pub probability: f64,
+    pub runs_per_copy: u64,
+    /// Datacores of one invention attempt.
+    pub datacore_cost: Isk,
+    pub decryptor_cost: Isk,
+    pub invention_job_cost: Isk,
+    /// Installation cost of the one-run T1 copy consumed by an attempt.
+    pub copy_job_cost: Isk,
+    pub cost_per_attempt: Isk,
+    /// Cost per attempt divided by the success probability, or `None` if the invention
+    /// can never succeed.
+    pub cost_per_success: Option<Isk>,
+}
+
+impl InventionCost
+{
+    /// Expected invention cost of one run of an invented copy.
+    pub fn cost_per_run(&self) -> Option<Isk>
+    {
+        self.cost_per_success.map(|c| Isk::from_f64(c.as_f64() / self.runs_per_copy as f64))
+    }
+}
+
+impl Decryptor
+{
+    pub const ALL: [Decryptor; 8] = [Decryptor::Accelerant, Decryptor::Attainment,
+        Decryptor::Augmentation, Decryptor::OptimizedAttainment,
+        Decryptor::OptimizedAugmentation, Decryptor::Parity, Decryptor::Process,
+        Decryptor::Symmetry];
+
+    pub fn type_id(self) -> i64
+    {
+        match self
+        {
+            Decryptor::Accelerant => 34201,
+            Decryptor::Attainment => 34202,
+            Decryptor::Augmentation => 34203,
+            Decryptor::Parity => 34204,
+            Decryptor::Process => 34205,
+            Decryptor::Symmetry => 34206,
+            Decryptor::OptimizedAttainment => 34207,
+            Decryptor::OptimizedAugmentation => 34208,
+        }
+    }
+
+    pub fn probability_multiplier(self) -> f64
+    {
+        match self
+        {
+            Decryptor::Accelerant => 1.2,
+            Decryptor::Attainment => 1.8,
+            Decryptor::Augmentation => 0.6,
+            Decryptor::OptimizedAttainment => 1.9,
+            Decryptor::OptimizedAugmentation => 0.9,
+            Decryptor::Parity => 1.5,
+            Decryptor::Process => 1.1,
+            Decryptor::Symmetry => 1.0,
+        }
+    }
+
+    pub fn runs_modifier(self) -> u64
+    {
+        match self
+        {
+            Decryptor::Accelerant => 1,
+            Decryptor::Attainment => 4,
+            Decryptor::Augmentation => 9,
+            Decryptor::OptimizedAttainment => 2,
+            Decryptor::OptimizedAugmentation => 7,
+            Decryptor::Parity => 3,
+            Decryptor::Process => 0,
+            Decryptor::Symmetry => 2,
+        }
+    }
+
+    pub fn material_research_modifier(self) -> i8
+    {
+        match self
+        {
+            Decryptor::Accelerant => 2,
+            Decryptor::Attainment => -1,
+            Decryptor::Augmentation => -2,
+            Decryptor::OptimizedAttainment => 1,
+            Decryptor::OptimizedAugmentation => 2,
+            Decryptor::Parity => 1,
+            Decryptor::Process => 3,
+            Decryptor::Symmetry => 1,
+        }
+    }
+
+    pub fn time_research_modifier(self) -> i8
+    {
+        match self
+        {
+            Decryptor::Accelerant => 10,
+            Decryptor::Attainment => 4,
+            Decryptor::Augmentation => 2,
+            Decryptor::OptimizedAttainment => -2,
+            Decryptor::OptimizedAugmentation => 0,
+            Decryptor::Parity => -2,
+            Decryptor::Process => 6,
+            Decryptor::Symmetry => 8,
+        }
+    }
+}
+
+impl Invention
+{
+    /// Success chance of one attempt. Encryption Methods adds 1/40 and each of the two
+    /// datacore science skills 1/30 per level before the decryptor multiplier.
+    pub fn probability(&self, skills: &Skills) -> f64
+    {
+        let skill_multiplier = 1.0 + skills.encryption as f64 / 40.0
+            + 2.0 * skills.science as f64 / 30.0;
+        let decryptor = self.decryptor.map_or(1.0, |d| d.probability_multiplier());
+        (self.base_probability * skill_multiplier * decryptor).min(1.0)
+    }
+
+    pub fn runs_per_copy(&self) -> u64
+    {
+        self.base_runs + self.decryptor.map_or(0, |d| d.runs_modifier())
+    }
+
+    pub fn material_research(&self) -> u8
+    {
+        (BASE_MATERIAL_RESEARCH + self.decryptor.map_or(0, |d| d.material_research_modifier()))
+            as u8
+    }
+
+    pub fn time_research(&self) -> u8
+    {
+        (BASE_TIME_RESEARCH + self.decryptor.map_or(0, |d| d.time_research_modifier())) as u8
+    }
+
+    pub fn cost(&self, db: &Database, settings: &Settings) -> Result<InventionCost>
+    {
+        let fees = &settings.fees;
+        let mut datacore_cost = Isk::ZERO;
+        for (name, quantity) in self.datacores.iter()
+        {
+            let item = db.get_item(name).ok_or_else(|| Error::UnknownItem(name.clone()))?;
+            datacore_cost += fees.material_price(item) * *quantity;
+        }
+        let decryptor_cost = match self.decryptor
+        {
+            Some(decryptor) =>
+            {
+                let name = db.type_name(decryptor.type_id())?;
+                fees.material_price(db.get_item(name)
+                    .ok_or_else(|| Error::UnknownItem(name.to_string()))?)
+            }
+            None => Isk::ZERO,
+        };
+        let (invention_job_cost, copy_job_cost) = match self.facility.solar_system
+        {
+            Some(system) =>
+            {
+                let eiv = db.sde_estimated_item_value(self.source_bp_id,
+                    JobActivity::Manufacturing)?.percent(industry::SCIENCE_JOB_EIV_PERCENT);
+                (industry::installation_cost(eiv, db.get_cost_index(system, "invention")?,
+                    &self.facility),
+                industry::installation_cost(eiv, db.get_cost_index(system, "copying")?,
+                    &self.facility))
+            }
+            None => (Isk::ZERO, Isk::ZERO),
+        };
+
+        let probability = self.probability(&settings.skills);
+        let cost_per_attempt = datacore_cost + decryptor_cost + invention_job_cost + copy_job_cost;
+        let cost_per_success = if probability > 0.0
+        {
+            Some(Isk::from_f64(cost_per_attempt.as_f64() / probability))
+        }
+        else
+        {
+            None
+        };
+        Ok(InventionCost {probability, runs_per_copy: self.runs_per_copy(), datacore_cost,
+            decryptor_cost, invention_job_cost, copy_job_cost, cost_per_attempt,
+            cost_per_success})
+    }
+}
+
+impl fmt::Display for Decryptor
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            Decryptor::Accelerant => write!(f, "accelerant"),
+            Decryptor::Attainment => write!(f, "attainment"),
+            Decryptor::Augmentation => write!(f, "augmentation"),
+            Decryptor::OptimizedAttainment => write!(f, "optimized-attainment"),
+            Decryptor::OptimizedAugmentation => write!(f, "optimized-augmentation"),
+            Decryptor::Parity => write!(f, "parity"),
+            Decryptor::Process => write!(f, "process"),
+            Decryptor::Symmetry => write!(f, "symmetry"),
+        }
+    }
+}
+
+impl FromStr for Decryptor
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Decryptor, String>
+    {
+        Decryptor::ALL.iter().copied().find(|x| x.to_string() == s)
+            .ok_or_else(|| format!("unknown decryptor '{}'", s))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::collections::HashMap;
+
+    use crate::evedata::Item;
+    use crate::sde::{Activity, Sde, SdeBlueprint, SdeType};
+
+    use super::*;
+
+    const NO_SKILLS: Skills = Skills {industry: 0, advanced_industry: 0, implant_time_bonus: 0.0,
+        encryption: 0, science: 0, reactions: 0, metallurgy: 0, research: 0, copying: 0};
+
+    fn assert_close(actual: f64, expected: f64)
+    {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    /// Inventing a T2 frigate from the Rifter Blueprint, whose materials have an estimated
+    /// value of 1,000,000 ISK.
+    fn invention(decryptor: Option<Decryptor>) -> Invention
+    {
+        Invention {source_bp_id: 691, datacores: vec![
+                ("Datacore - Mechanical Engineering".to_string(), 2),
+                ("Datacore - Electronic Engineering".to_string(), 2)],
+            base_probability: 0.34, base_runs: 10, decryptor,
+            facility: Facility {solar_system: Some(1), ..Facility::default()}}
+    }
+
+    fn item(id: i64, name: &str, buy_price: f64) -> (String, Item)
+    {
+        (name.to_string(), Item {name: name.to_string(), id, buy_price: Isk::from_f64(buy_price),
+            sell_price: Isk::ZERO, produced: false, stock: HashMap::new()})
+    }
+
+    fn database() -> Database
+    {
+        let mut sde = Sde::default();
+        for (id, name) in [(34, "Tritanium"), (691, "Rifter Blueprint"),
+            (34202, "Attainment Decryptor")].iter()
+        {
+            sde.types.insert(*id, SdeType {name: name.to_string(), group_id: 0, published: true});
+        }
+        let manufacturing = Activity {time: 6000, materials: vec![(34, 100)],
+            ..Activity::default()};
+        sde.blueprints.insert(691, SdeBlueprint {max_production_limit: 300,
+            activities: vec![("manufacturing".to_string(), manufacturing)].into_iter().collect()});
+        let mut db = Database::from_sde(sde);
+        db.known_items = vec![item(20424, "Datacore - Mechanical Engineering", 50_000.0),
+            item(20418, "Datacore - Electronic Engineering", 40_000.0),
+            item(34202, "Attainment Decryptor", 500_000.0)].into_iter().collect();
+        db.adjusted_prices.insert(34, Isk::from_f64(10_000.0));
+        db.cost_indices.insert(1, vec![("invention".to_string(), 0.04),
+            ("copying".to_string(), 0.02)].into_iter().collect());
+        db
+    }
+
+    #[test]
+    fn probability_with_skills_and_decryptor()
+    {
+        let skills = Skills {encryption: 4, science: 4, ..NO_SKILLS};
+        assert_close(invention(None).probability(&NO_SKILLS), 0.34);
+        // 34% * (1 + 4/40 + 2 * 4/30)
+        assert_close(invention(None).probability(&skills), 0.34 * (1.0 + 0.1 + 8.0 / 30.0));
+        assert_close(invention(Some(Decryptor::Attainment)).probability(&skills), 0.8364);
+        assert_close(invention(Some(Decryptor::Augmentation)).probability(&NO_SKILLS), 0.204);
+        // A chance above 100% is capped
+        let all_v = Skills {encryption: 5, science: 5, ..NO_SKILLS};
+        let mut likely = invention(Some(Decryptor::OptimizedAttainment));
+        likely.base_probability = 0.5;
+        assert_close(likely.probability(&all_v), 1.0);
+    }
+
+    #[test]
+    fn decryptors_change_runs_me_and_te()
+    {
+        // Runs, ME and TE of a copy invented with 10 base runs
+        let expected = [
+            (None, 10, 2, 4),
+            (Some(Decryptor::Accelerant), 11, 4, 14),
+            (Some(Decryptor::Attainment), 14, 1, 8),
+            (Some(Decryptor::Augmentation), 19, 0, 6),
+            (Some(Decryptor::OptimizedAttainment), 12, 3, 2),
+            (Some(Decryptor::OptimizedAugmentation), 17, 4, 4),
+            (Some(Decryptor::Parity), 13, 3, 2),
+            (Some(Decryptor::Process), 10, 5, 10),
+            (Some(Decryptor::Symmetry), 12, 3, 12),
+        ];
+        for (decryptor, runs, me, te) in expected.iter()
+        {
+            let invention = invention(*decryptor);
+            assert_eq!((invention.runs_per_copy(), invention.material_research(),
+                invention.time_research()), (*runs, *me, *te), "{:?}", decryptor);
+        }
+    }
+
+    #[test]
+    fn cost_per_success()
+    {
+        let db = database();
+        let settings = Settings::with_skills(Skills {encryption: 4, science: 4, ..NO_SKILLS});
+        let cost = invention(Some(Decryptor::Attainment)).cost(&db, &settings).unwrap();
+
+        assert_eq!(cost.datacore_cost, Isk::from_f64(180_000.0));
+        assert_eq!(cost.decryptor_cost, Isk::from_f64(500_000.0));
+        // 2% of the estimated value at 4% and 2% cost index, 0.25% tax and 4% SCC surcharge
+        assert_eq!(cost.invention_job_cost, Isk::from_f64(1_650.0));
+        assert_eq!(cost.copy_job_cost, Isk::from_f64(1_250.0));
+        assert_eq!(cost.cost_per_attempt, Isk::from_f64(682_900.0));
+        // 682,900 / 83.64%
+        assert_eq!(cost.cost_per_success, Some(Isk::from_f64(816_475.37)));
+        assert_eq!(cost.runs_per_copy, 14);
+        assert_eq!(cost.cost_per_run(), Some(Isk::from_f64(58_319.67)));
+    }
+
+    #[test]
+    fn impossible_invention_has_no_cost_per_success()
+    {
+        let db = database();
+        let mut impossible = invention(None);
+        impossible.base_probability = 0.0;
+        let cost = impossible.cost(&db, &Settings::with_skills(NO_SKILLS)).unwrap();
+        assert_eq!(cost.probability, 0.0);
+        assert_eq!(cost.cost_per_attempt, Isk::from_f64(182_900.0));
+        assert_eq!(cost.cost_per_success, None);
+        assert_eq!(cost.cost_per_run(), None);
+    }
+}
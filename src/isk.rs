@@ -0,0 +1,265 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{self, Visitor};
+use num_format::ToFormattedString;
+
+use crate::settings;
+
+/// An amount of ISK stored as a signed number of hundredths (0.01 ISK).
+///
+/// Arithmetic saturates at the bounds of the range instead of overflowing.
+///
+/// It is serialized as an exact decimal string of ISK like `"1234.56"`. Plain numbers,
+/// as saved by earlier versions, are still read.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Isk(i64);
+
+impl Isk
+{
+    pub const ZERO: Isk = Isk(0);
+    pub const MAX: Isk = Isk(i64::MAX);
+
+    pub fn from_cents(cents: i64) -> Isk
+    {
+        Isk(cents)
+    }
+
+    /// Converts a floating point ISK amount, rounding to the nearest 0.01 ISK.
+    pub fn from_f64(isk: f64) -> Isk
+    {
+        Isk((isk * 100.0).round() as i64)
+    }
+
+    pub fn as_f64(self) -> f64
+    {
+        self.0 as f64 / 100.0
+    }
+
+    /// The given percentage of this amount, rounded to the nearest 0.01 ISK.
+    pub fn percent(self, percent: f64) -> Isk
+    {
+        Isk((self.0 as f64 * percent / 100.0).round() as i64)
+    }
+}
+
+impl Add for Isk
+{
+    type Output = Isk;
+
+    fn add(self, other: Isk) -> Isk
+    {
+        Isk(self.0.saturating_add(other.0))
+    }
+}
+
+impl AddAssign for Isk
+{
+    fn add_assign(&mut self, other: Isk)
+    {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Isk
+{
+    type Output = Isk;
+
+    fn sub(self, other: Isk) -> Isk
+    {
+        Isk(self.0.saturating_sub(other.0))
+    }
+}
+
+impl SubAssign for Isk
+{
+    fn sub_assign(&mut self, other: Isk)
+    {
+        *self = *self - other;
+    }
+}
+
+impl Neg for Isk
+{
+    type Output = Isk;
+
+    fn neg(self) -> Isk
+    {
+        Isk(self.0.saturating_neg())
+    }
+}
+
+/// Price times quantity.
+impl Mul<u64> for Isk
+{
+    type Output = Isk;
+
+    fn mul(self, quantity: u64) -> Isk
+    {
+        Isk(self.0.saturating_mul(i64::try_from(quantity).unwrap_or(i64::MAX)))
+    }
+}
+
+impl Sum for Isk
+{
+    fn sum<I: Iterator<Item = Isk>>(iter: I) -> Isk
+    {
+        iter.fold(Isk::ZERO, |a, b| a + b)
+    }
+}
+
+impl fmt::Display for Isk
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        let locale = settings::locale();
+        let whole = (self.0 / 100).unsigned_abs();
+        let cents = (self.0 % 100).unsigned_abs();
+        let sign = if self.0 < 0 { locale.minus_sign() } else { "" };
+        write!(f, "{}{}{}{:02}", sign, whole.to_formatted_string(locale), locale.decimal(), cents)
+    }
+}
+
+impl FromStr for Isk
+{
+    type Err = String;
+
+    /// Parses a plain decimal number like `1234`, `-5.5` or `1_000.25`.
+    fn from_str(s: &str) -> Result<Isk, String>
+    {
+        let err = || format!("invalid ISK amount '{}'", s);
+        let cleaned: String = s.trim().chars().filter(|c| *c != '_').collect();
+        let (negative, digits) = match cleaned.strip_prefix('-')
+        {
+            Some(rest) => (true, rest),
+            None => (false, cleaned.as_str()),
+        };
+        let (whole, fraction) = match digits.split_once('.')
+        {
+            Some((w, f)) => (w, f),
+            None => (digits, ""),
+        };
+        if whole.is_empty() || fraction.len() > 2
+            || !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit())
+        {
+            return Err(err());
+        }
+        let whole: i64 = whole.parse().map_err(|_| err())?;
+        let fraction: i64 = format!("{:0<2}", fraction).parse().map_err(|_| err())?;
+        let cents = whole.checked_mul(100).and_then(|w| w.checked_add(fraction)).ok_or_else(err)?;
+        Ok(Isk(if negative { -cents } else { cents }))
+    }
+}
+
+impl Serialize for Isk
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let cents = self.0.unsigned_abs();
+        serializer.serialize_str(&format!("{}{}.{:02}", sign, cents / 100, cents % 100))
+    }
+}
+
+impl<'de> Deserialize<'de> for Isk
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Isk, D::Error>
+    {
+        deserializer.deserialize_any(IskVisitor)
+    }
+}
+
+struct IskVisitor;
+
+impl<'de> Visitor<'de> for IskVisitor
+{
+    type Value = Isk;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "an ISK amount as a decimal string or number")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Isk, E>
+    {
+        v.parse().map_err(E::custom)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Isk, E>
+    {
+        Ok(Isk(v.saturating_mul(100)))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Isk, E>
+    {
+        Ok(Isk(i64::try_from(v).unwrap_or(i64::MAX).saturating_mul(100)))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Isk, E>
+    {
+        Ok(Isk::from_f64(v))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn parses_decimal_amounts()
+    {
+        assert_eq!("1234".parse::<Isk>(), Ok(Isk::from_cents(123_400)));
+        assert_eq!("-5.5".parse::<Isk>(), Ok(Isk::from_cents(-550)));
+        assert_eq!(" 1_000.25 ".parse::<Isk>(), Ok(Isk::from_cents(100_025)));
+        for invalid in ["", ".5", "1.234", "1,000", "abc", "99999999999999999999"].iter()
+        {
+            assert!(invalid.parse::<Isk>().is_err(), "{} was accepted", invalid);
+        }
+    }
+
+    #[test]
+    fn displays_with_grouping_and_cents()
+    {
+        assert_eq!(Isk::from_cents(123_456_789).to_string(), "1,234,567.89");
+        assert_eq!(Isk::from_cents(-5).to_string(), "-0.05");
+        assert_eq!(Isk::ZERO.to_string(), "0.00");
+    }
+
+    #[test]
+    fn serializes_exactly()
+    {
+        // 2^53 + 1 cents is the smallest amount an f64 cannot hold
+        for isk in [Isk::MAX, Isk::from_cents(-i64::MAX), Isk::from_cents(9_007_199_254_740_993),
+            Isk::from_cents(-5), Isk::ZERO].iter()
+        {
+            let json = serde_json::to_string(isk).unwrap();
+            assert_eq!(serde_json::from_str::<Isk>(&json).unwrap(), *isk, "{}", json);
+        }
+        assert_eq!(serde_json::to_string(&Isk::from_cents(123_456)).unwrap(), "\"1234.56\"");
+        assert_eq!(serde_json::to_string(&Isk::from_cents(-5)).unwrap(), "\"-0.05\"");
+    }
+
+    #[test]
+    fn reads_numbers_saved_by_earlier_versions()
+    {
+        assert_eq!(serde_json::from_str::<Isk>("1500").unwrap(), Isk::from_cents(150_000));
+        assert_eq!(serde_json::from_str::<Isk>("-2").unwrap(), Isk::from_cents(-200));
+        assert_eq!(serde_json::from_str::<Isk>("12.34").unwrap(), Isk::from_cents(1234));
+        assert!(serde_json::from_str::<Isk>("\"1.234\"").is_err());
+        assert!(serde_json::from_str::<Isk>("true").is_err());
+    }
+
+    #[test]
+    fn arithmetic_saturates()
+    {
+        assert_eq!(Isk::MAX + Isk::from_cents(1), Isk::MAX);
+        assert_eq!(Isk::from_cents(100) * u64::MAX, Isk::MAX);
+        assert_eq!(Isk::from_cents(250) * 4, Isk::from_cents(1000));
+        assert_eq!(Isk::from_cents(1000).percent(2.5), Isk::from_cents(25));
+    }
+}
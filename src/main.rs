@@ -1,11 +1,54 @@
+mod error;
 mod utils;
+mod settings;
+mod isk;
+mod industry;
+mod sde;
+mod sde_source;
+mod search;
+mod esi;
+mod esi_import;
+mod marketlogs;
+mod orderbook;
+mod buildtree;
 mod evedata;
+mod fees;
+mod invention;
+mod research;
 mod menu;
+mod cli;
+
+use std::process;
 
 
 fn main()
 {
-    let mut db = evedata::Database::new("/home/michael/Projects/eve_industry_terminal/data");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (settings, command) = settings::Settings::load(&args);
+    settings.apply_locale();
+    let invocation = if command.is_empty() { None } else { Some(cli::parse(command)) };
+
+    let sde_source = sde_source::open(settings.sde_format, &settings.sde_dir);
+    let mut db = match evedata::Database::new(settings.data_dir.clone(), sde_source.as_ref())
+    {
+        Ok(db) => db,
+        Err(e) =>
+        {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
 
-    menu::main_menu(&mut db);
+    match invocation
+    {
+        Some(invocation) =>
+        {
+            if let Err(e) = cli::execute(invocation, &mut db, &settings)
+            {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        None => menu::main_menu(&mut db, &settings),
+    }
 }
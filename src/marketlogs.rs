@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use crate::error::{Error, Result};
+use crate::isk::Isk;
+use crate::orderbook::OrderBook;
+
+/// Which price of the order book is taken as an item's buy and sell price.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PricePoint
+{
+    /// Highest buy order and lowest sell order.
+    Best,
+    /// The price at which the given percentage of the order volume, counted from the
+    /// best order, is reached. Ignores small orders with outlier prices.
+    Percentile(f64),
+}
+
+/// Buy and sell price of one item. `None` if the export has no orders on that side.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MarketPrices
+{
+    pub buy: Option<Isk>,
+    pub sell: Option<Isk>,
+}
+
+/// Price, volume and whether it is a buy order.
+type Order = (Isk, u64, bool);
+
+/// Column positions of the fields we need, taken from the header line.
+struct Columns
+{
+    price: usize,
+    volume: usize,
+    type_id: usize,
+    bid: usize,
+}
+
+/// Order books read from the market exports of a directory. Exports that could not be
+/// read, e.g. ones the client is still writing, are listed in `skipped`.
+pub struct MarketLogs
+{
+    pub books: HashMap<i64, OrderBook>,
+    pub skipped: Vec<Error>,
+}
+
+impl MarketLogs
+{
+    pub fn report_skipped(&self)
+    {
+        if self.skipped.is_empty()
+        {
+            return;
+        }
+        eprintln!("Ignored {} unreadable market export(s):", self.skipped.len());
+        for e in self.skipped.iter()
+        {
+            eprintln!("  {}", e);
+        }
+    }
+}
+
+/// Reads all market exports (`*.txt`) in `dir` into the order book of every type found.
+/// If a type was exported more than once only the newest file is used.
+pub fn read_market_logs(dir: &Path) -> Result<MarketLogs>
+{
+    let entries = fs::read_dir(dir).map_err(|e| Error::Io(dir.to_path_buf(), e))?;
+    let mut newest = HashMap::<i64, (SystemTime, Vec<Order>)>::new();
+    let mut skipped = Vec::<Error>::new();
+    for entry in entries
+    {
+        let path = entry.map_err(|e| Error::Io(dir.to_path_buf(), e))?.path();
+        if path.extension().is_none_or(|ext| ext != "txt")
+        {
+            continue;
+        }
+        let read = fs::metadata(&path).and_then(|m| m.modified())
+            .map_err(|e| Error::Io(path.clone(), e))
+            .and_then(|modified| Ok((modified, read_market_log(&path)?)));
+        let (modified, orders) = match read
+        {
+            Ok(read) => read,
+            Err(e) =>
+            {
+                skipped.push(e);
+                continue;
+            }
+        };
+        for (type_id, orders) in orders
+        {
+            match newest.get(&type_id)
+            {
+                Some((time, _)) if *time >= modified => (),
+                _ => { newest.insert(type_id, (modified, orders)); }
+            }
+        }
+    }
+
+    let books = newest.into_iter()
+        .map(|(type_id, (_, orders))| (type_id, OrderBook::new(orders)))
+        .collect();
+    Ok(MarketLogs {books, skipped})
+}
+
+/// Reads the orders of one export, grouped by type ID.
+fn read_market_log(path: &Path) -> Result<HashMap<i64, Vec<Order>>>
+{
+    let content = fs::read_to_string(path).map_err(|e| Error::Io(path.to_path_buf(), e))?;
+    parse_market_log(path, &content)
+}
+
+/// Parses the contents of the export at `path`.
+fn parse_market_log(path: &Path, content: &str) -> Result<HashMap<i64, Vec<Order>>>
+{
+    let invalid = |line: usize, msg: &str| Error::MarketLog(path.to_path_buf(),
+        format!("line {}: {}", line, msg));
+    let mut lines = content.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+
+    let mut ret = HashMap::<i64, Vec<Order>>::new();
+    let header: Vec<&str> = match lines.next()
+    {
+        Some((_, l)) => l.split(',').map(str::trim).collect(),
+        None => return Ok(ret),
+    };
+    let column = |name: &str| header.iter().position(|h| *h == name)
+        .ok_or_else(|| Error::MarketLog(path.to_path_buf(), format!("no '{}' column", name)));
+    let columns = Columns {price: column("price")?, volume: column("volRemaining")?,
+        type_id: column("typeID")?, bid: column("bid")?};
+
+    for (idx, line) in lines
+    {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let field = |i: usize| fields.get(i).copied()
+            .ok_or_else(|| invalid(idx + 1, "missing field"));
+        let price = field(columns.price)?.parse::<f64>()
+            .map_err(|_| invalid(idx + 1, "invalid price"))?;
+        // Volumes are written as decimals, e.g. "1500.0".
+        let volume = field(columns.volume)?.parse::<f64>()
+            .map_err(|_| invalid(idx + 1, "invalid volume"))?;
+        let type_id = field(columns.type_id)?.parse::<i64>()
+            .map_err(|_| invalid(idx + 1, "invalid type ID"))?;
+        let bid = match field(columns.bid)?
+        {
+            "True" | "true" => true,
+            "False" | "false" => false,
+            _ => return Err(invalid(idx + 1, "invalid bid flag")),
+        };
+        ret.entry(type_id).or_default()
+            .push((Isk::from_f64(price), volume.max(0.0) as u64, bid));
+    }
+    Ok(ret)
+}
+
+/// Default location of the market exports written by the EVE client.
+pub fn default_dir() -> PathBuf
+{
+    let home = std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+    home.join("Documents/EVE/logs/Marketlogs")
+}
+
+impl fmt::Display for PricePoint
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            PricePoint::Best => write!(f, "best"),
+            PricePoint::Percentile(p) => write!(f, "p{}", p),
+        }
+    }
+}
+
+impl FromStr for PricePoint
+{
+    type Err = String;
+
+    /// Parses `best` or a percentile like `p5`.
+    fn from_str(s: &str) -> std::result::Result<PricePoint, String>
+    {
+        let err = || format!("unknown price point '{}' (expected best or p<PERCENT>, e.g. p5)", s);
+        if s == "best"
+        {
+            return Ok(PricePoint::Best);
+        }
+        let percent = s.strip_prefix('p').ok_or_else(err)?.parse::<f64>().map_err(|_| err())?;
+        if !(0.0..=100.0).contains(&percent)
+        {
+            return Err(err());
+        }
+        Ok(PricePoint::Percentile(percent))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const HEADER: &str = "price,volRemaining,typeID,range,orderID,volEntered,minVolume,bid,\
+        issueDate,duration,stationID,regionID,solarSystemID,jumps,";
+    /// Fields after the bid flag, which are not read.
+    const TAIL: &str = "2024-01-01 00:00:00.000,90,60003760,10000002,30000142,0,";
+
+    #[test]
+    fn parses_orders_by_type()
+    {
+        let content = format!("{header}\n5.5,1500.0,34,32767,1,2000,1,False,{tail}\n\
+            4.75,250.0,34,32767,2,500,1,True,{tail}\n\n12.0,10.0,35,32767,3,10,1,False,{tail}\n",
+            header = HEADER, tail = TAIL);
+        let orders = parse_market_log(Path::new("export.txt"), &content).unwrap();
+        assert_eq!(orders[&34], [(Isk::from_cents(550), 1500, false),
+            (Isk::from_cents(475), 250, true)]);
+        assert_eq!(orders[&35], [(Isk::from_cents(1200), 10, false)]);
+    }
+
+    #[test]
+    fn rejects_malformed_exports()
+    {
+        let path = Path::new("export.txt");
+        assert!(parse_market_log(path, "").unwrap().is_empty());
+        assert!(parse_market_log(path, "price,typeID\n5,34\n").is_err());
+        let bad_flag = format!("{}\n5,1,34,0,1,1,1,maybe,,,,,,,\n", HEADER);
+        assert!(parse_market_log(path, &bad_flag).is_err());
+        let missing_field = format!("{}\n5,1,34\n", HEADER);
+        assert!(parse_market_log(path, &missing_field).is_err());
+    }
+
+    #[test]
+    fn parses_price_points()
+    {
+        assert_eq!("best".parse::<PricePoint>(), Ok(PricePoint::Best));
+        assert_eq!("p5".parse::<PricePoint>(), Ok(PricePoint::Percentile(5.0)));
+        assert!("p101".parse::<PricePoint>().is_err());
+        assert!("5".parse::<PricePoint>().is_err());
+    }
+}
@@ -1,11 +1,20 @@
 use std::fmt;
 use std::process;
-use std::collections::HashMap;
-
-use num_format::{Locale, ToFormattedString};
+use std::path::Path;
 
+use crate::buildtree::{self, BuildChoice};
 use crate::utils;
-use crate::evedata::Database;
+use crate::settings::Settings;
+use crate::error::{Error, Result};
+use crate::esi::EsiClient;
+use crate::esi_import;
+use crate::evedata::{BlueprintKind, Database, Item, ProductionRun, RunStatus,
+    DEFAULT_LOCATION, MAX_QUANTITY};
+use crate::industry::{Facility, Structure, Rig, Security};
+use crate::isk::Isk;
+use crate::invention::Decryptor;
+use crate::research;
+use crate::search::{Category, SearchFilter};
 
 pub struct ChoiceMenu
 {
@@ -42,61 +51,88 @@ impl fmt::Display for ChoiceMenu
 }
 
 
-pub fn main_menu(db: &mut Database)
+pub fn main_menu(db: &mut Database, settings: &Settings)
 {
     let choices = vec!["Exit".to_string(), "List blueprints".to_string(), 
             "List items".to_string(), "Add blueprint".to_string(), 
             "Add production run".to_string(), "Manage buy prices".to_string(),
             "Manage sell prices".to_string(), "List production runs".to_string(),
-            "Create shopping list".to_string()];
+            "Create shopping list".to_string(), "Manage cost indices and adjusted prices".to_string(),
+            "Import prices from market exports".to_string(),
+            "Set up invention of a T2 blueprint".to_string(),
+            "Build or buy intermediates of a production run".to_string(),
+            "Set status of a production run".to_string(),
+            "Edit or delete blueprints, items and production runs".to_string(),
+            "Plan research and copying of a blueprint".to_string(),
+            "Manage inventory".to_string(),
+            "ESI login, updates and imports".to_string()];
 
     let menu = ChoiceMenu::new("MAIN MENU", choices);
 
     loop
     {
         let choice = menu.show();
-        match choice
+        let result = match choice
         {
             0 => process::exit(0),
-            1 => db.print_blueprints(),
-            2 => db.print_items(),
+            1 =>
+            {
+                db.print_blueprints();
+                Ok(())
+            }
+            2 =>
+            {
+                db.print_items();
+                Ok(())
+            }
             3 => add_blueprint_menu(db),
             4 => add_productionrun_menu(db),
             5 => buy_prices_menu(db),
             6 => sell_prices_menu(db),
-            7 => productionrun_menu(db),
-            8 => shopping_list_menu(db),
-            _ => (),
+            7 => productionrun_menu(db, settings),
+            8 => shopping_list_menu(db, settings),
+            9 => cost_data_menu(db),
+            10 => market_logs_menu(db, settings),
+            11 => invention_menu(db, settings),
+            12 => build_tree_menu(db, settings),
+            13 => run_status_menu(db, settings),
+            14 => edit_menu(db),
+            15 => research_menu(db, settings),
+            16 => inventory_menu(db, settings),
+            17 => esi_menu(db, settings),
+            _ => Ok(()),
+        };
+        if let Err(e) = result
+        {
+            eprintln!("Error: {}", e);
         }
     }
 }
 
 
-pub fn add_blueprint_menu(db: &mut Database)
+pub fn add_blueprint_menu(db: &mut Database) -> Result<()>
 {
     println!("ADD NEW BLUEPRINT:");
-    let mut choices = Vec::<String>::new();
-    let mut query = Vec::<(&str,i64)>::new();
+    let filter = SearchFilter {category: Some(Category::Blueprint), ..SearchFilter::default()};
+    let mut found = Vec::<(String, i64)>::new();
 
-    while choices.is_empty()
+    while found.is_empty()
     {
-        let input = utils::read_input("Blueprint name:");
-        query = db.search_ids(&input);
-        for i in &query
+        let input = utils::read_input("Blueprint name or type ID:");
+        found = db.search(&input, &filter, 20).iter()
+            .map(|hit| (hit.name.to_string(), hit.id))
+            .collect();
+        if found.is_empty()
         {
-            if i.0.contains("Blueprint")
-            {
-                choices.push(i.0.to_string());
-            }
+            println!("No matching blueprints found.");
         }
     }
 
-    let menu = ChoiceMenu::new("Found blueprints:", choices.clone());
+    let choices = found.iter().map(|f| f.0.clone()).collect();
+    let menu = ChoiceMenu::new("Found blueprints:", choices);
     let choice = menu.show();
-    let idx = query.iter().position(|&x| x.0 == choices[choice as usize]).unwrap();
-    let bp_id = query[idx].1.clone();
-    let bp_name = query[idx].0.to_string();
-    
+    let (bp_name, bp_id) = found[choice as usize].clone();
+
     if db.has_blueprint(&bp_name)
     {
         println!("This blueprint is already known.");
@@ -105,55 +141,738 @@ pub fn add_blueprint_menu(db: &mut Database)
     {
         let material_research = utils::parse_input::<u8>("Material research: ", 0, 10);
         let time_research = utils::parse_input::<u8>("Time research: ", 0, 20);
-        
-        db.add_blueprint(bp_id as usize, material_research, time_research);
+        let (kind, quantity, runs) = ownership_menu();
+
+        db.add_blueprint(bp_id as usize, material_research, time_research)?;
+        if let Err(e) = db.set_blueprint_ownership(&bp_name, kind, quantity, runs)
+        {
+            db.delete_blueprint(&bp_name, false)?;
+            return Err(e);
+        }
         println!("Added {} to known blueprints", bp_name);
     }
-
+    Ok(())
 }
 
 
-pub fn add_productionrun_menu(db: &mut Database)
+pub fn add_productionrun_menu(db: &mut Database) -> Result<()>
 {
     println!("ADD PRODUCTION RUN:");
     let choices = db.get_blueprint_vec();
     let menu = ChoiceMenu::new("Existing blueprints:", choices.clone());
     let choice = menu.show();
-    let pr_name = &choices[choice as usize];
+    let bp_name = &choices[choice as usize];
 
-    if db.has_productionrun(pr_name)
+    let max_runs = db.max_jobruns(bp_name, None);
+    if max_runs == 0
     {
-        println!("This blueprint already has a production run defined");
+        println!("No runs left on the copies of {}", bp_name);
+        return Ok(());
     }
-    else
+    let jobruns = utils::parse_input::<u64>("Job runs: ", 1, max_runs);
+    let facility = facility_menu("to enter the installation cost by hand");
+    let installation_cost = match facility.solar_system
+    {
+        Some(_) => None,
+        None => Some(utils::parse_input::<Isk>("Installation cost: ", Isk::ZERO, Isk::MAX)),
+    };
+    let build_components = ChoiceMenu::new("Components and reaction products",
+        vec!["Buy".to_string(), "Build".to_string()]).show() == 1;
+
+    let mut pr = ProductionRun::new(bp_name, jobruns, installation_cost, facility,
+        build_components, db)?;
+    if build_components && ChoiceMenu::new("Refinery for reaction sub-builds",
+        vec!["None".to_string(), "Enter refinery".to_string()]).show() == 1
+    {
+        let mut refinery = facility_menu("for the production run's system");
+        if refinery.solar_system.is_none()
+        {
+            refinery.solar_system = facility.solar_system;
+            refinery.facility_tax = facility.facility_tax;
+        }
+        pr.refinery = Some(refinery);
+    }
+    pr.name = utils::read_input("Name (empty for the blueprint name): ");
+    pr.planned_date = loop
+    {
+        let date = utils::read_input("Planned date as YYYY-MM-DD (empty for none): ");
+        if date.is_empty()
+        {
+            break None;
+        }
+        if utils::is_valid_date(&date)
+        {
+            break Some(date);
+        }
+    };
+    let id = db.add_productionrun(pr)?;
+    println!("Added production run {}", id);
+    Ok(())
+}
+
+/// Asks for the facility of a job. `without_system` explains what choosing no solar
+/// system means.
+pub fn facility_menu(without_system: &str) -> Facility
+{
+    let structure = Structure::ALL[ChoiceMenu::new("Structure",
+        Structure::ALL.iter().map(|x| x.to_string()).collect()).show() as usize];
+    let me_rig = Rig::ALL[ChoiceMenu::new("ME rig",
+        Rig::ALL.iter().map(|x| x.to_string()).collect()).show() as usize];
+    let te_rig = Rig::ALL[ChoiceMenu::new("TE rig",
+        Rig::ALL.iter().map(|x| x.to_string()).collect()).show() as usize];
+    let security = Security::ALL[ChoiceMenu::new("System security",
+        Security::ALL.iter().map(|x| x.to_string()).collect()).show() as usize];
+    let system = utils::parse_input::<i64>(
+        &format!("Solar system ID for the cost index (0 {}): ", without_system), 0, i64::MAX);
+    let mut facility = Facility {structure, me_rig, te_rig, security,
+        solar_system: None, ..Facility::default()};
+    if system != 0
     {
-        let jobruns = utils::parse_input::<u64>("Job runs: ", 1, std::u64::MAX);
-        let installation_cost = utils::parse_input::<u64>("Installation cost: ", 1, std::u64::MAX);
+        facility.solar_system = Some(system);
+        facility.facility_tax = utils::parse_input::<f64>("Facility tax in percent: ",
+            0.0, 100.0);
+    }
+    facility
+}
+
+pub fn cost_data_menu(db: &mut Database) -> Result<()>
+{
+    let choices = vec!["Back".to_string(), "Import ESI adjusted prices file".to_string(),
+        "Import ESI industry systems file".to_string(),
+        "Set manufacturing cost index".to_string()];
+    let menu = ChoiceMenu::new("COST INDICES AND ADJUSTED PRICES", choices);
 
-        db.add_productionrun(pr_name, jobruns, installation_cost);
-        println!("Added {} to production runs", pr_name);
+    loop
+    {
+        match menu.show()
+        {
+            1 =>
+            {
+                let path = utils::read_input("Path to /markets/prices/ JSON: ");
+                let count = db.import_adjusted_prices(Path::new(&path))?;
+                println!("Imported {} adjusted prices", count);
+            }
+            2 =>
+            {
+                let path = utils::read_input("Path to /industry/systems/ JSON: ");
+                let count = db.import_cost_indices(Path::new(&path))?;
+                println!("Imported cost indices of {} solar systems", count);
+            }
+            3 =>
+            {
+                let system = utils::parse_input::<i64>("Solar system ID: ", 1, i64::MAX);
+                let index = utils::parse_input::<f64>("Cost index (e.g. 0.05): ", 0.0, 1.0);
+                db.set_cost_index(system, "manufacturing", index)?;
+            }
+            _ => return Ok(()),
+        }
     }
 }
 
-pub fn productionrun_menu(db: &Database)
+pub fn market_logs_menu(db: &mut Database, settings: &Settings) -> Result<()>
 {
+    let input = utils::read_input(&format!("Market export directory [{}]: ",
+        settings.marketlogs_dir.display()));
+    let dir = if input.is_empty() { settings.marketlogs_dir.clone() } else { input.into() };
+    let count = db.import_market_logs(&dir, settings.price_point)?;
+    println!("Updated prices of {} items ({} price)", count, settings.price_point);
+    Ok(())
+}
+
+pub fn invention_menu(db: &mut Database, settings: &Settings) -> Result<()>
+{
+    println!("SET UP INVENTION:");
+    let filter = SearchFilter {category: Some(Category::Blueprint), ..SearchFilter::default()};
+    let mut found = Vec::<(String, i64)>::new();
+    while found.is_empty()
+    {
+        let input = utils::read_input("T2 blueprint name or type ID:");
+        found = db.search(&input, &filter, 20).iter()
+            .map(|hit| (hit.name.to_string(), hit.id))
+            .collect();
+        if found.is_empty()
+        {
+            println!("No matching blueprints found.");
+        }
+    }
+    let choice = ChoiceMenu::new("Found blueprints:",
+        found.iter().map(|f| f.0.clone()).collect()).show();
+    let bp_id = found[choice as usize].1;
+
+    let mut choices = vec!["none".to_string()];
+    choices.extend(Decryptor::ALL.iter().map(|d| d.to_string()));
+    let decryptor = match ChoiceMenu::new("Decryptor", choices).show()
+    {
+        0 => None,
+        x => Some(Decryptor::ALL[x as usize - 1]),
+    };
+    let facility = facility_menu("to leave out copy and invention job costs");
+
+    let name = db.set_invention(bp_id, decryptor, facility)?;
+    let bp = db.get_blueprint(&name).ok_or_else(|| Error::UnknownBlueprint(name.clone()))?;
+    if let Some(invention) = &bp.invention
+    {
+        let cost = invention.cost(db, settings)?;
+        println!("{}: ME {}, TE {}, {} runs per copy\n  Success chance: {:.1}%\n  \
+            Cost per attempt: {}\n  Expected cost per copy: {}", name, bp.material_research,
+            bp.time_research, cost.runs_per_copy, cost.probability * 100.0,
+            cost.cost_per_attempt, cost.cost_per_success
+            .map_or_else(|| "impossible".to_string(), |c| c.to_string()));
+    }
+    Ok(())
+}
+
+pub fn research_menu(db: &mut Database, settings: &Settings) -> Result<()>
+{
+    let name = match choose_blueprint(db)
+    {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+    let bp = db.get_blueprint(&name).ok_or_else(|| Error::UnknownBlueprint(name.clone()))?;
+    let target_me = utils::parse_input::<u8>("Target ME: ", bp.material_research,
+        research::MAX_MATERIAL_RESEARCH);
+    let target_te = loop
+    {
+        let target_te = utils::parse_input::<u8>("Target TE: ", bp.time_research,
+            research::MAX_TIME_RESEARCH);
+        match research::check_target_te(target_te)
+        {
+            Ok(()) => break target_te,
+            Err(e) => println!("{}", e),
+        }
+    };
+    let copies = utils::parse_input::<u64>("Copies to make (0 for none): ", 0,
+        bp.max_production_limit);
+    let runs = match copies
+    {
+        0 => 0,
+        _ => utils::parse_input::<u64>("Runs per copy: ", 1, bp.max_production_limit),
+    };
+    let facility = facility_menu("to leave out job costs");
+
+    let steps = research::research_steps(db, settings, bp, target_me, target_te, &facility)?;
+    for step in steps.iter()
+    {
+        print!("{} {}: {}, job cost {} (total {}, {})", step.activity, step.level,
+            utils::format_duration(step.time), step.job_cost,
+            utils::format_duration(step.total_time), step.total_cost);
+        match (step.savings_per_run, step.payback_runs)
+        {
+            (Some(savings), Some(runs)) =>
+                println!(", saves {} per run, pays back after {} runs", savings, runs),
+            _ => println!(),
+        }
+    }
+    if copies > 0
+    {
+        let job = research::copy_job(db, settings, bp, copies, runs, &facility)?;
+        println!("Copying {} x {} run(s): {}, job cost {}", job.copies, job.runs,
+            utils::format_duration(job.time), job.job_cost);
+    }
+    Ok(())
+}
+
+pub fn build_tree_menu(db: &mut Database, settings: &Settings) -> Result<()>
+{
+    let id = match choose_productionrun(db)
+    {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    loop
+    {
+        let pr = db.get_productionrun(id).ok_or(Error::UnknownProductionRun(id.to_string()))?;
+        if !pr.build_components
+        {
+            println!("All materials of this production run are bought.");
+            return Ok(());
+        }
+        let tree = pr.get_build_tree(db, &settings.fees)?;
+        println!("{} x {}", pr.blueprint, pr.jobruns);
+        print!("{}", buildtree::format_tree(&tree));
+        println!("Materials: {}", tree.iter().map(|n| n.cost()).sum::<Isk>());
+
+        let mut intermediates = Vec::<String>::new();
+        for node in tree.iter().flat_map(|n| n.all_nodes())
+        {
+            if node.job.is_some() && !intermediates.contains(&node.name)
+            {
+                intermediates.push(node.name.clone());
+            }
+        }
+        let mut choices = vec!["Back".to_string()];
+        choices.extend(intermediates.iter().map(|name| match pr.choices.get(name)
+        {
+            Some(choice) => format!("{} ({})", name, choice),
+            None => format!("{} (recommended)", name),
+        }));
+        let item = match ChoiceMenu::new("Change intermediate", choices).show()
+        {
+            0 => return Ok(()),
+            x => intermediates[x as usize - 1].clone(),
+        };
+        let choice = match ChoiceMenu::new("Choice", vec!["recommended".to_string(),
+            "build".to_string(), "buy".to_string()]).show()
+        {
+            0 => None,
+            x => Some(BuildChoice::ALL[x as usize - 1]),
+        };
+        db.set_build_choice(id, &item, choice)?;
+    }
+}
+
+pub fn run_status_menu(db: &mut Database, settings: &Settings) -> Result<()>
+{
+    let id = match choose_productionrun(db)
+    {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    let status = RunStatus::ALL[ChoiceMenu::new("Status",
+        RunStatus::ALL.iter().map(|x| x.to_string()).collect()).show() as usize];
+    db.set_productionrun_status(id, status)?;
+    if status != RunStatus::Done
+    {
+        return Ok(());
+    }
+    let pr = &db.productionruns[&id];
+    let (consumed, added) = (pr.materials_consumed, pr.products_added);
+    if !consumed && confirm("Take the materials out of the inventory?")
+    {
+        db.consume_materials(id, None, &settings.fees)?;
+    }
+    if !added && confirm("Add the products to the inventory?")
+    {
+        let location = read_location();
+        db.add_products(id, &location)?;
+    }
+    Ok(())
+}
+
+pub fn inventory_menu(db: &mut Database, settings: &Settings) -> Result<()>
+{
+    let choices = vec!["Back".to_string(), "List stock".to_string(),
+        "Set quantity of an item".to_string(), "Add to stock".to_string(),
+        "Remove from stock".to_string(), "Consume materials of a production run".to_string(),
+        "Add products of a production run".to_string()];
+    let menu = ChoiceMenu::new("INVENTORY", choices);
+
+    loop
+    {
+        match menu.show()
+        {
+            1 =>
+            {
+                let mut items: Vec<&Item> = db.known_items.values()
+                    .filter(|item| !item.stock.is_empty())
+                    .collect();
+                items.sort_by(|a, b| a.name.cmp(&b.name));
+                for item in items
+                {
+                    let mut stock: Vec<_> = item.stock.iter().collect();
+                    stock.sort();
+                    for (location, quantity) in stock
+                    {
+                        println!("  {} @ {}: {}", item.name, location, quantity);
+                    }
+                }
+            }
+            x @ 2..=4 =>
+            {
+                let mut items: Vec<String> = db.known_items.keys().cloned().collect();
+                items.sort();
+                let item = &items[ChoiceMenu::new("Item", items.clone()).show() as usize];
+                let location = read_location();
+                let quantity = utils::parse_input::<u64>("Quantity: ", 0, MAX_QUANTITY);
+                match x
+                {
+                    2 => db.set_stock(item, &location, quantity)?,
+                    3 => db.add_stock(item, &location, quantity)?,
+                    _ => db.remove_stock(item, Some(&location), quantity)?,
+                }
+                println!("{} @ {}: {}", item, location, db.stock_of(item, Some(&location)));
+            }
+            5 =>
+            {
+                if let Some(id) = choose_productionrun(db)
+                {
+                    for (name, quantity) in db.consume_materials(id, None, &settings.fees)?
+                    {
+                        println!("Consumed {} x {}", name, quantity);
+                    }
+                }
+            }
+            6 =>
+            {
+                if let Some(id) = choose_productionrun(db)
+                {
+                    let location = read_location();
+                    for (name, quantity) in db.add_products(id, &location)?
+                    {
+                        println!("Added {} x {}", name, quantity);
+                    }
+                }
+            }
+            _ => return Ok(()),
+        }
+    }
+}
+
+pub fn esi_menu(db: &mut Database, settings: &Settings) -> Result<()>
+{
+    let choices = vec!["Back".to_string(), "Import /characters/{id}/blueprints/ JSON".to_string(),
+        "Import /characters/{id}/assets/ JSON".to_string(),
+        "Fetch blueprints and assets of a character".to_string(),
+        "Log in with EVE SSO".to_string(),
+        "Fetch adjusted prices and cost indices".to_string(), "Log out".to_string(),
+        "Import order books from /markets/{region_id}/orders/ JSON".to_string(),
+        "Fetch order books of all items in a region".to_string()];
+    let menu = ChoiceMenu::new("ESI", choices);
+    let client = EsiClient::new(settings);
+
+    loop
+    {
+        if let Some(token) = client.token()?
+        {
+            println!("Logged in as {} ({})", token.character_name, token.character_id);
+        }
+        let (blueprints, assets) = match menu.show()
+        {
+            1 =>
+            {
+                let path = utils::read_input("Path to blueprints JSON: ");
+                (esi_import::read_blueprints(Path::new(&path))?, Vec::new())
+            }
+            2 =>
+            {
+                let path = utils::read_input("Path to assets JSON: ");
+                (Vec::new(), esi_import::read_assets(Path::new(&path))?)
+            }
+            3 =>
+            {
+                let character_id = match settings.character_id
+                    .or(client.token()?.map(|token| token.character_id))
+                {
+                    Some(id) => id,
+                    None => utils::parse_input::<i64>("Character ID: ", 1, i64::MAX),
+                };
+                let endpoint = |name| format!("characters/{}/{}/", character_id, name);
+                (client.get_authorized_pages(&endpoint("blueprints"))?,
+                client.get_authorized_pages(&endpoint("assets"))?)
+            }
+            4 =>
+            {
+                let request = client.login_request()?;
+                println!("Log in with this URL in a browser:\n{}\n", request.url);
+                let redirect = utils::read_input("Paste the URL the browser was redirected to: ");
+                let token = client.finish_login(&request, &redirect)?;
+                println!("Logged in as {} ({})", token.character_name, token.character_id);
+                continue;
+            }
+            5 =>
+            {
+                let count = db.set_adjusted_prices(esi_import::adjusted_prices(
+                    client.get_pages("markets/prices/")?))?;
+                println!("Imported {} adjusted prices", count);
+                let count = db.merge_cost_indices(esi_import::cost_indices(
+                    client.get_pages("industry/systems/")?))?;
+                println!("Imported cost indices of {} solar systems", count);
+                continue;
+            }
+            6 =>
+            {
+                if client.logout()?
+                {
+                    println!("Logged out");
+                }
+                continue;
+            }
+            x @ 7..=8 =>
+            {
+                let input = utils::read_input("Only orders at station or structure ID \
+                    (empty for all): ");
+                let location = input.parse::<i64>().ok();
+                let count = if x == 7
+                {
+                    let path = utils::read_input("Path to orders JSON: ");
+                    db.import_order_books(Path::new(&path), location)?
+                }
+                else
+                {
+                    let region = utils::parse_input::<i64>("Region ID: ", 1, i64::MAX);
+                    let mut type_ids: Vec<i64> = db.known_items.values().map(|i| i.id).collect();
+                    type_ids.sort_unstable();
+                    db.set_order_books(esi_import::fetch_order_books(&client, region, &type_ids,
+                        location)?)?
+                };
+                println!("Imported order books of {} types", count);
+                continue;
+            }
+            _ => return Ok(()),
+        };
+        let (imported, skipped) = db.import_esi_blueprints(&blueprints)?;
+        for name in imported
+        {
+            println!("Imported {}", name);
+        }
+        for entry in skipped
+        {
+            println!("Skipped blueprint {}", entry);
+        }
+        if !assets.is_empty()
+        {
+            let (stocked, unknown_types) = db.import_esi_assets(&assets)?;
+            println!("Set stock of {} items", stocked);
+            for type_id in unknown_types
+            {
+                println!("Skipped assets of unknown type ID {}", type_id);
+            }
+        }
+    }
+}
+
+fn read_location() -> String
+{
+    let location = utils::read_input(&format!("Location [{}]: ", DEFAULT_LOCATION));
+    if location.is_empty() { DEFAULT_LOCATION.to_string() } else { location }
+}
+
+pub fn edit_menu(db: &mut Database) -> Result<()>
+{
+    let choices = vec!["Back".to_string(), "Update blueprint ME/TE".to_string(),
+        "Change production run".to_string(), "Delete blueprint".to_string(),
+        "Delete item".to_string(), "Delete production run".to_string(),
+        "Set owned originals or copies of a blueprint".to_string()];
+    let menu = ChoiceMenu::new("EDIT AND DELETE", choices);
+
+    loop
+    {
+        match menu.show()
+        {
+            1 =>
+            {
+                if let Some(bp_name) = choose_blueprint(db)
+                {
+                    let material_research = utils::parse_input::<u8>("Material research: ", 0, 10);
+                    let time_research = utils::parse_input::<u8>("Time research: ", 0, 20);
+                    db.set_blueprint_research(&bp_name, material_research, time_research)?;
+                }
+            }
+            2 =>
+            {
+                if let Some(id) = choose_productionrun(db)
+                {
+                    let pr = db.get_productionrun(id)
+                        .ok_or_else(|| Error::UnknownProductionRun(id.to_string()))?;
+                    if pr.status == RunStatus::Done
+                    {
+                        println!("{} is done, its job runs stay at {}", pr.name, pr.jobruns);
+                    }
+                    else
+                    {
+                        let max_runs = db.max_jobruns(&pr.blueprint.clone(), Some(id));
+                        if max_runs == 0
+                        {
+                            println!("No runs left on the copies of {}", pr.blueprint);
+                        }
+                        else
+                        {
+                            let jobruns = utils::parse_input::<u64>("Job runs: ", 1, max_runs);
+                            db.set_productionrun_jobruns(id, jobruns)?;
+                        }
+                    }
+                    let name = utils::read_input("Name (empty for the default name): ");
+                    db.rename_productionrun(id, &name)?;
+                    let planned_date = loop
+                    {
+                        let date = utils::read_input("Planned date as YYYY-MM-DD \
+                            (empty for none): ");
+                        if date.is_empty()
+                        {
+                            break None;
+                        }
+                        if utils::is_valid_date(&date)
+                        {
+                            break Some(date);
+                        }
+                    };
+                    db.set_productionrun_date(id, planned_date)?;
+                }
+            }
+            3 =>
+            {
+                if let Some(bp_name) = choose_blueprint(db)
+                {
+                    let runs = db.productionruns_of(&bp_name);
+                    let mut delete_runs = false;
+                    if !runs.is_empty()
+                    {
+                        println!("WARNING: {} is used by these production runs, which will be \
+                            deleted as well:", bp_name);
+                        for id in runs.iter()
+                        {
+                            println!("  {} ({})", db.productionruns[id].name, id);
+                        }
+                        delete_runs = true;
+                    }
+                    if confirm(&format!("Delete {}?", bp_name))
+                    {
+                        db.delete_blueprint(&bp_name, delete_runs)?;
+                    }
+                }
+            }
+            4 =>
+            {
+                let mut items: Vec<String> = db.known_items.keys().cloned().collect();
+                items.sort();
+                let mut choices = vec!["Back".to_string()];
+                choices.extend(items.iter().cloned());
+                let choice = ChoiceMenu::new("Item", choices).show();
+                if choice != 0
+                {
+                    let name = &items[choice as usize - 1];
+                    let users = db.item_users(name);
+                    if users.is_empty()
+                    {
+                        let question = match db.stock_of(name, None)
+                        {
+                            0 => format!("Delete {}?", name),
+                            stock => format!("Delete {} and its stock of {}?", name, stock),
+                        };
+                        if confirm(&question)
+                        {
+                            db.delete_item(name, true)?;
+                        }
+                    }
+                    else
+                    {
+                        println!("{} cannot be deleted, it is used by:", name);
+                        for user in users.iter()
+                        {
+                            println!("  {}", user);
+                        }
+                    }
+                }
+            }
+            5 =>
+            {
+                if let Some(id) = choose_productionrun(db)
+                {
+                    if confirm(&format!("Delete {}?", db.productionruns[&id].name))
+                    {
+                        db.delete_productionrun(id)?;
+                    }
+                }
+            }
+            6 =>
+            {
+                if let Some(bp_name) = choose_blueprint(db)
+                {
+                    let (kind, quantity, runs) = ownership_menu();
+                    db.set_blueprint_ownership(&bp_name, kind, quantity, runs)?;
+                }
+            }
+            _ => return Ok(()),
+        }
+    }
+}
+
+fn confirm(question: &str) -> bool
+{
+    ChoiceMenu::new(question, vec!["No".to_string(), "Yes".to_string()]).show() == 1
+}
+
+/// Lets the user pick a known blueprint. Returns `None` for "Back".
+fn choose_blueprint(db: &Database) -> Option<String>
+{
+    let mut names = db.get_blueprint_vec();
+    names.sort();
+    let mut choices = vec!["Back".to_string()];
+    choices.extend(names.iter().cloned());
+    match ChoiceMenu::new("Blueprint", choices).show()
+    {
+        0 => None,
+        x => Some(names[x as usize - 1].clone()),
+    }
+}
+
+/// Asks whether originals or copies are owned, how many and, for copies, their runs.
+fn ownership_menu() -> (BlueprintKind, u64, u64)
+{
+    let kind = BlueprintKind::ALL[ChoiceMenu::new("Owned",
+        BlueprintKind::ALL.iter().map(|x| x.to_string()).collect()).show() as usize];
+    let quantity = utils::parse_input::<u64>("Quantity owned: ", 0, MAX_QUANTITY);
+    let runs = match kind
+    {
+        BlueprintKind::Original => 0,
+        BlueprintKind::Copy =>
+            utils::parse_input::<u64>("Runs left over all copies: ", 0, MAX_QUANTITY),
+    };
+    (kind, quantity, runs)
+}
+
+/// Lets the user pick a production run. Returns `None` for "Back".
+fn choose_productionrun(db: &Database) -> Option<u64>
+{
+    let ids = db.get_productionrun_vec();
+    let mut choices = vec!["Back".to_string()];
+    for id in ids.iter()
+    {
+        let pr = &db.productionruns[id];
+        choices.push(format!("{} ({}, {} x {})", pr.name, pr.status, pr.blueprint, pr.jobruns));
+    }
+    match ChoiceMenu::new("Production run", choices).show()
+    {
+        0 => None,
+        x => Some(ids[x as usize - 1]),
+    }
+}
+
+pub fn productionrun_menu(db: &Database, settings: &Settings) -> Result<()>
+{
+    let fees = &settings.fees;
+    println!("Fees: {:.2}% sales tax, {:.2}% broker fee ({}, {})", fees.sales_tax(),
+        fees.broker_fee(), fees.sell_mode, fees.buy_mode);
     for iter in db.get_productionrun_iter()
     {
-        let production_cost = iter.1.get_production_cost(db);
-        let sell_value = iter.1.get_sell_value(db);
-        let raw_profit = sell_value - production_cost;
-        let taxed_profit = raw_profit - (sell_value as f64 / 100 as f64 * 10 as f64) as u64;
-        println!("{} x {}:\n  Production cost: {}\n  Sell value: {} x {} = {}\n  \
-            raw profit: {}\n  minus fees (-10% overall sell value): {}",
-            iter.0, iter.1.jobruns, production_cost.to_formatted_string(&Locale::en),
-            (sell_value/iter.1.jobruns).to_formatted_string(&Locale::en), 
-            iter.1.jobruns, sell_value.to_formatted_string(&Locale::en), 
-            raw_profit.to_formatted_string(&Locale::en),
-            taxed_profit.to_formatted_string(&Locale::en));
+        print!("{} ({}): {} x {}, {}{} ({}):\n  Production cost: ",
+            iter.1.name, iter.0, iter.1.blueprint, iter.1.jobruns, iter.1.status,
+            iter.1.planned_date.as_ref().map_or(String::new(), |d| format!(" {}", d)),
+            iter.1.facility);
+        let profit = match iter.1.get_profit(db, settings)?
+        {
+            Some(profit) => profit,
+            None =>
+            {
+                println!("impossible (the blueprint copy can never be invented)");
+                continue;
+            }
+        };
+        println!("{}", profit.production_cost);
+        if profit.invention_cost != Isk::ZERO
+        {
+            println!("  (including expected invention cost: {})", profit.invention_cost);
+        }
+        for (name, units) in iter.1.get_units_produced()
+        {
+            let item = db.get_item(&name).ok_or_else(|| Error::UnknownItem(name.clone()))?;
+            let price = fees.product_price(item);
+            println!("  {}: {} x {} = {}", name, price, units, price * units);
+        }
+        println!("  Sell value: {}\n  raw profit: {}\n  \
+            material broker fee: {}\n  sales tax: {}\n  sell broker fee: {}\n  \
+            profit after fees: {}",
+            profit.sell_value, profit.raw_profit, profit.material_broker_fee, profit.sales_tax,
+            profit.sell_broker_fee, profit.taxed_profit);
+        println!("  Job duration: {}\n  Profit per hour: {}",
+            utils::format_duration(profit.job_time), profit.profit_per_hour);
     }
+    Ok(())
 }
 
-pub fn buy_prices_menu(db: &mut Database)
+pub fn buy_prices_menu(db: &mut Database) -> Result<()>
 {
     loop
     {
@@ -163,13 +882,9 @@ pub fn buy_prices_menu(db: &mut Database)
 
         for iter in db.get_item_iter()
         {
-            if iter.1.produced == false
-            {
-                item_list.push(iter.1.name.clone());
-                let s = format!("{}: {}ISK",iter.1.name,
-                    iter.1.buy_price.to_formatted_string(&Locale::en));
-                choices.push(s);
-            }
+            item_list.push(iter.1.name.clone());
+            let s = format!("{}: {}ISK",iter.1.name, iter.1.buy_price);
+            choices.push(s);
         }
 
         let menu = ChoiceMenu::new("Select item:", choices.clone());
@@ -179,12 +894,14 @@ pub fn buy_prices_menu(db: &mut Database)
             break;
         }
 
-        let new_price = utils::parse_input::<u64>("New buy price: ", 1, std::u64::MAX);
-        db.set_item_buy_price(&item_list[choice as usize], new_price);
+        let new_price = utils::parse_input::<Isk>("New buy price: ",
+            Isk::from_cents(1), Isk::MAX);
+        db.set_item_buy_price(&item_list[choice as usize], new_price)?;
     }
+    Ok(())
 }
 
-pub fn sell_prices_menu(db: &mut Database)
+pub fn sell_prices_menu(db: &mut Database) -> Result<()>
 {
     loop
     {
@@ -194,13 +911,9 @@ pub fn sell_prices_menu(db: &mut Database)
 
         for iter in db.known_items.iter()
         {
-            if iter.1.produced
-            {
-                item_list.push(iter.1.name.clone());
-                let s = format!("{}: {}ISK",iter.1.name,
-                    iter.1.sell_price.to_formatted_string(&Locale::en));
-                choices.push(s);
-            }
+            item_list.push(iter.1.name.clone());
+            let s = format!("{}: {}ISK",iter.1.name, iter.1.sell_price);
+            choices.push(s);
         }
 
         let menu = ChoiceMenu::new("Select item:", choices.clone());
@@ -210,22 +923,24 @@ pub fn sell_prices_menu(db: &mut Database)
             break;
         }
 
-        let new_price = utils::parse_input::<u64>("New sell price: ", 1, std::u64::MAX);
-        db.set_item_sell_price(&item_list[choice as usize], new_price);
+        let new_price = utils::parse_input::<Isk>("New sell price: ",
+            Isk::from_cents(1), Isk::MAX);
+        db.set_item_sell_price(&item_list[choice as usize], new_price)?;
     }
+    Ok(())
 }
 
-fn shopping_list_menu(db: &mut Database)
+fn shopping_list_menu(db: &mut Database, settings: &Settings) -> Result<()>
 {
     println!("SHOPPING LIST:");
     let mut choices = vec!["Done".to_string()];
-    let mut pr_list = vec!["Done".to_string()];
-    let mut shopping_list = Vec::<String>::new();
+    let mut pr_list = vec![0];
+    let mut shopping_list = Vec::<u64>::new();
 
     for iter in db.get_productionrun_iter()
     {
-        pr_list.push(iter.0.clone());
-        choices.push(format!("{} x {}", iter.0.clone(), iter.1.jobruns));
+        pr_list.push(*iter.0);
+        choices.push(format!("{} x {} ({})", iter.1.name, iter.1.jobruns, iter.1.status));
     }
 
 
@@ -238,39 +953,32 @@ fn shopping_list_menu(db: &mut Database)
         {
             break;
         }
-        shopping_list.push(pr_list[choice as usize].clone());
+        shopping_list.push(pr_list[choice as usize]);
     }
 
-    let mut item_list = HashMap::<String, u64>::new();
     println!("------------------------------------------------------");
     println!("Shopping list productions:");
     for iter in shopping_list.iter()
     {
-        let pr = db.get_productionrun(iter).unwrap();
-        let materials = pr.get_production_materials(db);
-
-        println!("  {} x {}", iter, pr.jobruns);
-
-        for iter in materials.iter()
-        {
-            if item_list.contains_key(&iter.0)
-            {
-                *item_list.get_mut(&iter.0).unwrap() += iter.1;
-            }
-            else
-            {
-                item_list.insert(iter.0.to_string(),iter.1);
-            }
-        }
+        let pr = db.get_productionrun(*iter)
+            .ok_or_else(|| Error::UnknownProductionRun(iter.to_string()))?;
+        println!("  {} x {}", pr.name, pr.jobruns);
     }
+    let item_list = db.shopping_list(&shopping_list, &settings.fees, None)?;
 
     println!("Item shopping list:");
-    for iter in item_list.iter()
+    for iter in item_list.iter().filter(|e| e.to_buy > 0)
     {
-        let item = db.get_item(iter.0).unwrap();
-        println!("  {}  @{}  x  {}", item.name,
-            item.buy_price.to_formatted_string(&Locale::en), iter.1);
+        let item = db.get_item(&iter.item).ok_or_else(|| Error::UnknownItem(iter.item.clone()))?;
+        let cost = db.purchase_cost(item, iter.to_buy, &settings.fees);
+        println!("  {}  @{}  x  {}  ({} needed, {} in stock)", item.name,
+            cost.average_price.map_or_else(|| "?".to_string(), |p| p.to_string()),
+            iter.to_buy, iter.needed, iter.in_stock);
+        if cost.short > 0
+        {
+            println!("    insufficient market depth, {} short", cost.short);
+        }
     }
     println!("------------------------------------------------------");
+    Ok(())
 }
-
@@ -0,0 +1,162 @@
+use serde::{Serialize, Deserialize};
+
+use crate::isk::Isk;
+use crate::marketlogs::{MarketPrices, PricePoint};
+
+/// Orders of one price in an order book.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct BookOrder
+{
+    pub price: Isk,
+    pub volume: u64,
+}
+
+/// Snapshot of the market orders of one type. Both sides are sorted from the best price
+/// outwards.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct OrderBook
+{
+    pub buy: Vec<BookOrder>,
+    pub sell: Vec<BookOrder>,
+}
+
+/// Cost of buying a quantity of an item.
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct PurchaseCost
+{
+    /// Cost of the whole quantity, or `None` if the order book is too thin to fill it.
+    pub total: Option<Isk>,
+    /// Cost of the quantity the order book can fill.
+    pub filled_cost: Isk,
+    /// Average unit price of the filled quantity, or `None` if nothing could be filled.
+    pub average_price: Option<Isk>,
+    /// Quantity the order book could not fill.
+    pub short: u64,
+    /// Whether the cost was taken from an order book rather than a single price.
+    pub order_book: bool,
+}
+
+impl OrderBook
+{
+    /// Builds a book from orders given as price, volume and whether it is a buy order.
+    pub fn new(orders: impl IntoIterator<Item = (Isk, u64, bool)>) -> OrderBook
+    {
+        let mut book = OrderBook::default();
+        for (price, volume, bid) in orders
+        {
+            let side = if bid { &mut book.buy } else { &mut book.sell };
+            side.push(BookOrder {price, volume});
+        }
+        book.buy.sort_by_key(|o| std::cmp::Reverse(o.price));
+        book.sell.sort_by_key(|o| o.price);
+        book
+    }
+
+    pub fn prices(&self, point: PricePoint) -> MarketPrices
+    {
+        MarketPrices {buy: price_at(&self.buy, point), sell: price_at(&self.sell, point)}
+    }
+
+    /// Cost of buying `quantity` instantly from the sell orders, cheapest first.
+    pub fn purchase_cost(&self, quantity: u64) -> PurchaseCost
+    {
+        let mut filled_cost = Isk::ZERO;
+        let mut remaining = quantity;
+        for order in self.sell.iter()
+        {
+            if remaining == 0
+            {
+                break;
+            }
+            let taken = remaining.min(order.volume);
+            filled_cost += order.price * taken;
+            remaining -= taken;
+        }
+        let filled = quantity - remaining;
+        let average_price = match filled
+        {
+            0 => self.sell.first().map(|o| o.price),
+            _ => Some(Isk::from_f64(filled_cost.as_f64() / filled as f64)),
+        };
+        PurchaseCost {total: (remaining == 0).then_some(filled_cost), filled_cost, average_price,
+            short: remaining, order_book: true}
+    }
+}
+
+impl PurchaseCost
+{
+    /// Cost of buying at a single unit price.
+    pub fn flat(price: Isk, quantity: u64) -> PurchaseCost
+    {
+        PurchaseCost {total: Some(price * quantity), filled_cost: price * quantity,
+            average_price: Some(price), short: 0, order_book: false}
+    }
+}
+
+/// Price of the order book side `orders`, sorted from the best price outwards.
+fn price_at(orders: &[BookOrder], point: PricePoint) -> Option<Isk>
+{
+    let best = orders.first()?.price;
+    let percent = match point
+    {
+        PricePoint::Best => return Some(best),
+        PricePoint::Percentile(p) => p,
+    };
+    let total: u64 = orders.iter().map(|o| o.volume).sum();
+    let threshold = total as f64 * percent / 100.0;
+    let mut cumulative = 0;
+    for order in orders.iter()
+    {
+        cumulative += order.volume;
+        if cumulative as f64 >= threshold
+        {
+            return Some(order.price);
+        }
+    }
+    Some(best)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn book() -> OrderBook
+    {
+        OrderBook::new(vec![(Isk::from_cents(1200), 10, false), (Isk::from_cents(800), 4, true),
+            (Isk::from_cents(1000), 5, false), (Isk::from_cents(900), 3, true)])
+    }
+
+    #[test]
+    fn sorts_both_sides_best_first()
+    {
+        let book = book();
+        assert_eq!(book.sell.iter().map(|o| o.price.as_f64()).collect::<Vec<_>>(), [10.0, 12.0]);
+        assert_eq!(book.buy.iter().map(|o| o.price.as_f64()).collect::<Vec<_>>(), [9.0, 8.0]);
+        let prices = book.prices(PricePoint::Best);
+        assert_eq!((prices.buy, prices.sell), (Some(Isk::from_cents(900)),
+            Some(Isk::from_cents(1000))));
+        assert_eq!(book.prices(PricePoint::Percentile(50.0)).sell, Some(Isk::from_cents(1200)));
+    }
+
+    #[test]
+    fn walks_the_sell_orders()
+    {
+        let cost = book().purchase_cost(8);
+        assert_eq!(cost.total, Some(Isk::from_cents(8600)));
+        assert_eq!(cost.average_price, Some(Isk::from_cents(1075)));
+        assert_eq!(cost.short, 0);
+    }
+
+    #[test]
+    fn thin_book_has_no_total()
+    {
+        let cost = book().purchase_cost(20);
+        assert_eq!(cost.total, None);
+        assert_eq!(cost.filled_cost, Isk::from_cents(17000));
+        assert_eq!(cost.short, 5);
+
+        let empty = OrderBook::default().purchase_cost(3);
+        assert_eq!((empty.total, empty.average_price, empty.short), (None, None, 3));
+    }
+}
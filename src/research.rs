@@ -0,0 +1,360 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::evedata::{Blueprint, BlueprintKind, Database};
+use crate::industry::{self, Facility, JobActivity, Skills};
+use crate::isk::Isk;
+use crate::settings::Settings;
+
+pub const MAX_MATERIAL_RESEARCH: u8 = 10;
+pub const MAX_TIME_RESEARCH: u8 = 20;
+
+/// Duration of each of the ten research levels in 105ths of the blueprint's base research
+/// time. Every level adds 1% ME or 2% TE.
+const LEVEL_MODIFIERS: [u64; 10] = [105, 250, 595, 1414, 3360, 8000, 19000, 45255, 107700,
+    256000];
+
+/// Science jobs installed on a blueprint original.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScienceActivity
+{
+    MaterialResearch,
+    TimeResearch,
+    Copying,
+}
+
+/// One research level of a plan.
+#[derive(Serialize, Clone, Debug)]
+pub struct ResearchStep
+{
+    pub activity: ScienceActivity,
+    /// ME or TE reached by this level.
+    pub level: u8,
+    /// Duration in seconds.
+    pub time: u64,
+    pub job_cost: Isk,
+    /// Time and cost from the blueprint's current research up to this level.
+    pub total_time: u64,
+    pub total_cost: Isk,
+    /// Material research only: materials saved per manufacturing run compared to the
+    /// current ME, at current prices.
+    pub savings_per_run: Option<Isk>,
+    /// Material research only: manufacturing runs until the savings pay for `total_cost`.
+    pub payback_runs: Option<u64>,
+    /// Time research only: seconds saved per manufacturing run compared to the current TE.
+    pub time_saved_per_run: Option<u64>,
+}
+
+/// Copying job making `copies` blueprint copies of `runs` runs each.
+#[derive(Serialize, Clone, Debug)]
+pub struct CopyJob
+{
+    pub copies: u64,
+    pub runs: u64,
+    /// Duration in seconds.
+    pub time: u64,
+    pub job_cost: Isk,
+}
+
+impl ScienceActivity
+{
+    pub fn sde_name(self) -> &'static str
+    {
+        match self
+        {
+            ScienceActivity::MaterialResearch => "research_material",
+            ScienceActivity::TimeResearch => "research_time",
+            ScienceActivity::Copying => "copying",
+        }
+    }
+
+    /// Activity name of the solar system cost indices as used by ESI.
+    pub fn cost_index_name(self) -> &'static str
+    {
+        match self
+        {
+            ScienceActivity::MaterialResearch => "researching_material_efficiency",
+            ScienceActivity::TimeResearch => "researching_time_efficiency",
+            ScienceActivity::Copying => "copying",
+        }
+    }
+
+    /// Metallurgy, Research and Science reduce the time of their activity by 5% per level,
+    /// Advanced Industry the time of all science jobs by 3% per level.
+    fn skill_multiplier(self, skills: &Skills) -> f64
+    {
+        let skill = match self
+        {
+            ScienceActivity::MaterialResearch => skills.metallurgy,
+            ScienceActivity::TimeResearch => skills.research,
+            ScienceActivity::Copying => skills.copying,
+        };
+        (1.0 - 0.05 * skill as f64) * (1.0 - 0.03 * skills.advanced_industry as f64)
+    }
+}
+
+/// Research levels from the blueprint's current ME and TE up to `target_me` and
+/// `target_te`, material research first. Without a solar system in `facility` job costs
+/// are zero.
+pub fn research_steps(db: &Database, settings: &Settings, bp: &Blueprint, target_me: u8,
+    target_te: u8, facility: &Facility) -> Result<Vec<ResearchStep>>
+{
+    check_blueprint(bp, facility)?;
+    check_target_te(target_te)?;
+    let mut material_savings = Isk::ZERO;
+    for (name, quantity) in bp.materials.iter()
+    {
+        let item = db.get_item(name).ok_or_else(|| Error::UnknownItem(name.clone()))?;
+        material_savings += settings.fees.material_price(item) * *quantity;
+    }
+    let manufacturing_time = bp.base_time as f64
+        * settings.skills.time_multiplier(JobActivity::Manufacturing);
+
+    let mut ret = Vec::<ResearchStep>::new();
+    let activities = [(ScienceActivity::MaterialResearch, bp.material_research, target_me, 1),
+        (ScienceActivity::TimeResearch, bp.time_research, target_te, 2)];
+    for (activity, current, target, per_level) in activities
+    {
+        let base_time = db.sde_activity_time(bp.bp_id, activity.sde_name())? as f64;
+        let (mut total_time, mut total_cost) = (0, Isk::ZERO);
+        for level in (current / per_level + 1)..=(target / per_level)
+        {
+            let modifier = LEVEL_MODIFIERS[level as usize - 1] as f64 / 105.0;
+            let time = (base_time * modifier * activity.skill_multiplier(&settings.skills)
+                * facility.science_time_multiplier()).round() as u64;
+            let job_cost = job_cost(db, bp, activity, facility, modifier)?;
+            total_time += time;
+            total_cost += job_cost;
+
+            let gained = (level * per_level - current) as f64 / 100.0;
+            let mut step = ResearchStep {activity, level: level * per_level, time, job_cost,
+                total_time, total_cost, savings_per_run: None, payback_runs: None,
+                time_saved_per_run: None};
+            match activity
+            {
+                ScienceActivity::MaterialResearch =>
+                {
+                    let savings = Isk::from_f64(material_savings.as_f64() * gained);
+                    step.savings_per_run = Some(savings);
+                    step.payback_runs = (savings > Isk::ZERO)
+                        .then(|| (total_cost.as_f64() / savings.as_f64()).ceil() as u64);
+                }
+                _ => step.time_saved_per_run = Some((manufacturing_time * gained).round() as u64),
+            }
+            ret.push(step);
+        }
+    }
+    Ok(ret)
+}
+
+pub fn copy_job(db: &Database, settings: &Settings, bp: &Blueprint, copies: u64, runs: u64,
+    facility: &Facility) -> Result<CopyJob>
+{
+    check_blueprint(bp, facility)?;
+    if copies > bp.max_production_limit
+    {
+        return Err(Error::CopyLimitExceeded(bp.name.clone(), bp.max_production_limit));
+    }
+    if runs > bp.max_production_limit
+    {
+        return Err(Error::RunLimitExceeded(format!("A copy of {}", bp.name),
+            bp.max_production_limit));
+    }
+    let activity = ScienceActivity::Copying;
+    let total_runs = copies as f64 * runs as f64;
+    let time = (db.sde_activity_time(bp.bp_id, activity.sde_name())? as f64 * total_runs
+        * activity.skill_multiplier(&settings.skills) * facility.science_time_multiplier())
+        .round() as u64;
+    Ok(CopyJob {copies, runs, time,
+        job_cost: job_cost(db, bp, activity, facility, total_runs)?})
+}
+
+/// Every level of time research adds 2% TE, so only even targets can be reached.
+pub fn check_target_te(target_te: u8) -> Result<()>
+{
+    if !target_te.is_multiple_of(2)
+    {
+        return Err(Error::OddTimeResearch(target_te));
+    }
+    Ok(())
+}
+
+fn check_blueprint(bp: &Blueprint, facility: &Facility) -> Result<()>
+{
+    if bp.activity == JobActivity::Reaction
+    {
+        return Err(Error::NotResearchable(bp.name.clone()));
+    }
+    if bp.kind == BlueprintKind::Copy
+    {
+        return Err(Error::BlueprintCopy(bp.name.clone()));
+    }
+    if facility.structure.is_refinery()
+    {
+        return Err(Error::InvalidFacility(bp.name.clone(),
+            format!("{} has no laboratory", facility.structure)));
+    }
+    Ok(())
+}
+
+/// Installation cost of a science job, `multiplier` times the cost of one base run.
+fn job_cost(db: &Database, bp: &Blueprint, activity: ScienceActivity, facility: &Facility,
+    multiplier: f64) -> Result<Isk>
+{
+    let system = match facility.solar_system
+    {
+        Some(system) => system,
+        None => return Ok(Isk::ZERO),
+    };
+    let eiv = db.sde_estimated_item_value(bp.bp_id, JobActivity::Manufacturing)?
+        .percent(industry::SCIENCE_JOB_EIV_PERCENT);
+    Ok(industry::installation_cost(Isk::from_f64(eiv.as_f64() * multiplier),
+        db.get_cost_index(system, activity.cost_index_name())?, facility))
+}
+
+impl fmt::Display for ScienceActivity
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            ScienceActivity::MaterialResearch => write!(f, "ME research"),
+            ScienceActivity::TimeResearch => write!(f, "TE research"),
+            ScienceActivity::Copying => write!(f, "copying"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::collections::HashMap;
+
+    use crate::evedata::Item;
+    use crate::sde::{Activity, Product, Sde, SdeBlueprint, SdeType};
+
+    use super::*;
+
+    const NO_SKILLS: Skills = Skills {industry: 0, advanced_industry: 0, implant_time_bonus: 0.0,
+        encryption: 0, science: 0, reactions: 0, metallurgy: 0, research: 0, copying: 0};
+
+    /// The Rifter Blueprint is a rank 1 blueprint, whose research levels take 105 seconds
+    /// times the level modifier. Its 100 Tritanium have an estimated value of 1,000,000 ISK
+    /// and are bought for 12 ISK each. Solar system 1 has a research cost index of 5%.
+    fn rifter_database() -> Database
+    {
+        let mut sde = Sde::default();
+        for (id, name) in [(34, "Tritanium"), (587, "Rifter"), (691, "Rifter Blueprint")].iter()
+        {
+            sde.types.insert(*id, SdeType {name: name.to_string(), group_id: 0, published: true});
+        }
+        let manufacturing = Activity {time: 6000, materials: vec![(34, 100)],
+            products: vec![Product {type_id: 587, quantity: 1, probability: None}],
+            skills: Vec::new()};
+        let research = || Activity {time: 105, ..Activity::default()};
+        sde.blueprints.insert(691, SdeBlueprint {max_production_limit: 300,
+            activities: vec![("manufacturing".to_string(), manufacturing),
+                ("research_material".to_string(), research()),
+                ("research_time".to_string(), research())].into_iter().collect()});
+        let mut db = Database::from_sde(sde);
+        db.known_items.insert("Tritanium".to_string(), Item {name: "Tritanium".to_string(),
+            id: 34, buy_price: Isk::from_f64(12.0), sell_price: Isk::from_f64(13.0),
+            produced: false, stock: HashMap::new()});
+        db.adjusted_prices.insert(34, Isk::from_f64(10_000.0));
+        db.cost_indices.insert(1, vec![
+            ("researching_material_efficiency".to_string(), 0.05),
+            ("researching_time_efficiency".to_string(), 0.05)].into_iter().collect());
+        db
+    }
+
+    fn rifter_blueprint(material_research: u8, time_research: u8) -> Blueprint
+    {
+        Blueprint {name: "Rifter Blueprint".to_string(), bp_id: 691,
+            activity: JobActivity::Manufacturing, materials: vec![("Tritanium".to_string(), 100)],
+            material_research, time_research, products: vec![("Rifter".to_string(), 1)],
+            base_time: 6000, invention: None, kind: BlueprintKind::Original, quantity: 1, runs: 0,
+            max_production_limit: 300}
+    }
+
+    fn in_system() -> Facility
+    {
+        Facility {solar_system: Some(1), ..Facility::default()}
+    }
+
+    #[test]
+    fn level_modifiers_grow_to_the_rank_times()
+    {
+        // Rank 1 levels take 1:45, 4:10, 9:55, 23:34, 56:00, 2:13:20, 5:16:40, 12:34:15,
+        // 1d 5:55:00 and 2d 23:06:40
+        assert_eq!(LEVEL_MODIFIERS, [105, 250, 595, 1414, 3360, 8000, 19000, 45255, 107700,
+            256000]);
+        assert_eq!(LEVEL_MODIFIERS.iter().sum::<u64>(), 441_679);
+    }
+
+    #[test]
+    fn material_research_times_costs_and_payback()
+    {
+        let db = rifter_database();
+        let settings = Settings::with_skills(Skills {metallurgy: 5, advanced_industry: 5,
+            ..NO_SKILLS});
+        let steps = research_steps(&db, &settings, &rifter_blueprint(0, 0), 10, 0, &in_system())
+            .unwrap();
+
+        // Metallurgy V and Advanced Industry V take the times down to 63.75%
+        let times = [67, 159, 379, 901, 2142, 5100, 12113, 28850, 68659, 163200];
+        // 2% of the estimated value times the level modifier, at 5% cost index, 0.25% tax
+        // and 4% SCC surcharge
+        let costs = [1_850.0, 4_404.76, 10_483.33, 24_913.33, 59_200.0, 140_952.38, 334_761.9,
+            797_350.0, 1_897_571.43, 4_510_476.19];
+        let payback = [155, 261, 465, 868, 1681, 3359, 6864, 14312, 30292, 64850];
+        assert_eq!(steps.len(), 10);
+        let mut total_cost = Isk::ZERO;
+        for (idx, step) in steps.iter().enumerate()
+        {
+            assert_eq!((step.activity, step.level), (ScienceActivity::MaterialResearch,
+                idx as u8 + 1));
+            assert_eq!(step.time, times[idx]);
+            assert_eq!(step.job_cost, Isk::from_f64(costs[idx]));
+            total_cost += step.job_cost;
+            assert_eq!(step.total_cost, total_cost);
+            // Every level saves 1% of the 1,200 ISK of materials per run
+            assert_eq!(step.savings_per_run, Some(Isk::from_f64(12.0 * (idx + 1) as f64)));
+            assert_eq!(step.payback_runs, Some(payback[idx]));
+        }
+        assert_eq!(steps[9].total_time, times.iter().sum::<u64>());
+        assert_eq!(steps[9].total_cost, Isk::from_f64(7_781_963.32));
+    }
+
+    #[test]
+    fn research_starts_at_the_current_level()
+    {
+        let db = rifter_database();
+        let steps = research_steps(&db, &Settings::with_skills(NO_SKILLS), &rifter_blueprint(8, 16),
+            10, 20, &Facility::default()).unwrap();
+        let levels: Vec<(ScienceActivity, u8, u64)> = steps.iter()
+            .map(|s| (s.activity, s.level, s.time))
+            .collect();
+        assert_eq!(levels, vec![(ScienceActivity::MaterialResearch, 9, 107700),
+            (ScienceActivity::MaterialResearch, 10, 256000),
+            (ScienceActivity::TimeResearch, 18, 107700),
+            (ScienceActivity::TimeResearch, 20, 256000)]);
+        // Savings count from the current ME, and there are no job costs without a system
+        assert_eq!(steps[1].savings_per_run, Some(Isk::from_f64(24.0)));
+        assert_eq!(steps[1].payback_runs, Some(0));
+        // Every TE level saves 2% of the 6,000 second manufacturing time
+        assert_eq!(steps[2].time_saved_per_run, Some(120));
+        assert_eq!(steps[3].time_saved_per_run, Some(240));
+    }
+
+    #[test]
+    fn odd_target_te_is_rejected()
+    {
+        let db = rifter_database();
+        let result = research_steps(&db, &Settings::with_skills(NO_SKILLS), &rifter_blueprint(0, 0),
+            0, 5, &Facility::default());
+        assert!(matches!(result, Err(Error::OddTimeResearch(5))));
+        assert!(check_target_te(20).is_ok());
+    }
+}
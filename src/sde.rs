@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, Result};
+use crate::sde_source::SdeSource;
+
+/// Bumped whenever the layout of `Sde` or the cache header changes so old caches are rebuilt.
+const CACHE_VERSION: u32 = 3;
+
+/// The parts of the static data export used by the program.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Sde
+{
+    pub types: HashMap<i64, SdeType>,
+    pub groups: HashMap<i64, SdeGroup>,
+    pub blueprints: HashMap<i64, SdeBlueprint>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SdeType
+{
+    /// English name.
+    pub name: String,
+    pub group_id: i64,
+    pub published: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SdeGroup
+{
+    /// English name.
+    pub name: String,
+    pub category_id: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SdeBlueprint
+{
+    /// Maximum number of runs of a single job or blueprint copy.
+    pub max_production_limit: u64,
+    /// Activities by their SDE name, e.g. `manufacturing` or `invention`.
+    pub activities: HashMap<String, Activity>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Activity
+{
+    /// Base duration of one run in seconds.
+    pub time: u64,
+    /// Type IDs and quantities consumed per run.
+    pub materials: Vec<(i64, u64)>,
+    pub products: Vec<Product>,
+    /// Required skills as type ID and level.
+    pub skills: Vec<(i64, u8)>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Product
+{
+    pub type_id: i64,
+    pub quantity: u64,
+    /// Base success chance of invention jobs.
+    pub probability: Option<f64>,
+}
+
+impl Sde
+{
+    /// Loads the SDE from the binary cache in `cache_dir`, or reads it from `source` and
+    /// rebuilds the cache if the source files changed since it was written.
+    pub fn load(source: &dyn SdeSource, cache_dir: &Path) -> Result<Sde>
+    {
+        let files = source.files();
+        let stamps = stamps(&files)?;
+        let cache_path = cache_dir.join("sde.bin");
+        let cached = match read_cache(&cache_path)
+        {
+            Some((header, sde)) if header.stamps == stamps => return Ok(sde),
+            cached => cached,
+        };
+
+        // Sizes or modification times changed, so only the contents can tell
+        let header = CacheHeader {stamps, checksum: checksum(&files)?};
+        let sde = match cached.filter(|(cached, _)| cached.checksum == header.checksum)
+        {
+            Some((_, sde)) => sde,
+            None =>
+            {
+                eprintln!("Building SDE cache");
+                source.read()?
+            }
+        };
+        if let Err(e) = write_cache(&cache_path, &header, &sde)
+        {
+            eprintln!("Could not write SDE cache: {}", e);
+        }
+        Ok(sde)
+    }
+}
+
+/// Size and modification time of a source file as recorded in the cache.
+#[derive(Serialize, Deserialize, PartialEq)]
+struct FileStamp
+{
+    path: PathBuf,
+    size: u64,
+    /// Nanoseconds since the Unix epoch.
+    modified: u128,
+}
+
+/// Written after `CACHE_VERSION`, which is read first so older layouts are never parsed.
+#[derive(Serialize, Deserialize)]
+struct CacheHeader
+{
+    stamps: Vec<FileStamp>,
+    /// SHA-256 over the contents of all source files.
+    checksum: [u8; 32],
+}
+
+fn stamps(paths: &[PathBuf]) -> Result<Vec<FileStamp>>
+{
+    paths.iter()
+        .map(|path|
+        {
+            let metadata = fs::metadata(path).map_err(|e| Error::Io(path.clone(), e))?;
+            let modified = metadata.modified().ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_nanos());
+            Ok(FileStamp {path: path.clone(), size: metadata.len(), modified})
+        })
+        .collect()
+}
+
+/// SHA-256 over the contents of all source files.
+fn checksum(paths: &[PathBuf]) -> Result<[u8; 32]>
+{
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 1 << 20];
+    for path in paths.iter()
+    {
+        let mut file = File::open(path).map_err(|e| Error::Io(path.clone(), e))?;
+        loop
+        {
+            let read = file.read(&mut buffer).map_err(|e| Error::Io(path.clone(), e))?;
+            if read == 0
+            {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// Returns the cached SDE with the header describing its sources, if the cache exists and
+/// was written by this version.
+fn read_cache(path: &Path) -> Option<(CacheHeader, Sde)>
+{
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let version: u32 = bincode::deserialize_from(&mut reader).ok()?;
+    if version != CACHE_VERSION
+    {
+        return None;
+    }
+    let header: CacheHeader = bincode::deserialize_from(&mut reader).ok()?;
+    match bincode::deserialize_from(&mut reader)
+    {
+        Ok(sde) => Some((header, sde)),
+        Err(e) =>
+        {
+            eprintln!("{}: discarding corrupt SDE cache: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn write_cache(path: &Path, header: &CacheHeader, sde: &Sde) -> std::result::Result<(), String>
+{
+    if let Some(dir) = path.parent()
+    {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    // Write to a temporary file first so an interrupted write never leaves a broken cache
+    let tmp = path.with_extension("tmp");
+    let mut writer = BufWriter::new(File::create(&tmp).map_err(|e| e.to_string())?);
+    bincode::serialize_into(&mut writer, &CACHE_VERSION).map_err(|e| e.to_string())?;
+    bincode::serialize_into(&mut writer, header).map_err(|e| e.to_string())?;
+    bincode::serialize_into(&mut writer, sde).map_err(|e| e.to_string())?;
+    writer.into_inner().map_err(|e| e.to_string())?;
+    fs::rename(&tmp, path).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::cell::Cell;
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+
+    /// One file whose contents become the name of type 1, counting full reads.
+    struct CountingSource
+    {
+        file: PathBuf,
+        reads: Cell<usize>,
+    }
+
+    impl SdeSource for CountingSource
+    {
+        fn files(&self) -> Vec<PathBuf>
+        {
+            vec![self.file.clone()]
+        }
+
+        fn read(&self) -> Result<Sde>
+        {
+            self.reads.set(self.reads.get() + 1);
+            let name = fs::read_to_string(&self.file).map_err(|e| Error::Io(self.file.clone(), e))?;
+            let mut sde = Sde::default();
+            sde.types.insert(1, SdeType {name, group_id: 0, published: true});
+            Ok(sde)
+        }
+    }
+
+    /// A source over `content` in an empty directory of its own, and its cache directory.
+    fn source(name: &str, content: &str) -> (CountingSource, PathBuf)
+    {
+        let dir = std::env::temp_dir()
+            .join(format!("eve_industry_terminal_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("types.txt");
+        fs::write(&file, content).unwrap();
+        (CountingSource {file, reads: Cell::new(0)}, dir.join("cache"))
+    }
+
+    fn set_modified(path: &Path, time: SystemTime)
+    {
+        File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+    }
+
+    #[test]
+    fn unchanged_sources_use_the_cache()
+    {
+        let (source, cache_dir) = source("sde_cache_unchanged", "Rifter");
+        assert_eq!(Sde::load(&source, &cache_dir).unwrap().types[&1].name, "Rifter");
+        assert_eq!(Sde::load(&source, &cache_dir).unwrap().types[&1].name, "Rifter");
+        assert_eq!(source.reads.get(), 1);
+        let _ = fs::remove_dir_all(cache_dir.parent().unwrap());
+    }
+
+    #[test]
+    fn touched_sources_with_the_same_content_use_the_cache()
+    {
+        let (source, cache_dir) = source("sde_cache_touched", "Rifter");
+        Sde::load(&source, &cache_dir).unwrap();
+        fs::write(&source.file, "Rifter").unwrap();
+        set_modified(&source.file, SystemTime::now() + Duration::from_secs(60));
+
+        assert_eq!(Sde::load(&source, &cache_dir).unwrap().types[&1].name, "Rifter");
+        assert_eq!(source.reads.get(), 1);
+        // The new stamp is recorded, so the next load does not hash the sources again
+        let (header, _) = read_cache(&cache_dir.join("sde.bin")).unwrap();
+        assert!(header.stamps == stamps(&source.files()).unwrap());
+        let _ = fs::remove_dir_all(cache_dir.parent().unwrap());
+    }
+
+    #[test]
+    fn changed_content_rebuilds_the_cache()
+    {
+        let (source, cache_dir) = source("sde_cache_changed", "Rifter");
+        Sde::load(&source, &cache_dir).unwrap();
+        // Same size, so only the checksum tells the files apart
+        fs::write(&source.file, "Breach").unwrap();
+        set_modified(&source.file, SystemTime::now() + Duration::from_secs(60));
+
+        assert_eq!(Sde::load(&source, &cache_dir).unwrap().types[&1].name, "Breach");
+        assert_eq!(source.reads.get(), 2);
+        assert_eq!(Sde::load(&source, &cache_dir).unwrap().types[&1].name, "Breach");
+        assert_eq!(source.reads.get(), 2);
+        let _ = fs::remove_dir_all(cache_dir.parent().unwrap());
+    }
+
+    #[test]
+    fn corrupt_cache_is_rebuilt()
+    {
+        let (source, cache_dir) = source("sde_cache_corrupt", "Rifter");
+        Sde::load(&source, &cache_dir).unwrap();
+        let cache_path = cache_dir.join("sde.bin");
+        let mut bytes = fs::read(&cache_path).unwrap();
+        bytes.truncate(bytes.len() - 3);
+        fs::write(&cache_path, &bytes).unwrap();
+
+        assert_eq!(Sde::load(&source, &cache_dir).unwrap().types[&1].name, "Rifter");
+        assert_eq!(source.reads.get(), 2);
+        fs::write(&cache_path, "garbage").unwrap();
+        assert_eq!(Sde::load(&source, &cache_dir).unwrap().types[&1].name, "Rifter");
+        assert_eq!(source.reads.get(), 3);
+        assert!(read_cache(&cache_path).is_some());
+        let _ = fs::remove_dir_all(cache_dir.parent().unwrap());
+    }
+}
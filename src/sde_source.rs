@@ -0,0 +1,669 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use yaml_rust::Yaml;
+
+use crate::error::{Error, Result};
+use crate::sde::{Activity, Product, Sde, SdeBlueprint, SdeGroup, SdeType};
+use crate::utils;
+
+/// A distribution format of the static data export that can fill the typed `Sde` model.
+pub trait SdeSource
+{
+    /// Files the SDE is read from. Their contents key the binary cache.
+    fn files(&self) -> Vec<PathBuf>;
+
+    fn read(&self) -> Result<Sde>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SdeFormat
+{
+    /// Legacy `fsd/typeIDs.yaml`, `fsd/blueprints.yaml` and optionally `fsd/groupIDs.yaml`.
+    Yaml,
+    /// `types.jsonl`, `blueprints.jsonl` and optionally `groups.jsonl` of the JSON lines
+    /// export.
+    Jsonl,
+    /// Fuzzwork style SQLite conversion.
+    Sqlite,
+}
+
+impl SdeFormat
+{
+    pub const ALL: [SdeFormat; 3] = [SdeFormat::Yaml, SdeFormat::Jsonl, SdeFormat::Sqlite];
+}
+
+/// Returns the source for an SDE of the given format at `path`. For SQLite `path` may be
+/// the database itself or a directory containing `sqlite-latest.sqlite`.
+pub fn open(format: SdeFormat, path: &Path) -> Box<dyn SdeSource>
+{
+    match format
+    {
+        SdeFormat::Yaml => Box::new(YamlSource {dir: path.to_path_buf()}),
+        SdeFormat::Jsonl => Box::new(JsonlSource {dir: path.to_path_buf()}),
+        SdeFormat::Sqlite =>
+        {
+            let file = if path.is_dir() { path.join("sqlite-latest.sqlite") }
+                else { path.to_path_buf() };
+            Box::new(SqliteSource {file})
+        }
+    }
+}
+
+pub struct YamlSource
+{
+    dir: PathBuf,
+}
+
+pub struct JsonlSource
+{
+    dir: PathBuf,
+}
+
+pub struct SqliteSource
+{
+    file: PathBuf,
+}
+
+impl SdeSource for YamlSource
+{
+    fn files(&self) -> Vec<PathBuf>
+    {
+        let mut ret = vec![self.dir.join("fsd/blueprints.yaml"), self.dir.join("fsd/typeIDs.yaml")];
+        ret.extend(existing(self.dir.join("fsd/groupIDs.yaml")));
+        ret
+    }
+
+    fn read(&self) -> Result<Sde>
+    {
+        let files = self.files();
+        let groups = match files.get(2)
+        {
+            Some(path) => read_yaml_groups(path)?,
+            None => HashMap::new(),
+        };
+        Ok(Sde {blueprints: read_yaml_blueprints(&files[0])?, types: read_yaml_types(&files[1])?,
+            groups})
+    }
+}
+
+fn read_yaml_types(path: &Path) -> Result<HashMap<i64, SdeType>>
+{
+    let yaml = utils::load_yaml(path)?;
+    let mut ret = HashMap::new();
+    for (id, entry) in yaml.as_hash().into_iter().flatten()
+    {
+        // Types without an english name can never be looked up, so they are left out
+        if let (Some(id), Some(name)) = (id.as_i64(), entry["name"]["en"].as_str())
+        {
+            ret.insert(id, SdeType {name: name.to_string(),
+                group_id: entry["groupID"].as_i64().unwrap_or(0),
+                published: entry["published"].as_bool().unwrap_or(false)});
+        }
+    }
+    Ok(ret)
+}
+
+fn read_yaml_groups(path: &Path) -> Result<HashMap<i64, SdeGroup>>
+{
+    let yaml = utils::load_yaml(path)?;
+    let mut ret = HashMap::new();
+    for (id, entry) in yaml.as_hash().into_iter().flatten()
+    {
+        if let (Some(id), Some(name)) = (id.as_i64(), entry["name"]["en"].as_str())
+        {
+            ret.insert(id, SdeGroup {name: name.to_string(),
+                category_id: entry["categoryID"].as_i64().unwrap_or(0)});
+        }
+    }
+    Ok(ret)
+}
+
+fn read_yaml_blueprints(path: &Path) -> Result<HashMap<i64, SdeBlueprint>>
+{
+    let yaml = utils::load_yaml(path)?;
+    let mut ret = HashMap::new();
+    for (id, entry) in yaml.as_hash().into_iter().flatten()
+    {
+        let id = match id.as_i64()
+        {
+            Some(id) => id,
+            None => continue,
+        };
+        let mut activities = HashMap::new();
+        for (name, activity) in entry["activities"].as_hash().into_iter().flatten()
+        {
+            if let Some(name) = name.as_str()
+            {
+                activities.insert(name.to_string(), read_yaml_activity(id, activity)?);
+            }
+        }
+        let max_production_limit = unsigned(id, &entry["maxProductionLimit"], "maxProductionLimit")?
+            .unwrap_or(1);
+        ret.insert(id, SdeBlueprint {max_production_limit, activities});
+    }
+    Ok(ret)
+}
+
+fn read_yaml_activity(bp_id: i64, yaml: &Yaml) -> Result<Activity>
+{
+    let mut ret = Activity {time: unsigned(bp_id, &yaml["time"], "time")?.unwrap_or(0),
+        ..Activity::default()};
+    for material in yaml["materials"].as_vec().into_iter().flatten()
+    {
+        ret.materials.push((type_id(bp_id, material)?, quantity(bp_id, material)?));
+    }
+    for product in yaml["products"].as_vec().into_iter().flatten()
+    {
+        ret.products.push(Product {type_id: type_id(bp_id, product)?,
+            quantity: quantity(bp_id, product)?,
+            probability: product["probability"].as_f64()});
+    }
+    for skill in yaml["skills"].as_vec().into_iter().flatten()
+    {
+        let level = unsigned(bp_id, &skill["level"], "level")?.unwrap_or(0);
+        ret.skills.push((type_id(bp_id, skill)?,
+            level.try_into().map_err(|_| Error::MissingField(bp_id, "level"))?));
+    }
+    Ok(ret)
+}
+
+fn type_id(bp_id: i64, yaml: &Yaml) -> Result<i64>
+{
+    yaml["typeID"].as_i64().ok_or(Error::MissingField(bp_id, "typeID"))
+}
+
+/// Quantity of a material or product. Products without one are made once per run.
+fn quantity(bp_id: i64, yaml: &Yaml) -> Result<u64>
+{
+    Ok(unsigned(bp_id, &yaml["quantity"], "quantity")?.unwrap_or(1))
+}
+
+/// Reads an optional non-negative integer field.
+fn unsigned(bp_id: i64, yaml: &Yaml, field: &'static str) -> Result<Option<u64>>
+{
+    match yaml
+    {
+        Yaml::BadValue | Yaml::Null => Ok(None),
+        _ => yaml.as_i64().and_then(|v| v.try_into().ok()).map(Some)
+            .ok_or(Error::MissingField(bp_id, field)),
+    }
+}
+
+/// One line of `types.jsonl`.
+#[derive(Deserialize)]
+struct JsonType
+{
+    #[serde(rename = "_key")]
+    id: i64,
+    #[serde(rename = "groupID", default)]
+    group_id: i64,
+    #[serde(default)]
+    name: HashMap<String, String>,
+    #[serde(default)]
+    published: bool,
+}
+
+/// One line of `groups.jsonl`.
+#[derive(Deserialize)]
+struct JsonGroup
+{
+    #[serde(rename = "_key")]
+    id: i64,
+    #[serde(rename = "categoryID", default)]
+    category_id: i64,
+    #[serde(default)]
+    name: HashMap<String, String>,
+}
+
+/// One line of `blueprints.jsonl`.
+#[derive(Deserialize)]
+struct JsonBlueprint
+{
+    #[serde(rename = "_key")]
+    id: i64,
+    #[serde(default)]
+    activities: HashMap<String, JsonActivity>,
+    #[serde(rename = "maxProductionLimit")]
+    max_production_limit: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct JsonActivity
+{
+    #[serde(default)]
+    time: u64,
+    #[serde(default)]
+    materials: Vec<JsonQuantity>,
+    #[serde(default)]
+    products: Vec<JsonQuantity>,
+    #[serde(default)]
+    skills: Vec<JsonSkill>,
+}
+
+#[derive(Deserialize)]
+struct JsonQuantity
+{
+    #[serde(rename = "typeID")]
+    type_id: i64,
+    #[serde(default = "one")]
+    quantity: u64,
+    probability: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct JsonSkill
+{
+    #[serde(rename = "typeID")]
+    type_id: i64,
+    level: u8,
+}
+
+fn one() -> u64
+{
+    1
+}
+
+impl SdeSource for JsonlSource
+{
+    fn files(&self) -> Vec<PathBuf>
+    {
+        let mut ret = vec![self.dir.join("blueprints.jsonl"), self.dir.join("types.jsonl")];
+        ret.extend(existing(self.dir.join("groups.jsonl")));
+        ret
+    }
+
+    fn read(&self) -> Result<Sde>
+    {
+        let files = self.files();
+        let mut sde = Sde::default();
+        read_jsonl(&files[0], |bp: JsonBlueprint|
+        {
+            let activities = bp.activities.into_iter()
+                .map(|(name, a)| (name, Activity {time: a.time,
+                    materials: a.materials.iter().map(|m| (m.type_id, m.quantity)).collect(),
+                    products: a.products.into_iter()
+                        .map(|p| Product {type_id: p.type_id, quantity: p.quantity,
+                            probability: p.probability})
+                        .collect(),
+                    skills: a.skills.iter().map(|s| (s.type_id, s.level)).collect()}))
+                .collect();
+            sde.blueprints.insert(bp.id, SdeBlueprint {activities,
+                max_production_limit: bp.max_production_limit.unwrap_or(1)});
+        })?;
+        read_jsonl(&files[1], |t: JsonType|
+        {
+            if let Some(name) = t.name.get("en")
+            {
+                sde.types.insert(t.id, SdeType {name: name.clone(), group_id: t.group_id,
+                    published: t.published});
+            }
+        })?;
+        if let Some(path) = files.get(2)
+        {
+            read_jsonl(path, |g: JsonGroup|
+            {
+                if let Some(name) = g.name.get("en")
+                {
+                    sde.groups.insert(g.id, SdeGroup {name: name.clone(),
+                        category_id: g.category_id});
+                }
+            })?;
+        }
+        Ok(sde)
+    }
+}
+
+/// Optional source files only take part in the checksum if they exist.
+fn existing(path: PathBuf) -> Option<PathBuf>
+{
+    if path.exists() { Some(path) } else { None }
+}
+
+/// Parses every non-empty line of a JSON lines file and passes it to `f`.
+fn read_jsonl<T: DeserializeOwned>(path: &Path, mut f: impl FnMut(T)) -> Result<()>
+{
+    let file = File::open(path).map_err(|e| Error::Io(path.to_path_buf(), e))?;
+    for (idx, line) in BufReader::new(file).lines().enumerate()
+    {
+        let line = line.map_err(|e| Error::Io(path.to_path_buf(), e))?;
+        if line.trim().is_empty()
+        {
+            continue;
+        }
+        f(serde_json::from_str(&line).map_err(|e| Error::JsonLine(path.to_path_buf(), idx + 1, e))?);
+    }
+    Ok(())
+}
+
+impl SdeSource for SqliteSource
+{
+    fn files(&self) -> Vec<PathBuf>
+    {
+        vec![self.file.clone()]
+    }
+
+    fn read(&self) -> Result<Sde>
+    {
+        let conn = rusqlite::Connection::open_with_flags(&self.file,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(|e| self.error(e))?;
+        self.read_tables(&conn).map_err(|e| self.error(e))
+    }
+}
+
+impl SqliteSource
+{
+    fn error(&self, e: rusqlite::Error) -> Error
+    {
+        Error::Sqlite(self.file.clone(), e)
+    }
+
+    fn read_tables(&self, conn: &rusqlite::Connection) -> rusqlite::Result<Sde>
+    {
+        let mut sde = Sde::default();
+
+        let mut stmt = conn.prepare("SELECT typeID, groupID, typeName, published FROM invTypes")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()?
+        {
+            if let Some(name) = row.get::<_, Option<String>>(2)?
+            {
+                sde.types.insert(row.get(0)?, SdeType {name,
+                    group_id: row.get::<_, Option<i64>>(1)?.unwrap_or(0),
+                    published: row.get::<_, Option<i64>>(3)?.unwrap_or(0) != 0});
+            }
+        }
+
+        let mut stmt = conn.prepare("SELECT groupID, categoryID, groupName FROM invGroups")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()?
+        {
+            if let Some(name) = row.get::<_, Option<String>>(2)?
+            {
+                sde.groups.insert(row.get(0)?, SdeGroup {name,
+                    category_id: row.get::<_, Option<i64>>(1)?.unwrap_or(0)});
+            }
+        }
+
+        let mut stmt = conn.prepare("SELECT typeID, maxProductionLimit FROM industryBlueprints")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()?
+        {
+            sde.blueprints.insert(row.get(0)?, SdeBlueprint {activities: HashMap::new(),
+                max_production_limit: row.get::<_, Option<u64>>(1)?.unwrap_or(1)});
+        }
+
+        let mut stmt = conn.prepare("SELECT typeID, activityID, time FROM industryActivity")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()?
+        {
+            if let Some(activity) = activity(&mut sde, row.get(0)?, row.get(1)?)
+            {
+                activity.time = row.get::<_, Option<u64>>(2)?.unwrap_or(0);
+            }
+        }
+
+        let mut stmt = conn.prepare("SELECT typeID, activityID, materialTypeID, quantity \
+            FROM industryActivityMaterials")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()?
+        {
+            if let Some(activity) = activity(&mut sde, row.get(0)?, row.get(1)?)
+            {
+                activity.materials.push((row.get(2)?, row.get(3)?));
+            }
+        }
+
+        let mut stmt = conn.prepare("SELECT p.typeID, p.activityID, p.productTypeID, p.quantity, \
+            pr.probability FROM industryActivityProducts p \
+            LEFT JOIN industryActivityProbabilities pr ON pr.typeID = p.typeID \
+            AND pr.activityID = p.activityID AND pr.productTypeID = p.productTypeID")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()?
+        {
+            if let Some(activity) = activity(&mut sde, row.get(0)?, row.get(1)?)
+            {
+                activity.products.push(Product {type_id: row.get(2)?, quantity: row.get(3)?,
+                    probability: row.get(4)?});
+            }
+        }
+
+        let mut stmt = conn.prepare("SELECT typeID, activityID, skillID, level \
+            FROM industryActivitySkills")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()?
+        {
+            if let Some(activity) = activity(&mut sde, row.get(0)?, row.get(1)?)
+            {
+                activity.skills.push((row.get(2)?, row.get(3)?));
+            }
+        }
+        Ok(sde)
+    }
+}
+
+/// Returns the activity of a blueprint by its numeric SQLite ID, creating it if needed.
+/// Rows of unknown blueprints or obsolete activities are skipped.
+fn activity(sde: &mut Sde, bp_id: i64, activity_id: i64) -> Option<&mut Activity>
+{
+    let name = match activity_id
+    {
+        1 => "manufacturing",
+        3 => "research_time",
+        4 => "research_material",
+        5 => "copying",
+        8 => "invention",
+        11 => "reaction",
+        _ => return None,
+    };
+    Some(sde.blueprints.get_mut(&bp_id)?.activities.entry(name.to_string()).or_default())
+}
+
+impl fmt::Display for SdeFormat
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            SdeFormat::Yaml => write!(f, "yaml"),
+            SdeFormat::Jsonl => write!(f, "jsonl"),
+            SdeFormat::Sqlite => write!(f, "sqlite"),
+        }
+    }
+}
+
+impl FromStr for SdeFormat
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<SdeFormat, String>
+    {
+        SdeFormat::ALL.iter().copied().find(|x| x.to_string() == s)
+            .ok_or_else(|| format!("unknown SDE format '{}'", s))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs;
+
+    use super::*;
+
+    /// An empty directory of its own under the system temp directory.
+    fn temp_dir(name: &str) -> PathBuf
+    {
+        let dir = std::env::temp_dir()
+            .join(format!("eve_industry_terminal_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Every fixture holds the Rifter, its blueprint with manufacturing and invention,
+    /// Tritanium and the Frigate group.
+    fn check_fixture(sde: &Sde)
+    {
+        assert_eq!(sde.types.len(), 3);
+        let rifter = &sde.types[&587];
+        assert_eq!((rifter.name.as_str(), rifter.group_id, rifter.published),
+            ("Rifter", 25, true));
+        assert!(!sde.types[&34].published);
+        assert_eq!(sde.groups[&25].name, "Frigate");
+        assert_eq!(sde.groups[&25].category_id, 6);
+
+        let bp = &sde.blueprints[&691];
+        assert_eq!(bp.max_production_limit, 300);
+        let manufacturing = &bp.activities["manufacturing"];
+        assert_eq!(manufacturing.time, 6000);
+        assert_eq!(manufacturing.materials, vec![(34, 32000)]);
+        assert_eq!(manufacturing.products.len(), 1);
+        assert_eq!(manufacturing.products[0].type_id, 587);
+        assert_eq!(manufacturing.products[0].quantity, 1);
+        assert_eq!(manufacturing.products[0].probability, None);
+        assert_eq!(manufacturing.skills, vec![(3380, 1)]);
+        let invention = &bp.activities["invention"];
+        assert_eq!(invention.time, 63900);
+        assert_eq!(invention.products[0].type_id, 11379);
+        assert_eq!(invention.products[0].probability, Some(0.3));
+    }
+
+    const YAML_TYPES: &str = "34:\n  groupID: 18\n  name:\n    en: Tritanium\n  \
+        published: false\n587:\n  groupID: 25\n  name:\n    en: Rifter\n  published: true\n\
+        691:\n  groupID: 105\n  name:\n    en: Rifter Blueprint\n  published: true\n\
+        999:\n  groupID: 1\n  name:\n    de: Nur Deutsch\n";
+    const YAML_GROUPS: &str = "25:\n  categoryID: 6\n  name:\n    en: Frigate\n";
+    const YAML_BLUEPRINTS: &str = "691:\n  activities:\n    invention:\n      products:\n      \
+        - probability: 0.3\n        quantity: 1\n        typeID: 11379\n      time: 63900\n    \
+        manufacturing:\n      materials:\n      - quantity: 32000\n        typeID: 34\n      \
+        products:\n      - quantity: 1\n        typeID: 587\n      skills:\n      \
+        - level: 1\n        typeID: 3380\n      time: 6000\n  maxProductionLimit: 300\n";
+
+    fn write_yaml(dir: &Path, blueprints: &str)
+    {
+        fs::create_dir_all(dir.join("fsd")).unwrap();
+        fs::write(dir.join("fsd/typeIDs.yaml"), YAML_TYPES).unwrap();
+        fs::write(dir.join("fsd/groupIDs.yaml"), YAML_GROUPS).unwrap();
+        fs::write(dir.join("fsd/blueprints.yaml"), blueprints).unwrap();
+    }
+
+    #[test]
+    fn reads_yaml()
+    {
+        let dir = temp_dir("sde_yaml");
+        write_yaml(&dir, YAML_BLUEPRINTS);
+        check_fixture(&open(SdeFormat::Yaml, &dir).read().unwrap());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn malformed_yaml_is_an_error()
+    {
+        let dir = temp_dir("sde_yaml_malformed");
+        write_yaml(&dir, "691: [unclosed\n");
+        assert!(matches!(open(SdeFormat::Yaml, &dir).read(), Err(Error::Yaml(..))));
+        write_yaml(&dir, &YAML_BLUEPRINTS.replace("quantity: 32000", "quantity: -5"));
+        assert!(matches!(open(SdeFormat::Yaml, &dir).read(),
+            Err(Error::MissingField(691, "quantity"))));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    const JSONL_TYPES: &str = "{\"_key\": 34, \"groupID\": 18, \"name\": {\"en\": \"Tritanium\"}, \
+        \"published\": false}\n\
+        {\"_key\": 587, \"groupID\": 25, \"name\": {\"en\": \"Rifter\"}, \"published\": true}\n\
+        \n\
+        {\"_key\": 691, \"groupID\": 105, \"name\": {\"en\": \"Rifter Blueprint\"}, \
+        \"published\": true}\n\
+        {\"_key\": 999, \"groupID\": 1, \"name\": {\"de\": \"Nur Deutsch\"}}\n";
+    const JSONL_GROUPS: &str =
+        "{\"_key\": 25, \"categoryID\": 6, \"name\": {\"en\": \"Frigate\"}}\n";
+    const JSONL_BLUEPRINTS: &str = "{\"_key\": 691, \"maxProductionLimit\": 300, \"activities\": \
+        {\"manufacturing\": {\"time\": 6000, \"materials\": \
+        [{\"typeID\": 34, \"quantity\": 32000}], \
+        \"products\": [{\"typeID\": 587, \"quantity\": 1}], \
+        \"skills\": [{\"typeID\": 3380, \"level\": 1}]}, \
+        \"invention\": {\"time\": 63900, \"products\": [{\"typeID\": 11379, \"quantity\": 1, \
+        \"probability\": 0.3}]}}}\n";
+
+    fn write_jsonl(dir: &Path, types: &str)
+    {
+        fs::write(dir.join("types.jsonl"), types).unwrap();
+        fs::write(dir.join("groups.jsonl"), JSONL_GROUPS).unwrap();
+        fs::write(dir.join("blueprints.jsonl"), JSONL_BLUEPRINTS).unwrap();
+    }
+
+    #[test]
+    fn reads_jsonl()
+    {
+        let dir = temp_dir("sde_jsonl");
+        write_jsonl(&dir, JSONL_TYPES);
+        check_fixture(&open(SdeFormat::Jsonl, &dir).read().unwrap());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn malformed_jsonl_is_an_error()
+    {
+        let dir = temp_dir("sde_jsonl_malformed");
+        write_jsonl(&dir, &JSONL_TYPES.replace("\"_key\": 587", "\"_key\": \"587\""));
+        assert!(matches!(open(SdeFormat::Jsonl, &dir).read(), Err(Error::JsonLine(_, 2, _))));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    const SQLITE_SCHEMA: &str = "
+        CREATE TABLE invTypes (typeID INTEGER, groupID INTEGER, typeName TEXT, published INTEGER);
+        CREATE TABLE invGroups (groupID INTEGER, categoryID INTEGER, groupName TEXT);
+        CREATE TABLE industryBlueprints (typeID INTEGER, maxProductionLimit INTEGER);
+        CREATE TABLE industryActivity (typeID INTEGER, activityID INTEGER, time INTEGER);
+        CREATE TABLE industryActivityMaterials (typeID INTEGER, activityID INTEGER,
+            materialTypeID INTEGER, quantity INTEGER);
+        CREATE TABLE industryActivityProducts (typeID INTEGER, activityID INTEGER,
+            productTypeID INTEGER, quantity INTEGER);
+        CREATE TABLE industryActivityProbabilities (typeID INTEGER, activityID INTEGER,
+            productTypeID INTEGER, probability REAL);
+        CREATE TABLE industryActivitySkills (typeID INTEGER, activityID INTEGER, skillID INTEGER,
+            level INTEGER);
+        INSERT INTO invTypes VALUES (34, 18, 'Tritanium', 0), (587, 25, 'Rifter', 1),
+            (691, 105, 'Rifter Blueprint', 1), (999, 1, NULL, 0);
+        INSERT INTO invGroups VALUES (25, 6, 'Frigate');
+        INSERT INTO industryBlueprints VALUES (691, 300);
+        INSERT INTO industryActivity VALUES (691, 1, 6000), (691, 8, 63900), (691, 2, 1);
+        INSERT INTO industryActivityMaterials VALUES (691, 1, 34, 32000);
+        INSERT INTO industryActivityProducts VALUES (691, 1, 587, 1), (691, 8, 11379, 1);
+        INSERT INTO industryActivityProbabilities VALUES (691, 8, 11379, 0.3);
+        INSERT INTO industryActivitySkills VALUES (691, 1, 3380, 1);";
+
+    #[test]
+    fn reads_sqlite()
+    {
+        let dir = temp_dir("sde_sqlite");
+        rusqlite::Connection::open(dir.join("sqlite-latest.sqlite")).unwrap()
+            .execute_batch(SQLITE_SCHEMA).unwrap();
+        let sde = open(SdeFormat::Sqlite, &dir).read().unwrap();
+        check_fixture(&sde);
+        // The obsolete activity 2 is skipped
+        assert_eq!(sde.blueprints[&691].activities.len(), 2);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn malformed_sqlite_is_an_error()
+    {
+        let dir = temp_dir("sde_sqlite_malformed");
+        let file = dir.join("sqlite-latest.sqlite");
+        fs::write(&file, "not a database").unwrap();
+        assert!(matches!(open(SdeFormat::Sqlite, &file).read(), Err(Error::Sqlite(..))));
+        fs::remove_file(&file).unwrap();
+        rusqlite::Connection::open(&file).unwrap()
+            .execute_batch("CREATE TABLE invTypes (typeID INTEGER);").unwrap();
+        assert!(matches!(open(SdeFormat::Sqlite, &file).read(), Err(Error::Sqlite(..))));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
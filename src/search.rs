@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::sde::Sde;
+
+/// SDE categories that searches can be restricted to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Category
+{
+    Blueprint,
+    Ship,
+    Module,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchFilter
+{
+    pub category: Option<Category>,
+    pub group_id: Option<i64>,
+    /// Also return types that are not published, e.g. NPC ships and test items.
+    pub include_unpublished: bool,
+}
+
+#[derive(Serialize)]
+pub struct SearchHit<'a>
+{
+    pub id: i64,
+    pub name: &'a str,
+    pub group: Option<&'a str>,
+    /// Relevance, higher is better.
+    pub score: u32,
+}
+
+/// Lower cased names of all SDE types with a trigram index for finding candidates.
+pub struct SearchIndex
+{
+    entries: Vec<Entry>,
+    trigrams: HashMap<[char; 3], Vec<usize>>,
+}
+
+struct Entry
+{
+    id: i64,
+    name: String,
+    words: Vec<String>,
+}
+
+impl Category
+{
+    pub const ALL: [Category; 3] = [Category::Blueprint, Category::Ship, Category::Module];
+
+    fn id(self) -> i64
+    {
+        match self
+        {
+            Category::Blueprint => 9,
+            Category::Ship => 6,
+            Category::Module => 7,
+        }
+    }
+}
+
+impl SearchIndex
+{
+    pub fn new(sde: &Sde) -> SearchIndex
+    {
+        let mut ret = SearchIndex {entries: Vec::new(), trigrams: HashMap::new()};
+        for (id, sde_type) in sde.types.iter()
+        {
+            let name = sde_type.name.to_lowercase();
+            let idx = ret.entries.len();
+            let mut grams = trigrams(&name);
+            grams.sort_unstable();
+            grams.dedup();
+            for gram in grams
+            {
+                ret.trigrams.entry(gram).or_default().push(idx);
+            }
+            let words = name.split_whitespace().map(str::to_string).collect();
+            ret.entries.push(Entry {id: *id, name, words});
+        }
+        ret
+    }
+
+    /// Returns up to `limit` types matching `query` sorted by relevance. The query is
+    /// matched against names with tolerance for typos; a numeric query also matches the
+    /// type ID.
+    pub fn search<'a>(&self, sde: &'a Sde, query: &str, filter: &SearchFilter, limit: usize)
+        -> Vec<SearchHit<'a>>
+    {
+        let query = query.trim().to_lowercase();
+        if query.is_empty()
+        {
+            return Vec::new();
+        }
+        let tokens: Vec<&str> = query.split_whitespace().collect();
+
+        let mut scored = Vec::<(u32, usize)>::new();
+        for idx in self.candidates(&query)
+        {
+            let entry = &self.entries[idx];
+            if !matches_filter(sde, entry.id, filter)
+            {
+                continue;
+            }
+            let mut score = score(entry, &query, &tokens);
+            if query.parse::<i64>() == Ok(entry.id)
+            {
+                score = Some(2000);
+            }
+            if let Some(score) = score
+            {
+                scored.push((score, idx));
+            }
+        }
+
+        scored.sort_by(|a, b| b.0.cmp(&a.0)
+            .then_with(|| self.entries[a.1].name.len().cmp(&self.entries[b.1].name.len()))
+            .then_with(|| self.entries[a.1].name.cmp(&self.entries[b.1].name)));
+        scored.into_iter().take(limit)
+            .filter_map(|(score, idx)|
+            {
+                let id = self.entries[idx].id;
+                let sde_type = sde.types.get(&id)?;
+                Some(SearchHit {id, name: &sde_type.name, score,
+                    group: sde.groups.get(&sde_type.group_id).map(|g| g.name.as_str())})
+            })
+            .collect()
+    }
+
+    /// Entries sharing at least one trigram with the query. Short and numeric queries
+    /// have too few trigrams to be selective, so they check every entry.
+    fn candidates(&self, query: &str) -> Vec<usize>
+    {
+        if query.chars().count() < 3 || query.parse::<i64>().is_ok()
+        {
+            return (0..self.entries.len()).collect();
+        }
+        let mut ret: Vec<usize> = trigrams(query).iter()
+            .filter_map(|gram| self.trigrams.get(gram))
+            .flatten()
+            .copied()
+            .collect();
+        ret.sort_unstable();
+        ret.dedup();
+        ret
+    }
+}
+
+fn matches_filter(sde: &Sde, id: i64, filter: &SearchFilter) -> bool
+{
+    let sde_type = match sde.types.get(&id)
+    {
+        Some(t) => t,
+        None => return false,
+    };
+    if !filter.include_unpublished && !sde_type.published
+    {
+        return false;
+    }
+    if filter.group_id.is_some_and(|group| group != sde_type.group_id)
+    {
+        return false;
+    }
+    match filter.category
+    {
+        // Blueprints are also recognized without group data
+        Some(Category::Blueprint) if sde.blueprints.contains_key(&id) => true,
+        Some(category) => sde.groups.get(&sde_type.group_id)
+            .is_some_and(|g| g.category_id == category.id()),
+        None => true,
+    }
+}
+
+/// Ranks whole-name and substring matches above matches of the individual query words,
+/// which may be word prefixes or contain typos.
+fn score(entry: &Entry, query: &str, tokens: &[&str]) -> Option<u32>
+{
+    if entry.name == query
+    {
+        return Some(1000);
+    }
+    if entry.name.starts_with(query)
+    {
+        return Some(900);
+    }
+    if let Some(pos) = entry.name.find(query)
+    {
+        let at_word_start = entry.name[..pos].ends_with(' ');
+        return Some(if at_word_start { 800 } else { 700 });
+    }
+
+    let mut penalty = 0;
+    for token in tokens.iter()
+    {
+        penalty += entry.words.iter().filter_map(|word| word_penalty(word, token)).min()?;
+    }
+    Some(600u32.saturating_sub(50 * penalty).max(1))
+}
+
+/// How well a query word matches a name word, or `None` if it does not match at all.
+fn word_penalty(word: &str, token: &str) -> Option<u32>
+{
+    if word == token
+    {
+        return Some(0);
+    }
+    if word.starts_with(token)
+    {
+        return Some(1);
+    }
+    let allowed = match token.chars().count()
+    {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    };
+    // Compare against the whole word and against a prefix of the same length, so a
+    // misspelled beginning of a word still matches
+    let prefix: String = word.chars().take(token.chars().count()).collect();
+    let distance = edit_distance(word, token).min(edit_distance(&prefix, token) + 1);
+    if distance <= allowed { Some(1 + 2 * distance as u32) } else { None }
+}
+
+/// Optimal string alignment distance: insertions, deletions, substitutions and
+/// transpositions of adjacent characters each count as one edit.
+fn edit_distance(a: &str, b: &str) -> usize
+{
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate()
+    {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate()
+    {
+        *cell = j;
+    }
+    for i in 1..=a.len()
+    {
+        for j in 1..=b.len()
+        {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut best = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1]
+            {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// Trigrams of every word, padded with spaces so word starts and ends count.
+fn trigrams(text: &str) -> Vec<[char; 3]>
+{
+    let mut ret = Vec::new();
+    for word in text.split_whitespace()
+    {
+        let chars: Vec<char> = std::iter::once(' ').chain(word.chars()).chain(std::iter::once(' '))
+            .collect();
+        for w in chars.windows(3)
+        {
+            ret.push([w[0], w[1], w[2]]);
+        }
+    }
+    ret
+}
+
+impl fmt::Display for Category
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            Category::Blueprint => write!(f, "blueprint"),
+            Category::Ship => write!(f, "ship"),
+            Category::Module => write!(f, "module"),
+        }
+    }
+}
+
+impl FromStr for Category
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Category, String>
+    {
+        Category::ALL.iter().copied().find(|x| x.to_string() == s)
+            .ok_or_else(|| format!("unknown category '{}'", s))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn edit_distance_counts_single_edits()
+    {
+        assert_eq!(edit_distance("tritanium", "tritanium"), 0);
+        assert_eq!(edit_distance("", "rifter"), 6);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        // A swap of adjacent letters is one edit
+        assert_eq!(edit_distance("tirtanium", "tritanium"), 1);
+        assert_eq!(edit_distance("ca", "abc"), 3);
+    }
+
+    #[test]
+    fn word_penalty_allows_typos_in_longer_words()
+    {
+        assert_eq!(word_penalty("tritanium", "tritanium"), Some(0));
+        assert_eq!(word_penalty("tritanium", "trit"), Some(1));
+        assert_eq!(word_penalty("tritanium", "tirtanium"), Some(3));
+        // Words of up to three letters have to match exactly
+        assert_eq!(word_penalty("ore", "orb"), None);
+    }
+}
@@ -0,0 +1,412 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+use num_format::Locale;
+
+use crate::cli;
+use crate::esi;
+use crate::fees::{BuyMode, FeeModel, SellMode};
+use crate::industry::Skills;
+use crate::marketlogs::{self, PricePoint};
+use crate::sde_source::SdeFormat;
+
+const APP_DIR: &str = "eve_industry_terminal";
+const DATA_DIR_ENV: &str = "EVE_INDUSTRY_DATA_DIR";
+const CONFIG_ENV: &str = "EVE_INDUSTRY_CONFIG";
+
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+/// Locale used for all number formatting, set once from the loaded settings.
+pub fn locale() -> &'static Locale
+{
+    LOCALE.get().unwrap_or(&Locale::en)
+}
+
+pub struct Settings
+{
+    pub data_dir: PathBuf,
+    pub sde_dir: PathBuf,
+    pub sde_format: SdeFormat,
+    pub fees: FeeModel,
+    pub locale: Locale,
+    pub skills: Skills,
+    /// Directory with the EVE client's market exports.
+    pub marketlogs_dir: PathBuf,
+    pub price_point: PricePoint,
+    /// ESI and EVE SSO base URLs, replaceable by a local mock.
+    pub esi_base_url: String,
+    pub sso_base_url: String,
+    /// Client ID and callback URL of the application registered for SSO logins.
+    pub esi_client_id: Option<String>,
+    pub esi_callback_url: String,
+    /// Key that encrypts the SSO token. It is kept out of the data directory so that a copy
+    /// of the data directory alone cannot decrypt the token.
+    pub esi_key_path: PathBuf,
+    /// Character whose assets and blueprints are fetched from ESI.
+    pub character_id: Option<i64>,
+}
+
+/// Contents of the TOML config file. Every key is optional.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile
+{
+    data_dir: Option<PathBuf>,
+    sde_dir: Option<PathBuf>,
+    sde_format: Option<String>,
+    sales_tax: Option<f64>,
+    broker_fee: Option<f64>,
+    locale: Option<String>,
+    industry: Option<u8>,
+    advanced_industry: Option<u8>,
+    implant_time_bonus: Option<f64>,
+    encryption_skill: Option<u8>,
+    science_skills: Option<u8>,
+    reactions: Option<u8>,
+    metallurgy: Option<u8>,
+    research: Option<u8>,
+    science: Option<u8>,
+    accounting: Option<u8>,
+    broker_relations: Option<u8>,
+    faction_standing: Option<f64>,
+    corp_standing: Option<f64>,
+    sell_mode: Option<String>,
+    buy_mode: Option<String>,
+    marketlogs_dir: Option<PathBuf>,
+    price_point: Option<String>,
+    esi_base_url: Option<String>,
+    sso_base_url: Option<String>,
+    esi_client_id: Option<String>,
+    esi_callback_url: Option<String>,
+    esi_key_file: Option<PathBuf>,
+    character_id: Option<i64>,
+}
+
+impl Settings
+{
+    /// Builds the settings from (lowest to highest precedence) built-in defaults,
+    /// the config file, the environment and the given command line arguments.
+    /// Parsing stops at the first argument that is not an option; that argument and
+    /// everything after it is returned as the subcommand.
+    pub fn load(args: &[String]) -> (Settings, &[String])
+    {
+        Settings::load_with_env(args, &|var| env::var_os(var))
+    }
+
+    /// `load` with the environment variables looked up through `env`.
+    fn load_with_env<'a>(args: &'a [String], env: &dyn Fn(&str) -> Option<OsString>)
+        -> (Settings, &'a [String])
+    {
+        // A config file named by the flag or the environment has to exist
+        let mut config_path = env(CONFIG_ENV).map(|path| (PathBuf::from(path), true))
+            .or_else(|| xdg_dir(env, "XDG_CONFIG_HOME", ".config")
+                .map(|p| (p.join(APP_DIR).join("config.toml"), false)));
+        let mut data_dir = None;
+        let mut sde_dir = None;
+        let mut sde_format = None;
+        let mut sales_tax = None;
+        let mut broker_fee = None;
+        let mut sell_mode = None;
+        let mut buy_mode = None;
+        let mut locale = None;
+
+        let mut command: &[String] = &[];
+        let mut iter = args.iter().enumerate();
+        while let Some((idx, arg)) = iter.next()
+        {
+            if !arg.starts_with('-')
+            {
+                command = &args[idx..];
+                break;
+            }
+            let mut value = |flag: &str| match iter.next()
+            {
+                Some((_, v)) => v.clone(),
+                None => usage_error(&format!("{} requires a value", flag)),
+            };
+            match arg.as_str()
+            {
+                "--config" => config_path = Some((PathBuf::from(value(arg)), true)),
+                "--data-dir" => data_dir = Some(PathBuf::from(value(arg))),
+                "--sde-dir" => sde_dir = Some(PathBuf::from(value(arg))),
+                "--sde-format" => sde_format = Some(value(arg)),
+                "--sales-tax" => sales_tax = Some(parse_percent(arg, &value(arg))),
+                "--broker-fee" => broker_fee = Some(parse_percent(arg, &value(arg))),
+                "--sell-mode" => sell_mode = Some(value(arg)),
+                "--buy-mode" => buy_mode = Some(value(arg)),
+                "--locale" => locale = Some(value(arg)),
+                "-h" | "--help" =>
+                {
+                    print_usage();
+                    process::exit(0);
+                }
+                _ => usage_error(&format!("Unknown argument: {}", arg)),
+            }
+        }
+
+        let config = match config_path
+        {
+            Some((path, explicit)) => read_config(&path, explicit)
+                .unwrap_or_else(|e| usage_error(&e)),
+            None => ConfigFile::default(),
+        };
+
+        let data_dir = data_dir
+            .or_else(|| env(DATA_DIR_ENV).map(PathBuf::from))
+            .or(config.data_dir)
+            .or_else(|| xdg_dir(env, "XDG_DATA_HOME", ".local/share").map(|p| p.join(APP_DIR)))
+            .unwrap_or_else(|| PathBuf::from("data"));
+        let sde_dir = sde_dir
+            .or(config.sde_dir)
+            .unwrap_or_else(|| data_dir.join("sde"));
+        let sde_format = sde_format.or(config.sde_format)
+            .map(|f| f.parse::<SdeFormat>().unwrap_or_else(|e| usage_error(&e)))
+            .unwrap_or(SdeFormat::Yaml);
+        let locale_name = locale.or(config.locale).unwrap_or_else(|| "en".to_string());
+        let locale = match Locale::from_name(&locale_name)
+        {
+            Ok(l) => l,
+            Err(_) => usage_error(&format!("Unknown locale: {}", locale_name)),
+        };
+
+        let skills = Skills {industry: config.industry.unwrap_or(0).min(5),
+            advanced_industry: config.advanced_industry.unwrap_or(0).min(5),
+            implant_time_bonus: config.implant_time_bonus.unwrap_or(0.0),
+            encryption: config.encryption_skill.unwrap_or(0).min(5),
+            science: config.science_skills.unwrap_or(0).min(5),
+            reactions: config.reactions.unwrap_or(0).min(5),
+            metallurgy: config.metallurgy.unwrap_or(0).min(5),
+            research: config.research.unwrap_or(0).min(5),
+            copying: config.science.unwrap_or(0).min(5)};
+
+        let sell_mode = sell_mode.or(config.sell_mode)
+            .map(|m| m.parse::<SellMode>().unwrap_or_else(|e| usage_error(&e)))
+            .unwrap_or(SellMode::SellOrder);
+        let buy_mode = buy_mode.or(config.buy_mode)
+            .map(|m| m.parse::<BuyMode>().unwrap_or_else(|e| usage_error(&e)))
+            .unwrap_or(BuyMode::BuyOrder);
+        let fees = FeeModel {
+            base_sales_tax: sales_tax.or(config.sales_tax).unwrap_or(7.5),
+            accounting: config.accounting.unwrap_or(0).min(5),
+            broker_relations: config.broker_relations.unwrap_or(0).min(5),
+            faction_standing: config.faction_standing.unwrap_or(0.0),
+            corp_standing: config.corp_standing.unwrap_or(0.0),
+            structure_broker_fee: broker_fee.or(config.broker_fee),
+            sell_mode, buy_mode};
+
+        let marketlogs_dir = config.marketlogs_dir.unwrap_or_else(marketlogs::default_dir);
+        let price_point = config.price_point
+            .map(|p| p.parse::<PricePoint>().unwrap_or_else(|e| usage_error(&e)))
+            .unwrap_or(PricePoint::Best);
+
+        let esi_base_url = config.esi_base_url.unwrap_or_else(|| esi::DEFAULT_BASE_URL.to_string());
+        let sso_base_url = config.sso_base_url.unwrap_or_else(|| esi::DEFAULT_SSO_URL.to_string());
+        let esi_callback_url = config.esi_callback_url
+            .unwrap_or_else(|| esi::DEFAULT_CALLBACK_URL.to_string());
+        let esi_key_path = config.esi_key_file
+            .or_else(|| xdg_dir(env, "XDG_CONFIG_HOME", ".config")
+                .map(|p| p.join(APP_DIR).join(esi::KEY_FILE)))
+            .unwrap_or_else(|| PathBuf::from(esi::KEY_FILE));
+
+        (Settings {data_dir, sde_dir, sde_format, fees, locale, skills, marketlogs_dir,
+            price_point, esi_base_url, sso_base_url, esi_client_id: config.esi_client_id,
+            esi_callback_url, esi_key_path, character_id: config.character_id}, command)
+    }
+
+    /// Built-in defaults with the given skills, independent of the environment.
+    #[cfg(test)]
+    pub fn with_skills(skills: Skills) -> Settings
+    {
+        let fees = FeeModel {base_sales_tax: 7.5, accounting: 0, broker_relations: 0,
+            faction_standing: 0.0, corp_standing: 0.0, structure_broker_fee: None,
+            sell_mode: SellMode::SellOrder, buy_mode: BuyMode::BuyOrder};
+        Settings {data_dir: PathBuf::from("data"), sde_dir: PathBuf::from("data/sde"),
+            sde_format: SdeFormat::Yaml, fees, locale: Locale::en, skills,
+            marketlogs_dir: PathBuf::new(), price_point: PricePoint::Best,
+            esi_base_url: esi::DEFAULT_BASE_URL.to_string(),
+            sso_base_url: esi::DEFAULT_SSO_URL.to_string(), esi_client_id: None,
+            esi_callback_url: esi::DEFAULT_CALLBACK_URL.to_string(),
+            esi_key_path: PathBuf::from(esi::KEY_FILE), character_id: None}
+    }
+
+    /// Makes the configured locale the one returned by `settings::locale()`.
+    pub fn apply_locale(&self)
+    {
+        let _ = LOCALE.set(self.locale);
+    }
+}
+
+fn xdg_dir(env: &dyn Fn(&str) -> Option<OsString>, var: &str, home_fallback: &str)
+    -> Option<PathBuf>
+{
+    match env(var)
+    {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => env("HOME").map(|home| Path::new(&home).join(home_fallback)),
+    }
+}
+
+/// Reads the config file. Only a file that was not named explicitly may be missing.
+fn read_config(path: &Path, explicit: bool) -> std::result::Result<ConfigFile, String>
+{
+    if !explicit && !path.exists()
+    {
+        return Ok(ConfigFile::default());
+    }
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read config file {}: {}", path.display(), e))?;
+    toml::from_str(&content).map_err(|e| format!("Invalid config file {}: {}", path.display(), e))
+}
+
+fn parse_percent(flag: &str, value: &str) -> f64
+{
+    match value.parse::<f64>()
+    {
+        Ok(v) if (0.0..=100.0).contains(&v) => v,
+        _ => usage_error(&format!("{} expects a percentage between 0 and 100", flag)),
+    }
+}
+
+fn print_usage()
+{
+    println!("Usage: eve_industry_terminal [OPTIONS] [COMMAND]\n\n\
+        Without a command the interactive menu is started.\n\n\
+        Options:\n  \
+        --config <FILE>       Config file (default: $XDG_CONFIG_HOME/{app}/config.toml)\n  \
+        --data-dir <DIR>      Data directory (env: {env}, default: $XDG_DATA_HOME/{app})\n  \
+        --sde-dir <DIR>       SDE directory (default: <data-dir>/sde)\n  \
+        --sde-format <FMT>    yaml (fsd/*.yaml), jsonl (*.jsonl) or sqlite\n                        \
+        (sqlite-latest.sqlite or the --sde-dir file) (default: yaml)\n  \
+        --sales-tax <PCT>     Base sales tax in percent before Accounting (default: 7.5)\n  \
+        --broker-fee <PCT>    Fixed broker fee of a player structure in percent\n                        \
+        (default: NPC station fee from skills and standings)\n  \
+        --sell-mode <MODE>    sell-order or instant-sell (default: sell-order)\n  \
+        --buy-mode <MODE>     buy-order or instant-buy (default: buy-order)\n  \
+        --locale <NAME>       Number formatting locale (default: en)\n  \
+        -h, --help            Print this help\n\n\
+        Character skills are read from the config file keys industry, advanced_industry,\n\
+        accounting, broker_relations, encryption_skill, science_skills (levels 0-5; these\n\
+        two are used for invention), reactions, metallurgy, research, science (research and\n\
+        copying), implant_time_bonus (percent) and\n\
+        faction_standing, corp_standing (standings towards the market station owner).\n\
+        Market exports are read from marketlogs_dir (default: ~/Documents/EVE/logs/Marketlogs)\n\
+        using price_point best or p<PERCENT> of the order volume, e.g. p5 (default: best).\n\
+        Assets and blueprints of character_id are fetched from esi_base_url\n\
+        (default: https://esi.evetech.net/latest). Logins use the SSO at sso_base_url\n\
+        (default: https://login.eveonline.com) with the esi_client_id and esi_callback_url\n\
+        (default: http://localhost/callback) of an application registered at\n\
+        https://developers.eveonline.com with the scopes\n\
+        esi-characters.read_blueprints.v1 and esi-assets.read_assets.v1.\n\
+        The SSO token in the data directory is encrypted with the key in esi_key_file\n\
+        (default: $XDG_CONFIG_HOME/{app}/esi.key). This protects copies and backups of the\n\
+        data directory; anyone who can read both files can decrypt the token.\n\n\
+        {commands}",
+        app = APP_DIR, env = DATA_DIR_ENV, commands = cli::COMMANDS_HELP);
+}
+
+pub fn usage_error(message: &str) -> !
+{
+    eprintln!("{}", message);
+    eprintln!("Try --help for more information.");
+    process::exit(2);
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// An empty directory of its own under the system temp directory.
+    fn temp_dir(name: &str) -> PathBuf
+    {
+        let dir = env::temp_dir()
+            .join(format!("eve_industry_terminal_{}_{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn args(args: &[&str]) -> Vec<String>
+    {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    /// Loads the settings with only the given environment variables set.
+    fn load(args: &[String], vars: &[(&str, &Path)]) -> Settings
+    {
+        let vars: HashMap<String, OsString> = vars.iter()
+            .map(|(var, value)| (var.to_string(), value.as_os_str().to_os_string()))
+            .collect();
+        Settings::load_with_env(args, &|var| vars.get(var).cloned()).0
+    }
+
+    #[test]
+    fn flag_beats_environment_beats_file_beats_default()
+    {
+        let dir = temp_dir("settings_precedence");
+        let config = dir.join("config.toml");
+        fs::write(&config, "data_dir = \"/from/file\"\nsales_tax = 5.0\n").unwrap();
+        let env_dir = Path::new("/from/env");
+
+        let flag = load(&args(&["--data-dir", "/from/flag", "--sales-tax", "3"]),
+            &[(CONFIG_ENV, &config), (DATA_DIR_ENV, env_dir)]);
+        assert_eq!((flag.data_dir.as_path(), flag.fees.base_sales_tax),
+            (Path::new("/from/flag"), 3.0));
+        let env = load(&[], &[(CONFIG_ENV, &config), (DATA_DIR_ENV, env_dir)]);
+        assert_eq!(env.data_dir, env_dir);
+        let file = load(&[], &[(CONFIG_ENV, &config)]);
+        assert_eq!((file.data_dir.as_path(), file.fees.base_sales_tax),
+            (Path::new("/from/file"), 5.0));
+        assert_eq!(file.sde_dir, Path::new("/from/file/sde"));
+
+        // Without a config file the XDG data directory is the default
+        let xdg = load(&[], &[("XDG_DATA_HOME", Path::new("/xdg")), ("HOME", &dir)]);
+        assert_eq!(xdg.data_dir, Path::new("/xdg").join(APP_DIR));
+        assert_eq!(xdg.fees.base_sales_tax, 7.5);
+        let home = load(&[], &[("HOME", &dir)]);
+        assert_eq!(home.data_dir, dir.join(".local/share").join(APP_DIR));
+        assert_eq!(load(&[], &[]).data_dir, Path::new("data"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn config_flag_beats_config_environment()
+    {
+        let dir = temp_dir("settings_config_flag");
+        let (env_config, flag_config) = (dir.join("env.toml"), dir.join("flag.toml"));
+        fs::write(&env_config, "sales_tax = 5.0\n").unwrap();
+        fs::write(&flag_config, "sales_tax = 6.0\nindustry = 4\n").unwrap();
+        let command = args(&["--config", flag_config.to_str().unwrap(), "list-items", "--json"]);
+
+        let (settings, rest) = Settings::load_with_env(&command,
+            &|var| (var == CONFIG_ENV).then(|| env_config.clone().into_os_string()));
+        assert_eq!(settings.fees.base_sales_tax, 6.0);
+        assert_eq!(settings.skills.industry, 4);
+        assert_eq!(rest, &command[2..]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn only_the_default_config_file_may_be_missing()
+    {
+        let dir = temp_dir("settings_missing_config");
+        let missing = dir.join("missing.toml");
+        assert!(matches!(read_config(&missing, true),
+            Err(e) if e.starts_with("Cannot read config file")));
+        assert!(read_config(&missing, false).is_ok());
+        // The default config file under an empty home directory does not exist
+        assert_eq!(load(&[], &[("HOME", &dir)]).fees.base_sales_tax, 7.5);
+
+        fs::write(&missing, "sales_tax = \"high\"\n").unwrap();
+        assert!(matches!(read_config(&missing, false),
+            Err(e) if e.starts_with("Invalid config file")));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,13 +1,45 @@
 use yaml_rust::{YamlLoader, Yaml, YamlEmitter};
-use std::io::{self,Write};
-use std::fs;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::io::{self,Write,BufReader};
+use std::fs::{self, File};
+use std::path::Path;
 
-pub fn load_yaml(filepath: &str) -> Vec<Yaml>
+use crate::error::{Error, Result};
+
+/// Loads the first document of a yaml file.
+pub fn load_yaml(filepath: &Path) -> Result<Yaml>
 {
     let yaml_file = fs::read_to_string(filepath)
-        .expect("Cannot read yaml file");
+        .map_err(|e| Error::Io(filepath.to_path_buf(), e))?;
     YamlLoader::load_from_str(&yaml_file)
-        .expect("Cannot deserialize yaml file")
+        .map_err(|e| Error::Yaml(filepath.to_path_buf(), e))?
+        .into_iter().next()
+        .ok_or_else(|| Error::EmptyYaml(filepath.to_path_buf()))
+}
+
+pub fn read_json<T: DeserializeOwned>(filepath: &Path) -> Result<T>
+{
+    let file = File::open(filepath).map_err(|e| Error::Io(filepath.to_path_buf(), e))?;
+    serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| Error::Json(filepath.to_path_buf(), e))
+}
+
+/// Like `read_json`, but a missing file is not an error.
+pub fn read_optional_json<T: DeserializeOwned>(filepath: &Path) -> Result<Option<T>>
+{
+    if !filepath.exists()
+    {
+        return Ok(None);
+    }
+    read_json(filepath).map(Some)
+}
+
+pub fn write_json<T: Serialize>(filepath: &Path, value: &T) -> Result<()>
+{
+    let serialized = serde_json::to_string(value)
+        .map_err(|e| Error::Json(filepath.to_path_buf(), e))?;
+    fs::write(filepath, serialized).map_err(|e| Error::Io(filepath.to_path_buf(), e))
 }
 
 pub fn _dump_yaml(yaml_obj: &Yaml)
@@ -18,6 +50,44 @@ pub fn _dump_yaml(yaml_obj: &Yaml)
     println!("{}", out);
 }
 
+/// Formats a number of seconds like `2d 3h 4m 5s`, leaving out leading zero units.
+pub fn format_duration(seconds: u64) -> String
+{
+    let units = [(seconds / 86400, "d"), (seconds / 3600 % 24, "h"), (seconds / 60 % 60, "m"),
+        (seconds % 60, "s")];
+    let parts: Vec<String> = units.iter()
+        .skip_while(|u| u.0 == 0 && u.1 != "s")
+        .map(|u| format!("{}{}", u.0, u.1))
+        .collect();
+    parts.join(" ")
+}
+
+/// Checks that a date is a valid calendar day written as YYYY-MM-DD.
+pub fn is_valid_date(date: &str) -> bool
+{
+    let parts: Vec<&str> = date.split('-').collect();
+    let (year, month, day) = match parts.as_slice()
+    {
+        [y, m, d] if y.len() == 4 && m.len() == 2 && d.len() == 2 =>
+            match (y.parse::<u32>(), m.parse::<u32>(), d.parse::<u32>())
+            {
+                (Ok(y), Ok(m), Ok(d)) => (y, m, d),
+                _ => return false,
+            },
+        _ => return false,
+    };
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month
+    {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+    (1..=days).contains(&day)
+}
+
 pub fn read_input(prompt: &str) -> String
 {
     print!("{}",prompt);